@@ -0,0 +1,90 @@
+//! Shows how to feed the map with positions coming from a GPS receiver, or any other source
+//! which updates them once in a while.
+
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+use egui::{Context, Window};
+use walkers::{providers::openstreetmap, Map, MapMemory, Position, PositionExt, Tiles};
+
+fn main() -> Result<(), eframe::Error> {
+    env_logger::init();
+    eframe::run_native(
+        "Locate me",
+        Default::default(),
+        Box::new(|cc| Box::new(LocateMe::new(cc.egui_ctx.clone()))),
+    )
+}
+
+struct LocateMe {
+    tiles: Tiles,
+    map_memory: MapMemory,
+
+    /// Positions reported by the (fake) GPS receiver.
+    positions: Receiver<Position>,
+
+    /// Most recent position. Map is drawn with it until a new one comes.
+    my_position: Position,
+}
+
+impl LocateMe {
+    fn new(egui_ctx: Context) -> Self {
+        Self {
+            tiles: Tiles::new(openstreetmap, egui_ctx.clone(), |_| {
+                Err("no hard cache".to_owned())
+            }),
+            map_memory: MapMemory::default(),
+            positions: fake_gps(egui_ctx),
+            my_position: Position::from_lat_lon(51.09916, 17.03664),
+        }
+    }
+}
+
+/// Pretends to be a GPS receiver, which reports a position once a second. A real one would be
+/// read from a serial port, gpsd, the browser's geolocation API, etc.
+fn fake_gps(egui_ctx: Context) -> Receiver<Position> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for step in 0.. {
+            let position = Position::from_lat_lon(51.09916, 17.03664 + step as f64 * 0.0002);
+            if tx.send(position).is_err() {
+                break;
+            }
+
+            // Make sure that the new position gets drawn, even if nothing else happens.
+            egui_ctx.request_repaint();
+            std::thread::sleep(Duration::from_secs(1));
+        }
+    });
+    rx
+}
+
+impl eframe::App for LocateMe {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Take whatever came since the last frame, keeping the most recent one.
+        if let Some(position) = self.positions.try_iter().last() {
+            self.my_position = position;
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            // Map glides to the new position, instead of jumping.
+            ui.add(Map::new(
+                Some(&mut self.tiles),
+                &mut self.map_memory,
+                self.my_position,
+            ));
+
+            Window::new("Position")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label(format!(
+                        "{:.05} {:.05}",
+                        self.my_position.latitude(),
+                        self.my_position.longitude()
+                    ));
+                    ui.add(&walkers::providers::openstreetmap_attribution());
+                });
+        });
+    }
+}
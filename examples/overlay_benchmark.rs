@@ -0,0 +1,68 @@
+//! Compares how long it takes to draw and tessellate many polylines, drawn one by one and as
+//! a single batch, while the map is being panned. Run with:
+//!
+//! ```sh
+//! cargo run --release --example overlay_benchmark --features testing
+//! ```
+
+use std::time::{Duration, Instant};
+
+use egui::{Color32, Stroke};
+use walkers::{
+    overlays::{Batch, Polyline},
+    testing::MapHarness,
+    Position,
+};
+
+const FRAMES: u32 = 50;
+
+fn polylines(count: usize) -> Vec<Polyline> {
+    (0..count)
+        .map(|n| {
+            let angle = n as f64 / count as f64 * std::f64::consts::TAU;
+            let center = Position::new(17.03664, 51.09916);
+            Polyline::new(
+                (0..10)
+                    .map(|step| {
+                        let distance = step as f64 * 0.002;
+                        Position::new(
+                            center.x() + angle.cos() * distance,
+                            center.y() + angle.sin() * distance,
+                        )
+                    })
+                    .collect(),
+                Stroke::new(2., Color32::RED),
+            )
+        })
+        .collect()
+}
+
+/// Average time of drawing a frame and tessellating its shapes.
+fn measure(mut frame: impl FnMut(&mut MapHarness) -> egui::FullOutput) -> Duration {
+    let mut harness = MapHarness::new(Position::new(17.03664, 51.09916));
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        let output = frame(&mut harness);
+        let _ = harness.ctx.tessellate(output.shapes);
+    }
+    start.elapsed() / FRAMES
+}
+
+fn main() {
+    for count in [100, 1_000, 10_000] {
+        let lines = polylines(count);
+
+        let separate = measure(|harness| {
+            harness.memory.pan_by(egui::vec2(1., 0.));
+            harness.run_with(Vec::new(), |map| map.with_plugin(lines.clone()))
+        });
+        let mut batch = Batch::new(lines.clone());
+        let batched = measure(|harness| {
+            // Keep the map moving, so the batch is not just drawn at the same place.
+            harness.memory.pan_by(egui::vec2(1., 0.));
+            harness.run_with(Vec::new(), |map| map.with_plugin(&mut batch))
+        });
+
+        println!("{count:>6} polylines: {separate:>10.2?} separately, {batched:>10.2?} batched");
+    }
+}
@@ -0,0 +1,159 @@
+use egui::{ComboBox, Id, Response, Ui, Widget};
+
+use crate::{providers::Attribution, Tiles};
+
+/// One of the maps user can choose from in the [`BasemapPicker`].
+pub struct Basemap {
+    /// Shown in the picker.
+    pub name: String,
+    pub tiles: Tiles,
+
+    /// Credits to be shown while this basemap is active.
+    pub attribution: Option<Attribution>,
+}
+
+impl Basemap {
+    pub fn new(name: impl Into<String>, tiles: Tiles) -> Self {
+        Self {
+            name: name.into(),
+            tiles,
+            attribution: None,
+        }
+    }
+
+    pub fn with_attribution(self, attribution: Attribution) -> Self {
+        Self {
+            attribution: Some(attribution),
+            ..self
+        }
+    }
+}
+
+/// Lets the user switch between several basemaps, e.g. a street map and an aerial imagery.
+/// It must persist between frames. Add it to the [`Ui`] to show a drop-down list of the
+/// basemaps, and pass [`BasemapPicker::tiles`] to the [`crate::Map`].
+pub struct BasemapPicker {
+    basemaps: Vec<Basemap>,
+    active: usize,
+    id_source: Id,
+}
+
+impl BasemapPicker {
+    /// Picker with the first of the `basemaps` active.
+    pub fn new(basemaps: Vec<Basemap>) -> Self {
+        Self {
+            basemaps,
+            active: 0,
+            id_source: Id::new("walkers_basemap_picker"),
+        }
+    }
+
+    /// Needed if there is more than one picker in the same [`Ui`], so that they don't share
+    /// the drop-down list.
+    pub fn with_id_source(self, id_source: impl std::hash::Hash) -> Self {
+        Self {
+            id_source: Id::new(id_source),
+            ..self
+        }
+    }
+
+    /// Index of the active basemap.
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Make basemap at given `index` the active one. Out of range indices are ignored.
+    pub fn set_active(&mut self, index: usize) {
+        if index < self.basemaps.len() {
+            self.active = index;
+        }
+    }
+
+    /// Tiles of the active basemap, `None` if there are no basemaps at all.
+    pub fn tiles(&mut self) -> Option<&mut Tiles> {
+        self.basemaps
+            .get_mut(self.active)
+            .map(|basemap| &mut basemap.tiles)
+    }
+
+    /// Attribution of the active basemap.
+    pub fn attribution(&self) -> Option<&Attribution> {
+        self.basemaps.get(self.active)?.attribution.as_ref()
+    }
+}
+
+impl Widget for &mut BasemapPicker {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let selected = self
+            .basemaps
+            .get(self.active)
+            .map(|basemap| basemap.name.as_str())
+            .unwrap_or_default();
+
+        let mut active = self.active;
+        let response = ComboBox::from_id_source(self.id_source)
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for (index, basemap) in self.basemaps.iter().enumerate() {
+                    ui.selectable_value(&mut active, index, &basemap.name);
+                }
+            })
+            .response;
+
+        self.set_active(active);
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::solid_color_tiles;
+    use egui::{Color32, Context};
+
+    #[test]
+    fn switching_basemaps() {
+        let ctx = Context::default();
+        let mut picker = BasemapPicker::new(vec![
+            Basemap::new("Streets", solid_color_tiles(Color32::GRAY, ctx.clone())),
+            Basemap::new("Aerial", solid_color_tiles(Color32::GREEN, ctx))
+                .with_attribution(Attribution::new("Aerial imagery")),
+        ]);
+
+        assert_eq!(0, picker.active());
+        assert!(picker.attribution().is_none());
+
+        picker.set_active(1);
+        assert_eq!(1, picker.active());
+        assert_eq!("Aerial imagery", picker.attribution().unwrap().text);
+
+        // There is no such basemap.
+        picker.set_active(2);
+        assert_eq!(1, picker.active());
+        assert!(picker.tiles().is_some());
+    }
+
+    #[test]
+    fn pickers_in_the_same_ui_have_their_own_ids() {
+        let ctx = Context::default();
+        let mut first = BasemapPicker::new(vec![Basemap::new(
+            "Streets",
+            solid_color_tiles(Color32::GRAY, ctx.clone()),
+        )]);
+        let mut second = BasemapPicker::new(vec![Basemap::new(
+            "Aerial",
+            solid_color_tiles(Color32::GREEN, ctx.clone()),
+        )])
+        .with_id_source("second");
+
+        let mut ids = Vec::new();
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ids.push(ui.add(&mut first).id);
+                ids.push(ui.add(&mut second).id);
+            });
+        });
+
+        assert_ne!(ids[0], ids[1]);
+    }
+}
@@ -0,0 +1,42 @@
+//! Ready-made widgets controlling the map.
+
+use egui::{Response, RichText, Ui, Widget};
+
+use crate::{Easing, MapMemory};
+
+/// Button which brings the map back to its home view, see [`MapMemory::go_home`].
+pub struct HomeButton<'a> {
+    memory: &'a mut MapMemory,
+    animation: Option<(f64, Easing)>,
+}
+
+impl<'a> HomeButton<'a> {
+    pub fn new(memory: &'a mut MapMemory) -> Self {
+        Self {
+            memory,
+            animation: None,
+        }
+    }
+
+    /// How long (in seconds) and with what easing the map flies back home, see
+    /// [`MapMemory::go_home_with`]. By default, it's the [`MapMemory::set_flight_animation`].
+    pub fn with_animation(mut self, duration: f64, easing: Easing) -> Self {
+        self.animation = Some((duration, easing));
+        self
+    }
+}
+
+impl Widget for HomeButton<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let response = ui
+            .button(RichText::new("🏠").heading())
+            .on_hover_text("Go home");
+        if response.clicked() {
+            match self.animation {
+                Some((duration, easing)) => self.memory.go_home_with(duration, easing),
+                None => self.memory.go_home(),
+            }
+        }
+        response
+    }
+}
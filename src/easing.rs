@@ -0,0 +1,59 @@
+/// How an animation progresses over time. Lets the map's motion match the rest of the
+/// application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    /// Constant speed.
+    #[default]
+    Linear,
+
+    /// Starts slowly and accelerates.
+    EaseIn,
+
+    /// Starts quickly and decelerates.
+    EaseOut,
+
+    /// Starts and ends slowly.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Turn linear progress `t`, from `0.` to `1.`, into eased one.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1. - (1. - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4. * t * t * t
+                } else {
+                    1. - (-2. * t + 2.).powi(3) / 2.
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easing_starts_at_zero_and_ends_at_one() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            approx::assert_relative_eq!(0., easing.apply(0.));
+            approx::assert_relative_eq!(1., easing.apply(1.));
+            approx::assert_relative_eq!(1., easing.apply(2.));
+        }
+
+        approx::assert_relative_eq!(0.5, Easing::EaseInOut.apply(0.5));
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+    }
+}
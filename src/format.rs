@@ -0,0 +1,104 @@
+//! Turning positions into human readable text.
+
+use crate::{Position, PositionExt};
+
+/// How the coordinates are written. See [`PositionExt::format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoordinateFormat {
+    pub notation: Notation,
+    pub order: Order,
+}
+
+impl Default for CoordinateFormat {
+    /// Decimal degrees with 5 fractional digits (about a meter), latitude first.
+    fn default() -> Self {
+        Self {
+            notation: Notation::Decimal { precision: 5 },
+            order: Order::LatLon,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// Signed decimal degrees with given number of fractional digits, e.g. `51.09916`.
+    Decimal { precision: usize },
+
+    /// Degrees, minutes and seconds with the hemisphere, e.g. `51°05'56.98"N`.
+    Dms,
+}
+
+/// Which of the coordinates comes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    LatLon,
+    LonLat,
+}
+
+pub(crate) fn format(position: &Position, format: CoordinateFormat) -> String {
+    let latitude = coordinate(position.latitude(), format.notation, ['N', 'S']);
+    let longitude = coordinate(position.longitude(), format.notation, ['E', 'W']);
+
+    match format.order {
+        Order::LatLon => format!("{latitude} {longitude}"),
+        Order::LonLat => format!("{longitude} {latitude}"),
+    }
+}
+
+/// Single coordinate, with `hemispheres` being letters used for positive and negative values.
+fn coordinate(value: f64, notation: Notation, hemispheres: [char; 2]) -> String {
+    match notation {
+        Notation::Decimal { precision } => format!("{value:.precision$}"),
+        Notation::Dms => {
+            let hemisphere = if value < 0. {
+                hemispheres[1]
+            } else {
+                hemispheres[0]
+            };
+
+            // Round first, so that seconds never end up as 60.
+            let seconds = (value.abs() * 360_000.).round() / 100.;
+            let degrees = (seconds / 3600.).floor();
+            let minutes = ((seconds - degrees * 3600.) / 60.).floor();
+            let seconds = seconds - degrees * 3600. - minutes * 60.;
+
+            format!("{degrees}°{minutes:02}'{seconds:05.2}\"{hemisphere}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting_coordinates() {
+        let wroclaw = Position::from_lat_lon(51.09916, 17.03664);
+        assert_eq!("51.09916 17.03664", wroclaw.format(Default::default()));
+
+        assert_eq!(
+            "17.04 51.10",
+            wroclaw.format(CoordinateFormat {
+                notation: Notation::Decimal { precision: 2 },
+                order: Order::LonLat,
+            })
+        );
+
+        assert_eq!(
+            "51°05'56.98\"N 17°02'11.90\"E",
+            wroclaw.format(CoordinateFormat {
+                notation: Notation::Dms,
+                ..Default::default()
+            })
+        );
+
+        let rio = Position::from_lat_lon(-22.9, -43.2);
+        assert_eq!(
+            "22°54'00.00\"S 43°12'00.00\"W",
+            rio.format(CoordinateFormat {
+                notation: Notation::Dms,
+                ..Default::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Tracks, routes and waypoints of [GPX](https://www.topografix.com/gpx.asp) files, drawn as a
+//! [`Plugin`].
+
+use std::io::Read;
+
+use egui::{Color32, Painter, Response, Shape, Stroke};
+
+use crate::overlays::{Marker, Polyline, ToShape};
+use crate::{BoundingBox, Plugin, Position, Projector};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("could not parse GPX: {0}")]
+    Parse(#[from] ::gpx::errors::GpxError),
+}
+
+/// Contents of a GPX document. Track segments and routes become [`Polyline`]s and waypoints
+/// become [`Marker`]s, all of which can be restyled before drawing.
+#[derive(Debug, Clone)]
+pub struct GpxLayer {
+    /// Track segments, followed by routes.
+    pub lines: Vec<Polyline>,
+
+    /// Elevation of each point of the `lines`, in meters, if the document has it.
+    pub elevations: Vec<Vec<Option<f64>>>,
+
+    pub waypoints: Vec<Marker>,
+
+    /// If set, each segment of the `lines` gets the color returned for its mean elevation,
+    /// instead of the line's stroke color. Segments without elevation keep the stroke color.
+    pub color_by_elevation: Option<fn(f64) -> Color32>,
+}
+
+impl GpxLayer {
+    /// Parse a GPX document, e.g. a file.
+    pub fn read(reader: impl Read) -> Result<Self, Error> {
+        let gpx = ::gpx::read(reader)?;
+
+        let lines: Vec<&[::gpx::Waypoint]> = gpx
+            .tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .map(|segment| segment.points.as_slice())
+            .chain(gpx.routes.iter().map(|route| route.points.as_slice()))
+            .collect();
+
+        let stroke = Stroke::new(3., Color32::from_rgb(0, 92, 175));
+
+        Ok(Self {
+            elevations: lines
+                .iter()
+                .map(|points| points.iter().map(|point| point.elevation).collect())
+                .collect(),
+            lines: lines
+                .iter()
+                .map(|points| {
+                    Polyline::new(points.iter().map(|point| point.point()).collect(), stroke)
+                })
+                .collect(),
+            waypoints: gpx
+                .waypoints
+                .iter()
+                .map(|waypoint| Marker::new(waypoint.point()))
+                .collect(),
+            color_by_elevation: None,
+        })
+    }
+
+    /// Color the lines according to their elevation, see [`GpxLayer::color_by_elevation`].
+    pub fn with_color_by_elevation(self, color: fn(f64) -> Color32) -> Self {
+        Self {
+            color_by_elevation: Some(color),
+            ..self
+        }
+    }
+
+    /// All positions of the document, lines and waypoints alike.
+    pub fn positions(&self) -> impl Iterator<Item = Position> + '_ {
+        self.lines
+            .iter()
+            .flat_map(|line| line.positions.iter().copied())
+            .chain(self.waypoints.iter().map(|marker| marker.position))
+    }
+
+    /// Area covered by the document, e.g. for [`crate::MapMemory::fit_bounds`]. `None` if it's
+    /// empty.
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        BoundingBox::from_positions(&self.positions().collect::<Vec<_>>())
+    }
+}
+
+impl Plugin for GpxLayer {
+    fn draw(&mut self, response: &Response, painter: Painter, projector: &Projector) {
+        for (line, elevations) in self.lines.iter_mut().zip(&self.elevations) {
+            match self.color_by_elevation {
+                Some(color) => {
+                    painter.extend(colored_segments(line, elevations, color, projector));
+                }
+                None => line.draw(response, painter.clone(), projector),
+            }
+        }
+
+        for waypoint in &self.waypoints {
+            painter.add(waypoint.shape(projector));
+        }
+    }
+}
+
+/// Segments of the `line`, each colored according to its mean elevation.
+fn colored_segments(
+    line: &Polyline,
+    elevations: &[Option<f64>],
+    color: fn(f64) -> Color32,
+    projector: &Projector,
+) -> Vec<Shape> {
+    line.positions
+        .windows(2)
+        .zip(elevations.windows(2))
+        .map(|(positions, elevations)| {
+            let segment_color = match elevations {
+                [Some(a), Some(b)] => color((a + b) / 2.),
+                _ => line.stroke.color,
+            };
+            let width = line
+                .sizing
+                .to_pixels(line.stroke.width, positions[0], projector);
+            Shape::line_segment(
+                [
+                    projector.project(positions[0]).to_pos2(),
+                    projector.project(positions[1]).to_pos2(),
+                ],
+                Stroke::new(width, segment_color.gamma_multiply(projector.opacity())),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GPX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="walkers">
+  <wpt lat="51.11" lon="17.03"><name>Start</name></wpt>
+  <trk>
+    <trkseg>
+      <trkpt lat="51.10" lon="17.00"><ele>120</ele></trkpt>
+      <trkpt lat="51.12" lon="17.05"><ele>140</ele></trkpt>
+    </trkseg>
+  </trk>
+  <rte>
+    <rtept lat="51.20" lon="17.10"></rtept>
+    <rtept lat="51.21" lon="17.11"></rtept>
+  </rte>
+</gpx>"#;
+
+    #[test]
+    fn reading_gpx() {
+        let layer = GpxLayer::read(GPX.as_bytes()).unwrap();
+
+        assert_eq!(layer.lines.len(), 2);
+        assert_eq!(
+            layer.lines[0].positions,
+            [Position::new(17.00, 51.10), Position::new(17.05, 51.12)]
+        );
+        assert_eq!(layer.elevations[0], [Some(120.), Some(140.)]);
+        assert_eq!(layer.elevations[1], [None, None]);
+        assert_eq!(layer.waypoints[0].position, Position::new(17.03, 51.11));
+
+        assert_eq!(
+            layer.bounds(),
+            Some(BoundingBox {
+                west: 17.00,
+                south: 51.10,
+                east: 17.11,
+                north: 51.21
+            })
+        );
+    }
+
+    #[test]
+    fn reading_malformed_gpx() {
+        assert!(GpxLayer::read("<gpx>".as_bytes()).is_err());
+    }
+}
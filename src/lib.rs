@@ -1,15 +1,53 @@
-#![doc = include_str!("../README.md")]
+#![cfg_attr(feature = "gui", doc = include_str!("../README.md"))]
 #![deny(clippy::unwrap_used, rustdoc::broken_intra_doc_links)]
+#![cfg_attr(test, allow(clippy::unwrap_used))]
 
+#[cfg(feature = "gui")]
+mod basemaps;
+#[cfg(feature = "gui")]
+mod controls;
+mod easing;
+pub mod format;
+#[cfg(feature = "gpx")]
+pub mod gpx;
+#[cfg(feature = "gui")]
 mod map;
 mod mercator;
+#[cfg(feature = "gui")]
+mod minimap;
+#[cfg(feature = "mvt")]
+pub mod mvt;
+#[cfg(feature = "gui")]
+pub mod overlays;
+#[cfg(feature = "gui")]
 pub mod providers;
+#[cfg(all(feature = "gui", any(test, feature = "testing")))]
+pub mod testing;
+#[cfg(feature = "gui")]
+pub mod tilejson;
+#[cfg(feature = "gui")]
 mod tiles;
+#[cfg(feature = "gui")]
 mod tokio;
 mod zoom;
 
-pub use map::{Center, Map, MapMemory};
-pub use mercator::{screen_to_position, Position, PositionExt};
-pub use tiles::{Tiles, Tile};
-pub use zoom::Zoom;
-pub use mercator::TileId;
+#[cfg(feature = "gui")]
+pub use basemaps::{Basemap, BasemapPicker};
+#[cfg(feature = "gui")]
+pub use controls::HomeButton;
+pub use easing::Easing;
+#[cfg(feature = "gui")]
+pub use map::{Center, Map, MapMemory, PaintStage, Plugin, Projector};
+pub use mercator::{
+    ground_resolution, screen_to_position, zoom_for_resolution, Latitude, Longitude, Position,
+    PositionExt, MAX_LATITUDE,
+};
+pub use mercator::{BoundingBox, TileId};
+#[cfg(feature = "gui")]
+pub use minimap::Minimap;
+#[cfg(feature = "gui")]
+pub use tiles::{
+    Credentials, FetchMode, Preprocess, RegionDownload, TemporalTileUrl, Tile, TileError,
+    TileEvent, TileRequest, TileStats, TileUrl, Tiles, TilesOptions,
+};
+pub use zoom::{InvalidZoom, Zoom};
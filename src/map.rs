@@ -1,15 +1,69 @@
 use std::collections::{hash_map::Entry, HashMap};
+use std::ops::RangeInclusive;
 
-use egui::{Mesh, Painter, Pos2, Response, Sense, Ui, Widget};
+use egui::{
+    vec2, Align, Color32, CursorIcon, Frame, Key, Layout, Mesh, Painter, Pos2, Rect, Response,
+    Sense, Ui, Vec2, Widget, WidgetInfo, WidgetType,
+};
 
 use crate::{
-    mercator::{screen_to_position, PositionExt, TileId},
-    Position, Tiles, Zoom,
+    mercator::{ground_resolution, screen_to_position, PositionExt, TileId, TILE_SIZE},
+    overlays::MarkerTransitions,
+    providers::{Attribution, Attributions},
+    zoom::{InvalidZoom, MAX_ZOOM},
+    BoundingBox, Easing, Position, Tiles, Zoom,
 };
 
+/// How far (in pixels) the map moves when an arrow key is pressed.
+const KEYBOARD_PAN_STEP: f32 = 64.;
+
+/// How long (in seconds) it takes for the map to glide to the new `my_position`.
+const MY_POSITION_ANIMATION: f64 = 0.5;
+
+/// How far (in pixels) `my_position` needs to move for the map to follow it.
+const MY_POSITION_THRESHOLD: f32 = 1.;
+
+/// How long (in seconds) it takes for the map to fly back home, see [`MapMemory::go_home`] and
+/// [`MapMemory::set_flight_animation`].
+const FLIGHT_ANIMATION: f64 = 0.5;
+
+/// How soon (in seconds) after a tap the next press starts the double-tap zoom, see
+/// [`Map::with_double_tap_zoom`].
+const DOUBLE_TAP_TIME: f64 = 0.3;
+
+/// How far (in pixels) from the first tap the second one can be.
+const DOUBLE_TAP_DISTANCE: f32 = 20.;
+
+/// How far (in pixels) the pointer needs to be dragged to zoom by one level with the double-tap
+/// zoom.
+const DOUBLE_TAP_ZOOM_DRAG: f32 = 100.;
+
+/// Time (in seconds) over which the speed of the zoom gesture is averaged for the zoom inertia,
+/// so that a single step, like a notch of the mouse wheel, gives only a slight momentum.
+const ZOOM_VELOCITY_SMOOTHING: f32 = 0.25;
+
+/// Fastest the zoom inertia can go, in levels per second.
+const MAX_ZOOM_VELOCITY: f32 = 4.;
+
+/// How many zoom levels below the current one are kept in memory, so that their tiles can fill in
+/// for the ones which are not loaded yet, e.g. right after zooming in.
+const FALLBACK_LEVELS: u8 = 3;
+
+/// How large (in points) the box zoom's selection needs to be, so that e.g. a `Shift` + click
+/// does not zoom into a single point.
+const MIN_BOX_ZOOM_SIZE: f32 = 4.;
+
+/// Height (in points) of the strips the mask of [`Map::with_clip_polygon`] is made of.
+const MASK_STRIP: f32 = 1.;
+
 /// The actual map widget. Instances are to be created on each frame, as all necessary state is
 /// stored in [`Tiles`] and [`MapMemory`].
 ///
+/// Map can be focused with the keyboard, and then panned using arrows and zoomed with `+` and
+/// `-` keys. Dragging while holding `Shift` selects a rectangular area and zooms into it, see
+/// [`MapMemory::selection`]. Tapping, then pressing again and dragging vertically can zoom with a
+/// single finger, see [`Map::with_double_tap_zoom`].
+///
 /// # Examples
 ///
 /// ```
@@ -23,13 +77,68 @@ use crate::{
 ///     ));
 /// }
 /// ```
-pub struct Map<'a, 'b> {
+///
+/// # Tracking the position
+///
+/// `my_position` is typically fed by a GPS sensor, or the browser's geolocation API. Pass the most
+/// recent one each frame, regardless of how often it's updated (GPS receivers usually do it once
+/// a second). When it changes, the map glides to the new one, instead of jumping. See
+/// [`Map::with_my_position_animation`].
+///
+/// # Layers
+///
+/// Map is always painted in the same order, from the bottom:
+///
+/// 1. tiles,
+/// 2. plugins, in the order they were added with [`Map::with_plugin`],
+/// 3. mask covering everything outside of the clip polygon, see [`Map::with_clip_polygon`],
+/// 4. attribution, see [`Map::with_attribution`].
+///
+/// Use [`Map::on_paint`] to paint something in between.
+pub struct Map<'a, 'b, 'c> {
     tiles: Option<&'b mut Tiles>,
     memory: &'a mut MapMemory,
     my_position: Position,
+    plugins: Vec<Box<dyn Plugin + 'c>>,
+    hover_cursor: CursorIcon,
+    drag_cursor: CursorIcon,
+    opacity: f32,
+    opacity_for_plugins: bool,
+    sense: Sense,
+    zoom_enabled: bool,
+    zoom_inertia: Option<f32>,
+    double_tap_zoom: bool,
+    my_position_animation: (f64, Easing),
+    my_position_threshold: f32,
+    interaction_guard: bool,
+    plugins_clip_margin: f32,
+    tint: Color32,
+    tile_tint: Option<Box<dyn Fn(TileId) -> Color32 + 'c>>,
+    attributions: Vec<Attribution>,
+    paint_callbacks: Vec<(PaintStage, Box<PaintCallback<'c>>)>,
+    clip_polygon: Option<(Vec<Position>, Color32)>,
+    aspect_ratio: Option<f32>,
+    fast_pan_speed: Option<f32>,
+    tile_buffer: Option<f32>,
+    min_drag_distance: f32,
+}
+
+type PaintCallback<'c> = dyn FnMut(&Painter, &Projector) + 'c;
+
+/// Moment of painting the [`Map`], see [`Map::on_paint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaintStage {
+    /// Above the tiles, but below the plugins.
+    AboveTiles,
+
+    /// Above the plugins, but below the attribution.
+    AbovePlugins,
+
+    /// On top of everything.
+    AboveAttribution,
 }
 
-impl<'a, 'b> Map<'a, 'b> {
+impl<'a, 'b, 'c> Map<'a, 'b, 'c> {
     pub fn new(
         tiles: Option<&'b mut Tiles>,
         memory: &'a mut MapMemory,
@@ -39,61 +148,846 @@ impl<'a, 'b> Map<'a, 'b> {
             tiles,
             memory,
             my_position,
+            plugins: Vec::new(),
+            hover_cursor: CursorIcon::Grab,
+            drag_cursor: CursorIcon::Grabbing,
+            opacity: 1.,
+            opacity_for_plugins: false,
+            sense: Sense::drag(),
+            zoom_enabled: true,
+            zoom_inertia: None,
+            double_tap_zoom: false,
+            my_position_animation: (MY_POSITION_ANIMATION, Easing::EaseOut),
+            my_position_threshold: MY_POSITION_THRESHOLD,
+            interaction_guard: false,
+            plugins_clip_margin: 0.,
+            tint: Color32::WHITE,
+            tile_tint: None,
+            attributions: Vec::new(),
+            paint_callbacks: Vec::new(),
+            clip_polygon: None,
+            aspect_ratio: None,
+            fast_pan_speed: None,
+            tile_buffer: None,
+            min_drag_distance: 0.,
+        }
+    }
+
+    /// Show the tile provider's attribution in the bottom-right corner of the map, on top of the
+    /// plugins. Can be called for each of the stacked tile layers, they are all shown in a single
+    /// line then, without repetitions. See [`Attributions`].
+    pub fn with_attribution(mut self, attribution: Attribution) -> Self {
+        self.attributions.push(attribution);
+        self
+    }
+
+    /// Paint something at given `stage`, e.g. between the tiles and the plugins. Callbacks of the
+    /// same stage are called in the order they were added.
+    pub fn on_paint(
+        mut self,
+        stage: PaintStage,
+        callback: impl FnMut(&Painter, &Projector) + 'c,
+    ) -> Self {
+        self.paint_callbacks.push((stage, Box::new(callback)));
+        self
+    }
+
+    fn paint_stage(&mut self, stage: PaintStage, painter: &Painter, projector: &Projector) {
+        for (_, callback) in self
+            .paint_callbacks
+            .iter_mut()
+            .filter(|(callback_stage, _)| *callback_stage == stage)
+        {
+            callback(painter, projector);
+        }
+    }
+
+    /// Show the map only inside of the `polygon`, e.g. a country boundary, covering everything
+    /// outside of it (tiles and plugins alike) with the `mask` color, typically the background of
+    /// the surrounding UI. The polygon can be concave.
+    pub fn with_clip_polygon(mut self, polygon: Vec<Position>, mask: Color32) -> Self {
+        self.clip_polygon = Some((polygon, mask));
+        self
+    }
+
+    /// Color tiles are multiplied by, e.g. to give them a sepia look or dim a basemap, without
+    /// processing the imagery. [`Color32::WHITE`] (the default) leaves them as they are.
+    pub fn with_tint(mut self, tint: Color32) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    /// Tint each tile with a color given by `tint`, e.g. depending on some data value of the area
+    /// it covers. Takes precedence over [`Map::with_tint`].
+    pub fn with_tile_tint(mut self, tint: impl Fn(TileId) -> Color32 + 'c) -> Self {
+        self.tile_tint = Some(Box::new(tint));
+        self
+    }
+
+    /// How long (in seconds) and with what easing the map glides to the new `my_position`, when
+    /// it follows it. By default it takes half a second. Use `0.` to make it jump right away.
+    pub fn with_my_position_animation(mut self, duration: f64, easing: Easing) -> Self {
+        self.my_position_animation = (duration, easing);
+        self
+    }
+
+    /// How far (in screen pixels) `my_position` needs to move for the map to follow it, one
+    /// pixel by default. Smaller changes are accumulated until they exceed it, so that frequent
+    /// updates (e.g. from a 10 Hz GPS receiver) do not make the map repaint for invisible,
+    /// sub-pixel movements.
+    pub fn with_my_position_threshold(mut self, pixels: f32) -> Self {
+        self.my_position_threshold = pixels;
+        self
+    }
+
+    /// What kind of interaction the map senses, [`Sense::drag`] by default. Map can be panned
+    /// and zoomed only if it senses dragging, so use [`Sense::hover`] to get a static map, e.g. for
+    /// a thumbnail. [`Sense::click_and_drag`] additionally reports clicks in the response.
+    pub fn with_sense(mut self, sense: Sense) -> Self {
+        self.sense = sense;
+        self
+    }
+
+    /// Make the map ignore the input until it's clicked, showing a hint when hovered. Clicking
+    /// anywhere else makes it ignore the input again. Useful when the map is embedded in
+    /// a scrolled page, so that it does not get panned by accident.
+    pub fn with_interaction_guard(mut self, enabled: bool) -> Self {
+        self.interaction_guard = enabled;
+        self
+    }
+
+    /// Clip the plugins (and [`Map::on_paint`]) to the map's area expanded by `margin` pixels,
+    /// e.g. to let labels hang off its edges. Negative values clip them more tightly than the
+    /// tiles. Plugins can never draw outside of the [`Ui`] the map is in, though.
+    pub fn with_plugins_clip_margin(mut self, margin: f32) -> Self {
+        self.plugins_clip_margin = margin;
+        self
+    }
+
+    /// Keep the map's width to height ratio at `aspect_ratio` (e.g. `1.` for a square), instead of
+    /// filling all of the available space. The map is centered within that space, which is left
+    /// empty around it.
+    pub fn with_aspect_ratio(mut self, aspect_ratio: f32) -> Self {
+        self.aspect_ratio = Some(aspect_ratio);
+        self
+    }
+
+    /// While the map is panned faster than `speed` (in pixels per second), do not download any
+    /// tiles, but draw the ones already cached, falling back to the parts of their parents, which
+    /// are kept for a few zoom levels below the current one. Tiles of all these transient views
+    /// would be thrown away anyway. Missing tiles are requested once the panning slows down.
+    /// Disabled by default.
+    pub fn with_fast_pan_speed(mut self, speed: f32) -> Self {
+        self.fast_pan_speed = Some(speed);
+        self
+    }
+
+    /// Drop the tiles of the current zoom level which are further than `pixels` away from the
+    /// map's edges, so that the memory does not grow while panning far away. Tiles within that
+    /// margin are kept, so panning back and forth does not load them again. By default, tiles
+    /// are dropped only when they are of another zoom level.
+    pub fn with_tile_buffer(mut self, pixels: f32) -> Self {
+        self.tile_buffer = Some(pixels);
+        self
+    }
+
+    /// Whether the user can zoom the map, with mouse wheel, pinch gesture or keyboard. Panning
+    /// keeps working regardless. Zoom can still be changed programmatically via [`MapMemory`].
+    pub fn zoom_enabled(mut self, enabled: bool) -> Self {
+        self.zoom_enabled = enabled;
+        self
+    }
+
+    /// Keep zooming for a while after the gesture (e.g. pinch on a trackpad) ends, slowing down
+    /// until it stops. `time_constant` (in seconds) tells how quickly it slows down, something
+    /// around `0.2` feels natural. It keeps zooming around the same point as the gesture did,
+    /// the map's center, and stops as soon as the map is panned. Disabled by default.
+    pub fn with_zoom_inertia(mut self, time_constant: f32) -> Self {
+        self.zoom_inertia = Some(time_constant);
+        self
+    }
+
+    /// Whether a tap, followed by pressing again and dragging vertically, zooms the map around
+    /// the tapped point, for zooming with one thumb. Dragging down zooms in. Disabled by default,
+    /// as it is a touch gesture which gets in the way of panning with a mouse.
+    pub fn with_double_tap_zoom(mut self, enabled: bool) -> Self {
+        self.double_tap_zoom = enabled;
+        self
+    }
+
+    /// How far (in pixels) the map following `my_position` needs to be dragged before it gets
+    /// detached, so that a tap which slightly moves does not break the following. Once past
+    /// it, the map catches up with the pointer. `0.` by default.
+    pub fn with_min_drag_distance(mut self, pixels: f32) -> Self {
+        self.min_drag_distance = pixels;
+        self
+    }
+
+    /// Draw tiles with given opacity, from `0.` (invisible) to `1.` (opaque, the default). Useful
+    /// for dimming the map, or showing it behind other content.
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0., 1.);
+        self
+    }
+
+    /// Whether plugins should respect the [`Map::with_opacity`] too. They don't by default.
+    /// See [`Projector::opacity`].
+    pub fn with_opacity_for_plugins(mut self, enabled: bool) -> Self {
+        self.opacity_for_plugins = enabled;
+        self
+    }
+
+    /// Mouse cursors shown when hovering over the map and while dragging it. By default these
+    /// are [`CursorIcon::Grab`] and [`CursorIcon::Grabbing`].
+    pub fn with_cursor_icons(mut self, hover: CursorIcon, drag: CursorIcon) -> Self {
+        self.hover_cursor = hover;
+        self.drag_cursor = drag;
+        self
+    }
+
+    /// Add a plugin, which will draw on top of the tiles. Plugins are drawn in the order they
+    /// were added.
+    pub fn with_plugin(mut self, plugin: impl Plugin + 'c) -> Self {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    /// Arrows pan the map, plus and minus keys zoom it.
+    fn handle_keyboard(&mut self, ui: &Ui) {
+        let (pan, zoom) = ui.input(|input| {
+            let mut pan = Vec2::ZERO;
+            for (key, direction) in [
+                (Key::ArrowLeft, vec2(-1., 0.)),
+                (Key::ArrowRight, vec2(1., 0.)),
+                (Key::ArrowUp, vec2(0., -1.)),
+                (Key::ArrowDown, vec2(0., 1.)),
+            ] {
+                if input.key_pressed(key) {
+                    pan += direction * KEYBOARD_PAN_STEP;
+                }
+            }
+
+            let zoom =
+                input.key_pressed(Key::PlusEquals) as i32 - input.key_pressed(Key::Minus) as i32;
+            (pan, zoom)
+        });
+
+        if pan != Vec2::ZERO {
+            self.memory
+                .center_mode
+                .shift(pan, self.my_position, self.memory.zoom.round());
+            self.memory.pan_delta += pan;
+        }
+
+        if !self.zoom_enabled {
+            return;
+        }
+
+        let zoomed = match zoom {
+            1 => self.memory.zoom.zoom_in().is_ok(),
+            -1 => self.memory.zoom.zoom_out().is_ok(),
+            _ => false,
+        };
+        if zoomed {
+            self.clean_up_zoom();
+        }
+    }
+
+    /// Zoom by `delta` levels, dropping the tiles which are not needed anymore if the zoom level
+    /// changes.
+    fn zoom_by(&mut self, delta: f32) {
+        if self.memory.zoom.zoom_by(delta) {
+            self.clean_up_zoom();
+        }
+    }
+
+    /// How much the center should move because of the dragging in this frame. While the map
+    /// follows `my_position`, the dragging is held back until it adds up to
+    /// [`Map::with_min_drag_distance`].
+    fn drag_delta(&mut self, response: &Response) -> Vec2 {
+        if !response.dragged_by(egui::PointerButton::Primary) {
+            self.memory.pending_drag = Vec2::ZERO;
+            return Vec2::ZERO;
+        }
+
+        let delta = -response.drag_delta();
+        if self.memory.center_mode != Center::MyPosition {
+            return delta;
+        }
+
+        self.memory.pending_drag += delta;
+        if self.memory.pending_drag.length() < self.min_drag_distance {
+            Vec2::ZERO
+        } else {
+            std::mem::take(&mut self.memory.pending_drag)
+        }
+    }
+
+    /// Zoom by dragging vertically right after a tap, see [`Map::with_double_tap_zoom`]. Returns
+    /// whether the gesture is in progress, in which case the map should not be panned.
+    fn handle_double_tap_zoom(&mut self, ui: &Ui, rect: Rect) -> bool {
+        if !self.double_tap_zoom || !self.zoom_enabled {
+            self.memory.last_tap = None;
+            self.memory.double_tap_anchor = None;
+            return false;
+        }
+
+        let (now, clicked, pressed, down, position, delta) = ui.input(|input| {
+            (
+                input.time,
+                input.pointer.any_click(),
+                input.pointer.any_pressed(),
+                input.pointer.primary_down(),
+                input.pointer.interact_pos(),
+                input.pointer.delta(),
+            )
+        });
+
+        if pressed {
+            if let (Some((time, tap)), Some(position)) = (self.memory.last_tap.take(), position) {
+                if now - time < DOUBLE_TAP_TIME && tap.distance(position) < DOUBLE_TAP_DISTANCE {
+                    self.memory.double_tap_anchor = Some(position);
+                }
+            }
+        } else if clicked && self.memory.double_tap_anchor.is_none() {
+            self.memory.last_tap = position.map(|position| (now, position));
+        }
+
+        let Some(anchor) = self.memory.double_tap_anchor else {
+            return false;
+        };
+
+        if !down {
+            self.memory.double_tap_anchor = None;
+            return false;
+        }
+
+        if delta.y != 0. {
+            let my_position = self.my_position;
+            let projector = move |memory: &MapMemory| Projector {
+                clip_rect: rect,
+                map_center: memory.center_mode.position(my_position),
+                zoom: memory.zoom.round(),
+                opacity: 1.,
+            };
+
+            // Keep the anchor where it was on the screen.
+            let anchored = projector(self.memory).unproject(anchor);
+            self.zoom_by(delta.y / DOUBLE_TAP_ZOOM_DRAG);
+            let moved = projector(self.memory).project(anchored).to_pos2() - anchor;
+            if moved != Vec2::ZERO {
+                self.memory
+                    .center_mode
+                    .shift(moved, self.my_position, self.memory.zoom.round());
+            }
+        }
+
+        true
+    }
+
+    /// Average the speed of the zoom gesture, which zoomed by `delta` in this frame, over the
+    /// last few frames.
+    fn track_zoom_velocity(&mut self, delta: f32, ui: &Ui) {
+        let dt = ui.input(|input| input.stable_dt);
+        if dt <= 0. {
+            return;
+        }
+        let weight = (dt / ZOOM_VELOCITY_SMOOTHING).min(1.);
+        let velocity = self.memory.zoom_velocity * (1. - weight) + delta / dt * weight;
+        self.memory.zoom_velocity = velocity.clamp(-MAX_ZOOM_VELOCITY, MAX_ZOOM_VELOCITY);
+    }
+
+    /// Keep zooming with the velocity of the most recent gesture, slowing it down.
+    fn apply_zoom_inertia(&mut self, ui: &Ui) {
+        let (Some(time_constant), true) = (self.zoom_inertia, self.zoom_enabled) else {
+            self.memory.zoom_velocity = 0.;
+            return;
+        };
+
+        // Slower than that is not noticeable anyway.
+        if self.memory.zoom_velocity.abs() < 0.01 {
+            self.memory.zoom_velocity = 0.;
+            return;
+        }
+
+        let dt = ui.input(|input| input.stable_dt);
+        self.zoom_by(self.memory.zoom_velocity * dt);
+        self.memory.zoom_velocity *= (-dt / time_constant).exp();
+        ui.ctx().request_repaint();
+    }
+
+    /// Replace the `my_position` with where it should be drawn, while gliding to the new one.
+    fn animate_my_position(&mut self, ui: &Ui) {
+        let (duration, easing) = self.my_position_animation;
+        let transition = self
+            .memory
+            .my_position_transition
+            .get_or_insert_with(|| MarkerTransitions::new(duration));
+        transition.duration = duration;
+        transition.easing = easing;
+
+        let mut target = self.my_position;
+        if let Some(current) = transition.target(&()) {
+            let zoom = self.memory.zoom.round();
+            if (target.project(zoom) - current.project(zoom)).length() < self.my_position_threshold
+            {
+                target = current;
+            }
+        }
+
+        let now = ui.input(|input| input.time);
+        let (position, moving) = transition.update((), target, now);
+        self.my_position = position;
+
+        if moving && self.memory.center_mode == Center::MyPosition {
+            ui.ctx().request_repaint();
+        }
+    }
+
+    /// Move the map towards the destination of the flight, e.g. the home view after
+    /// [`MapMemory::go_home`] was called.
+    fn fly(&mut self, ui: &Ui) {
+        let Some(flight) = &mut self.memory.flight else {
+            return;
+        };
+
+        let now = ui.input(|input| input.time);
+        let (from, from_zoom, start) = *flight.start.get_or_insert((
+            self.memory.center_mode.position(self.my_position),
+            self.memory.zoom.value(),
+            now,
+        ));
+        let (home, home_zoom) = flight.destination.clone();
+        let (duration, easing) = flight.animation;
+
+        let t = if duration > 0. {
+            (now - start) / duration
+        } else {
+            1.
+        };
+        if t >= 1. {
+            self.memory.center_mode = home;
+            self.memory.zoom = home_zoom;
+            self.memory.flight = None;
+            return;
+        }
+
+        let t = easing.apply(t);
+        let to = home.position(self.my_position);
+        self.memory.center_mode = Center::Exact(from.lerp(to, t));
+        if let Ok(zoom) = Zoom::try_from(from_zoom + (home_zoom.value() - from_zoom) * t as f32) {
+            self.memory.zoom = zoom;
+        }
+        ui.ctx().request_repaint();
+    }
+
+    /// Fly back to `my_position` once the map was left alone for long enough, see
+    /// [`MapMemory::set_auto_recenter`].
+    fn auto_recenter(&mut self, ui: &Ui, interacted: bool) {
+        let now = ui.input(|input| input.time);
+        if interacted || self.memory.pan_delta != Vec2::ZERO {
+            self.memory.last_interaction = Some(now);
+        }
+
+        let (Some(timeout), Some(last_interaction)) =
+            (self.memory.auto_recenter, self.memory.last_interaction)
+        else {
+            return;
+        };
+
+        if self.memory.center_mode == Center::MyPosition {
+            self.memory.last_interaction = None;
+            return;
+        }
+
+        let idle = now - last_interaction;
+        if idle >= timeout {
+            self.memory.last_interaction = None;
+            self.memory.follow_my_position();
+            ui.ctx().request_repaint();
+        } else {
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_secs_f64(timeout - idle));
+        }
+    }
+
+    /// Track the rectangle being selected with `Shift` held, and zoom into it once released.
+    fn handle_box_zoom(&mut self, ui: &Ui, response: &Response, rect: Rect) {
+        let (shift, origin, pointer) = ui.input(|input| {
+            (
+                input.modifiers.shift,
+                input.pointer.press_origin(),
+                input.pointer.interact_pos(),
+            )
+        });
+
+        if response.drag_released() {
+            if let (Some(selection), Some(projector)) = (
+                self.memory
+                    .box_zoom
+                    .take()
+                    .filter(|selection| selection.size().max_elem() >= MIN_BOX_ZOOM_SIZE),
+                self.memory.projector(rect),
+            ) {
+                let bounds = BoundingBox::from_corners(
+                    projector.unproject(selection.min),
+                    projector.unproject(selection.max),
+                );
+                self.memory.selection = Some(bounds);
+
+                if self.zoom_enabled {
+                    let old = self.memory.zoom.round();
+                    self.memory.fit_bounds(bounds, rect.size());
+                    if old != self.memory.zoom.round() {
+                        self.clean_up_zoom();
+                    }
+                }
+            }
+        } else if response.dragged() && (shift || self.memory.box_zoom.is_some()) {
+            if let (Some(origin), Some(pointer)) = (origin, pointer) {
+                self.memory.box_zoom = Some(Rect::from_two_pos(origin, pointer).intersect(rect));
+            }
+        } else {
+            self.memory.box_zoom = None;
         }
     }
-    fn clean_up_zoom(&mut self, zoom: u8){
-        if let Some(tiles) = &mut self.tiles{
-            tiles.clean_up_zoom(zoom);
+
+    /// Drop the tiles of the zoom levels other than the current one, except for the few below
+    /// it, which fill in for the missing tiles, see [`FALLBACK_LEVELS`].
+    fn clean_up_zoom(&mut self) {
+        let zoom = self.memory.zoom.round();
+        if let Some(tiles) = &mut self.tiles {
+            tiles.retain_zooms(zoom.saturating_sub(FALLBACK_LEVELS)..=zoom);
         }
     }
 }
 
-impl Widget for Map<'_, '_> {
+impl Widget for Map<'_, '_, '_> {
     fn ui(mut self, ui: &mut Ui) -> Response {
-        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::drag());
+        let guarded = self.interaction_guard && !self.memory.interacting;
+
+        // A guarded map does not sense dragging, so that it's left to e.g. the scroll area
+        // around it.
+        let sense = if guarded { Sense::click() } else { self.sense };
+        let (id, available) = ui.allocate_space(ui.available_size());
+        let rect = match self.aspect_ratio {
+            Some(aspect_ratio) => letterbox(available, aspect_ratio),
+            None => available,
+        };
+        let response = ui.interact(rect, id, sense);
+        let interactive = sense.drag;
+
+        if self.interaction_guard {
+            if guarded && response.clicked() {
+                self.memory.interacting = true;
+                ui.ctx().request_repaint();
+            } else if !guarded && response.clicked_elsewhere() {
+                self.memory.interacting = false;
+            }
+        }
+
+        let zoom_before = self.memory.zoom;
+        self.memory.selection = None;
+        self.memory.pan_delta = Vec2::ZERO;
+        self.memory.zoom_delta = 0.;
+
+        // Happens e.g. in a collapsed panel, or in the very first frame. There is nothing to draw
+        // then, and trying would only spin over nonsensical tile ranges.
+        if !has_area(rect) || !ui.ctx().pixels_per_point().is_normal() {
+            return response;
+        }
+
+        self.animate_my_position(ui);
+        self.fly(ui);
 
-        if response.hovered() {
+        let mut zoomed = false;
+        if interactive && response.hovered() && self.handle_double_tap_zoom(ui, rect) {
+            zoomed = true;
+        } else if interactive && response.hovered() {
             let zoom_delta = ui.input(|input| input.zoom_delta());
 
             // Zooming and dragging need to be exclusive, otherwise the map will get dragged when
-            // pinch gesture is used.
+            // pinch gesture is used. That holds even if zooming is disabled.
             if !(0.99..=1.01).contains(&zoom_delta) {
-                // Shift by 1 because of the values given by zoom_delta(). Multiple by 2, because
-                // then it felt right with both mouse wheel, and an Android phone.
-                let old = self.memory.zoom.round();
-                if self.memory.zoom.zoom_by((zoom_delta - 1.) * 2.) {
-                    self.clean_up_zoom(old);
-                };
-            } else {
-                self.memory
-                    .center_mode
-                    .drag(&response, self.my_position, self.memory.zoom.round());
+                if self.zoom_enabled {
+                    // Shift by 1 because of the values given by zoom_delta(). Multiple by 2,
+                    // because then it felt right with both mouse wheel, and an Android phone.
+                    self.zoom_by((zoom_delta - 1.) * 2.);
+                    zoomed = true;
+                    if self.zoom_inertia.is_some() {
+                        self.track_zoom_velocity((zoom_delta - 1.) * 2., ui);
+                    }
+                }
+            } else if self.memory.box_zoom.is_none() && !ui.input(|input| input.modifiers.shift) {
+                let delta = self.drag_delta(&response);
+                if delta != Vec2::ZERO {
+                    self.memory.center_mode.shift(
+                        delta,
+                        self.my_position,
+                        self.memory.zoom.round(),
+                    );
+                    self.memory.pan_delta += delta;
+                }
+            }
+        }
+
+        if !zoomed {
+            self.apply_zoom_inertia(ui);
+        }
+
+        // User takes over.
+        if zoomed || self.memory.pan_delta != Vec2::ZERO {
+            self.memory.flight = None;
+        }
+        if self.memory.pan_delta != Vec2::ZERO {
+            self.memory.zoom_velocity = 0.;
+        }
+
+        if response.dragged() {
+            ui.ctx().set_cursor_icon(self.drag_cursor);
+        } else if interactive && response.hovered() {
+            ui.ctx().set_cursor_icon(self.hover_cursor);
+        }
+
+        if interactive && response.has_focus() {
+            self.handle_keyboard(ui);
+        }
+
+        self.memory.my_position = Some(self.my_position);
+
+        if interactive {
+            self.handle_box_zoom(ui, &response, rect);
+        }
+
+        self.auto_recenter(ui, zoomed || response.dragged());
+
+        if let Some(levels) = self.tiles.as_ref().and_then(|tiles| tiles.zoom_levels()) {
+            let levels = levels.to_vec();
+            let old = self.memory.zoom.round();
+            self.memory.zoom.snap_to_levels(&levels, zoom_before);
+            if self.memory.zoom.round() != old {
+                self.clean_up_zoom();
             }
         }
 
+        self.memory.zoom_delta = self.memory.zoom.value() - zoom_before.value();
+
         let map_center = self.memory.center_mode.position(self.my_position);
         let painter = ui.painter().with_clip_rect(rect);
 
-        if let Some(tiles) = self.tiles {
+        let projector = Projector {
+            clip_rect: rect,
+            map_center,
+            zoom: self.memory.zoom.round(),
+            opacity: if self.opacity_for_plugins {
+                self.opacity
+            } else {
+                1.
+            },
+        };
+
+        let pan_speed = self.memory.pan_delta.length() / ui.input(|input| input.stable_dt);
+        let fast = self.fast_pan_speed.is_some_and(|speed| pan_speed > speed);
+        if fast {
+            // Make sure that the tiles get requested, even if there is no more input.
+            ui.ctx().request_repaint();
+        }
+
+        if let Some(tiles) = self.tiles.take() {
+            let zoom = self.memory.zoom.round();
             let mut meshes = Default::default();
             draw_tiles(
                 &painter,
-                map_center.tile_id(self.memory.zoom.round()),
-                map_center.project(self.memory.zoom.round()),
+                map_center.tile_id(zoom),
+                map_center.project(zoom),
                 tiles,
+                !fast,
                 ui,
                 &mut meshes,
             );
 
-            for (_, shape) in meshes {
-                painter.add(shape);
+            if let Some(buffer) = self.tile_buffer {
+                let kept = rect.expand(buffer);
+                tiles.retain(|tile_id| {
+                    tile_id.zoom != zoom
+                        || kept.intersects(tile_rect(tile_id, map_center.project(zoom), rect))
+                });
+            }
+
+            // Always in the same order, so that the frames are reproducible. Coarser tiles, which
+            // fill in for the missing ones, go first.
+            let mut meshes: Vec<(TileId, Mesh)> = meshes
+                .into_iter()
+                .filter(|(_, mesh)| !mesh.is_empty())
+                .collect();
+            meshes.sort_by_key(|(tile_id, _)| (tile_id.zoom, tile_id.y, tile_id.x));
+
+            for (tile_id, mut mesh) in meshes {
+                let tint = match &self.tile_tint {
+                    Some(tile_tint) => tile_tint(tile_id),
+                    None => self.tint,
+                }
+                .gamma_multiply(self.opacity);
+
+                if tint != Color32::WHITE {
+                    mesh.vertices
+                        .iter_mut()
+                        .for_each(|vertex| vertex.color = tint);
+                }
+                painter.add(mesh);
+            }
+        }
+
+        let painter = ui
+            .painter()
+            .with_clip_rect(rect.expand(self.plugins_clip_margin));
+
+        self.paint_stage(PaintStage::AboveTiles, &painter, &projector);
+
+        for plugin in &mut self.plugins {
+            if plugin.visible_zooms().contains(&projector.zoom()) {
+                plugin.draw(&response, painter.clone(), &projector);
             }
         }
 
+        if let Some((polygon, mask)) = &self.clip_polygon {
+            let polygon: Vec<Pos2> = polygon
+                .iter()
+                .map(|position| projector.project(*position).to_pos2())
+                .collect();
+            painter.add(outside_of_polygon(&polygon, painter.clip_rect(), *mask));
+        }
+
+        self.paint_stage(PaintStage::AbovePlugins, &painter, &projector);
+
+        if !self.attributions.is_empty() {
+            let mut ui = ui.child_ui(rect.shrink(4.), Layout::bottom_up(Align::Max));
+            Frame::none()
+                .fill(ui.visuals().extreme_bg_color.gamma_multiply(0.7))
+                .inner_margin(2.)
+                .rounding(2.)
+                .show(&mut ui, |ui| ui.add(Attributions::new(&self.attributions)));
+        }
+
+        self.paint_stage(PaintStage::AboveAttribution, &painter, &projector);
+
+        let painter = ui.painter().with_clip_rect(rect);
+        if guarded && response.hovered() {
+            painter.rect_filled(rect, 0., Color32::from_black_alpha(64));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Click to interact",
+                egui::FontId::proportional(16.),
+                Color32::WHITE,
+            );
+        }
+
+        if let Some(selection) = self.memory.box_zoom {
+            let stroke = ui.visuals().selection.stroke;
+            painter.rect(selection, 0., stroke.color.gamma_multiply(0.2), stroke);
+        }
+
+        if response.has_focus() {
+            painter.rect_stroke(rect.shrink(1.), 0., ui.visuals().selection.stroke);
+        }
+
+        // Announce where the map is for the assistive technologies.
+        let zoom = self.memory.zoom.round();
+        response.widget_info(|| {
+            WidgetInfo::labeled(
+                WidgetType::Other,
+                format!(
+                    "Map centered at latitude {:.5}, longitude {:.5}, zoom {}",
+                    map_center.y(),
+                    map_center.x(),
+                    zoom
+                ),
+            )
+        });
+
         response
     }
 }
 
+/// Plugins allow drawing custom shapes on the map. Implement this trait for your type and add it to
+/// the map with [`Map::with_plugin`]. See [`crate::overlays`] for some ready-made ones.
+pub trait Plugin {
+    /// Draw the plugin. `painter` is clipped to the map's area.
+    fn draw(&mut self, response: &Response, painter: Painter, projector: &Projector);
+
+    /// Zoom levels at which the plugin is drawn at all. Map does not call [`Plugin::draw`] at
+    /// the other ones. See [`crate::overlays::ZoomRange`] to limit any plugin.
+    fn visible_zooms(&self) -> RangeInclusive<u8> {
+        0..=MAX_ZOOM
+    }
+}
+
+impl<T: Plugin + ?Sized> Plugin for Box<T> {
+    fn draw(&mut self, response: &Response, painter: Painter, projector: &Projector) {
+        (**self).draw(response, painter, projector);
+    }
+
+    fn visible_zooms(&self) -> RangeInclusive<u8> {
+        (**self).visible_zooms()
+    }
+}
+
+impl<T: Plugin + ?Sized> Plugin for &mut T {
+    fn draw(&mut self, response: &Response, painter: Painter, projector: &Projector) {
+        (**self).draw(response, painter, projector);
+    }
+
+    fn visible_zooms(&self) -> RangeInclusive<u8> {
+        (**self).visible_zooms()
+    }
+}
+
+/// Turns geographical positions into screen coordinates of the currently drawn map, and back.
+pub struct Projector {
+    clip_rect: Rect,
+    map_center: Position,
+    zoom: u8,
+    opacity: f32,
+}
+
+impl Projector {
+    /// Screen coordinates of the `position`.
+    pub fn project(&self, position: Position) -> Vec2 {
+        self.clip_rect.center().to_vec2() + position.project(self.zoom).to_vec2()
+            - self.map_center.project(self.zoom).to_vec2()
+    }
+
+    /// Geographical position of the point on the screen.
+    pub fn unproject(&self, screen_position: Pos2) -> Position {
+        screen_to_position(
+            &(self.map_center.project(self.zoom) + (screen_position - self.clip_rect.center())),
+            self.zoom,
+        )
+    }
+
+    /// How many screen pixels does one meter take around the `position`.
+    pub fn pixels_per_meter(&self, position: Position) -> f32 {
+        (1. / ground_resolution(position.y(), self.zoom)) as f32
+    }
+
+    /// Zoom level the map is drawn with.
+    pub fn zoom(&self) -> u8 {
+        self.zoom
+    }
+
+    /// Area of the screen taken by the map.
+    pub fn clip_rect(&self) -> Rect {
+        self.clip_rect
+    }
+
+    /// Opacity plugins should be drawn with. It's always `1.`, unless the map was told otherwise
+    /// with [`Map::with_opacity_for_plugins`].
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+}
+
 /// Position at the map's center. Initially, the map follows `my_position` argument which typically
 /// is meant to be fed by a GPS sensor or other geo-localization method. If user drags the map,
 /// it becomes "detached" and stays this way until [`MapMemory::center_mode`] is changed back to
@@ -109,14 +1003,13 @@ pub enum Center {
 }
 
 impl Center {
-    fn drag(&mut self, response: &Response, my_position: Position, zoom: u8) {
-        if response.dragged_by(egui::PointerButton::Primary) {
-            // We always end up in some exact, "detached" position, regardless of the current mode.
-            *self = Center::Exact(screen_to_position(
-                &(self.position(my_position).project(zoom) - response.drag_delta()),
-                zoom,
-            ));
-        }
+    /// Move the center by given amount of screen pixels.
+    fn shift(&mut self, delta: Vec2, my_position: Position, zoom: u8) {
+        // We always end up in some exact, "detached" position, regardless of the current mode.
+        *self = Center::Exact(screen_to_position(
+            &(self.position(my_position).project(zoom) + delta),
+            zoom,
+        ));
     }
 
     /// Get the real position at the map's center.
@@ -133,49 +1026,1339 @@ impl Center {
 pub struct MapMemory {
     pub center_mode: Center,
     pub zoom: Zoom,
-}
 
-fn draw_tiles(
-    painter: &Painter,
-    tile_id: TileId,
-    map_center_projected_position: Pos2,
-    tiles: &mut Tiles,
-    ui: &mut Ui,
-    meshes: &mut HashMap<TileId, Mesh>,
-) {
-    let tile_projected = tile_id.project();
-    let tile_screen_position = painter.clip_rect().center().to_vec2() + tile_projected.to_vec2()
-        - map_center_projected_position.to_vec2();
+    /// `my_position` the map was most recently drawn with.
+    my_position: Option<Position>,
 
-    let Some(image) = tiles.at(tile_id) else {
-        return;
-    };
+    /// Makes the map glide when `my_position` changes.
+    my_position_transition: Option<MarkerTransitions<()>>,
 
-    if painter
-        .clip_rect()
-        .intersects(image.rect(tile_screen_position))
-    {
-        if let Entry::Vacant(vacant) = meshes.entry(tile_id) {
-            vacant.insert(image.mesh(tile_screen_position, ui.ctx()));
-
-            for coordinates in [
-                tile_id.north(),
-                tile_id.east(),
-                tile_id.south(),
-                tile_id.west(),
-            ]
-            .iter()
-            .flatten()
-            {
-                draw_tiles(
-                    painter,
-                    *coordinates,
-                    map_center_projected_position,
-                    tiles,
-                    ui,
-                    meshes,
-                );
-            }
-        }
+    /// Rectangle being currently selected for the box zoom, in screen coordinates.
+    box_zoom: Option<Rect>,
+
+    /// Area selected with the box zoom in the most recent frame.
+    selection: Option<BoundingBox>,
+
+    /// How much the user moved the map in the most recent frame.
+    pan_delta: Vec2,
+
+    /// Dragging which did not move the map yet, see [`Map::with_min_drag_distance`].
+    pending_drag: Vec2,
+    zoom_delta: f32,
+
+    /// Speed of the zoom inertia, in levels per second.
+    zoom_velocity: f32,
+
+    /// Whether the map was clicked, releasing the guard, see [`Map::with_interaction_guard`].
+    interacting: bool,
+
+    /// Time and place of the most recent tap, and where the double-tap zoom started, if it's in
+    /// progress. See [`Map::with_double_tap_zoom`].
+    last_tap: Option<(f64, Pos2)>,
+    double_tap_anchor: Option<Pos2>,
+
+    /// View which [`MapMemory::go_home`] returns to.
+    home: (Center, Zoom),
+    flight: Option<Flight>,
+
+    /// See [`MapMemory::set_flight_animation`].
+    flight_animation: Option<(f64, Easing)>,
+
+    /// See [`MapMemory::set_auto_recenter`].
+    auto_recenter: Option<f64>,
+    last_interaction: Option<f64>,
+}
+
+/// Animation started by [`MapMemory::go_home`], [`MapMemory::follow_my_position`], or the
+/// auto-recenter.
+#[derive(Clone)]
+struct Flight {
+    /// Center, zoom and time at which the flight started. Known once the map gets drawn.
+    start: Option<(Position, f32, f64)>,
+    destination: (Center, Zoom),
+
+    /// Duration (in seconds) and easing of the flight.
+    animation: (f64, Easing),
+}
+
+impl MapMemory {
+    /// Memory of a map which starts centered at `center`, detached from `my_position`, with
+    /// given `zoom` level.
+    ///
+    /// ```
+    /// # use walkers::{MapMemory, Position};
+    /// let memory = MapMemory::with_view(Position::new(17.03664, 51.09916), 12.).unwrap();
+    /// assert_eq!(12, memory.zoom.round());
+    /// ```
+    pub fn with_view(center: Position, zoom: f32) -> Result<Self, InvalidZoom> {
+        let zoom = Zoom::try_from(zoom)?;
+        Ok(Self {
+            center_mode: Center::Exact(center),
+            zoom,
+            my_position: None,
+            my_position_transition: None,
+            box_zoom: None,
+            selection: None,
+            pan_delta: Vec2::ZERO,
+            pending_drag: Vec2::ZERO,
+            zoom_delta: 0.,
+            zoom_velocity: 0.,
+            interacting: false,
+            last_tap: None,
+            double_tap_anchor: None,
+            home: (Center::Exact(center), zoom),
+            flight: None,
+            flight_animation: None,
+            auto_recenter: None,
+            last_interaction: None,
+        })
+    }
+
+    /// Current view in the form used by the OpenStreetMap in its URLs, e.g.
+    /// `map=14/51.0992/17.0366`, to be put after the `#`. Number of fractional digits grows with
+    /// the zoom, so it's precise enough to restore the view with [`MapMemory::from_hash_fragment`].
+    /// `None` if the map follows `my_position`, but was not drawn yet.
+    pub fn to_hash_fragment(&self) -> Option<String> {
+        let position = match self.center_mode {
+            Center::Exact(position) => position,
+            Center::MyPosition => self.my_position?,
+        };
+        let zoom = self.zoom.round();
+        let precision = (zoom as f32).log2().ceil().max(0.) as usize;
+        Some(format!(
+            "map={zoom}/{:.precision$}/{:.precision$}",
+            position.latitude(),
+            position.longitude()
+        ))
+    }
+
+    /// Memory of a map showing the view given by a URL fragment in the form of
+    /// `map=<zoom>/<latitude>/<longitude>`, with or without the leading `#`. `None` if it's
+    /// malformed.
+    ///
+    /// ```
+    /// # use walkers::MapMemory;
+    /// let memory = MapMemory::from_hash_fragment("#map=14/51.0992/17.0366").unwrap();
+    /// assert_eq!(14, memory.zoom.round());
+    /// assert_eq!("map=14/51.0992/17.0366", memory.to_hash_fragment().unwrap());
+    /// ```
+    pub fn from_hash_fragment(fragment: &str) -> Option<Self> {
+        let view = fragment.trim_start_matches('#').strip_prefix("map=")?;
+        let mut parts = view.split('/').map(str::parse::<f64>);
+        let (Some(Ok(zoom)), Some(Ok(latitude)), Some(Ok(longitude)), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return None;
+        };
+
+        if !(-90. ..=90.).contains(&latitude) || !(-180. ..=180.).contains(&longitude) {
+            return None;
+        }
+
+        Self::with_view(Position::from_lat_lon(latitude, longitude), zoom as f32).ok()
+    }
+
+    /// Set the view which [`MapMemory::go_home`] returns to. By default, it's the view the map
+    /// started with.
+    pub fn set_home(&mut self, center: Center, zoom: Zoom) {
+        self.home = (center, zoom);
+    }
+
+    /// Smoothly move the map back to the home view, see [`MapMemory::set_home`]. Panning or
+    /// zooming the map stops it half-way. It's animated like the other flights, see
+    /// [`MapMemory::set_flight_animation`].
+    pub fn go_home(&mut self) {
+        let (duration, easing) = self.flight_animation();
+        self.go_home_with(duration, easing);
+    }
+
+    /// Like [`MapMemory::go_home`], but taking `duration` seconds, with given `easing`.
+    pub fn go_home_with(&mut self, duration: f64, easing: Easing) {
+        self.flight = Some(Flight {
+            start: None,
+            destination: self.home.clone(),
+            animation: (duration, easing),
+        });
+    }
+
+    /// Smoothly move the map back to `my_position`, keeping the zoom, and follow it from there on.
+    /// Panning or zooming the map stops it half-way. To snap there instantly instead, set the
+    /// [`MapMemory::center_mode`] to [`Center::MyPosition`]. It's animated like the other
+    /// flights, see [`MapMemory::set_flight_animation`].
+    pub fn follow_my_position(&mut self) {
+        let (duration, easing) = self.flight_animation();
+        self.follow_my_position_with(duration, easing);
+    }
+
+    /// Like [`MapMemory::follow_my_position`], but taking `duration` seconds, with given
+    /// `easing`.
+    pub fn follow_my_position_with(&mut self, duration: f64, easing: Easing) {
+        if self.center_mode == Center::MyPosition {
+            return;
+        }
+        self.flight = Some(Flight {
+            start: None,
+            destination: (Center::MyPosition, self.zoom),
+            animation: (duration, easing),
+        });
+    }
+
+    /// How long (in seconds) and with what easing the map flies to its destination, e.g. after
+    /// [`MapMemory::go_home`] or [`MapMemory::follow_my_position`]. By default it takes half a
+    /// second, with [`Easing::EaseInOut`]. Use `0.` to make it jump right away. Flights already
+    /// started keep their animation.
+    pub fn set_flight_animation(&mut self, duration: f64, easing: Easing) {
+        self.flight_animation = Some((duration, easing));
+    }
+
+    fn flight_animation(&self) -> (f64, Easing) {
+        self.flight_animation
+            .unwrap_or((FLIGHT_ANIMATION, Easing::EaseInOut))
+    }
+
+    /// After the user pans or zooms the map, detaching it from `my_position`, smoothly move it
+    /// back to [`Center::MyPosition`] once the map is left alone for `timeout` seconds, like the
+    /// navigation apps do. `None` (the default) keeps the map where the user left it.
+    pub fn set_auto_recenter(&mut self, timeout: Option<f64>) {
+        self.auto_recenter = timeout;
+    }
+
+    /// Center the map at the `bounds` and pick the highest zoom level at which they fit into a
+    /// map of given `size`.
+    pub fn fit_bounds(&mut self, bounds: BoundingBox, size: Vec2) {
+        let north_west = Position::new(bounds.west, bounds.north);
+        let south_east = Position::new(bounds.east, bounds.south);
+
+        let zoom = (0..=MAX_ZOOM)
+            .rev()
+            .find(|&zoom| {
+                let extent = south_east.project(zoom) - north_west.project(zoom);
+                extent.x <= size.x && extent.y <= size.y
+            })
+            .unwrap_or(0);
+
+        let center =
+            north_west.project(zoom) + (south_east.project(zoom) - north_west.project(zoom)) / 2.;
+        self.center_mode = Center::Exact(screen_to_position(&center, zoom));
+        if let Ok(zoom) = Zoom::try_from(zoom as f32) {
+            self.zoom = zoom;
+        }
+    }
+
+    /// Area the user selected by dragging with `Shift` held (also known as box zoom). It's
+    /// available only in the frame in which the mouse button got released, which is also when
+    /// the map zooms into it.
+    pub fn selection(&self) -> Option<BoundingBox> {
+        self.selection
+    }
+
+    /// Position at the map's center. `None` if the map follows `my_position`, but it was not
+    /// drawn yet.
+    pub(crate) fn center(&self) -> Option<Position> {
+        match self.center_mode {
+            Center::MyPosition => self.my_position,
+            Center::Exact(position) => Some(position),
+        }
+    }
+
+    /// Projector for a map occupying the `viewport` (typically the `rect` of the map's
+    /// [`Response`]), e.g. to hit test the overlays after the map was drawn. `None` if the map
+    /// follows `my_position`, but it was not drawn yet.
+    pub fn projector(&self, viewport: Rect) -> Option<Projector> {
+        Some(Projector {
+            clip_rect: viewport,
+            map_center: self.center()?,
+            zoom: self.zoom.round(),
+            opacity: 1.,
+        })
+    }
+
+    /// Screen coordinates of the `position`, on a map occupying the `viewport` (typically the
+    /// `rect` of the map's [`Response`]). `None` if it's not visible.
+    pub fn project_to_screen(&self, position: Position, viewport: Rect) -> Option<Pos2> {
+        let screen_position = self.projector(viewport)?.project(position).to_pos2();
+        viewport
+            .contains(screen_position)
+            .then_some(screen_position)
+    }
+
+    /// How much the user moved the map's center in the most recent frame, in screen pixels
+    /// (positive `x` is to the east). Apply it to another map with [`MapMemory::pan_by`] to keep
+    /// them in sync.
+    pub fn pan_delta(&self) -> Vec2 {
+        self.pan_delta
+    }
+
+    /// How much the user zoomed the map in the most recent frame (positive when zooming in).
+    /// Apply it to another map with [`Zoom::zoom_by`] to keep them in sync.
+    pub fn zoom_delta(&self) -> f32 {
+        self.zoom_delta
+    }
+
+    /// Move the map's center by `delta` screen pixels, detaching it from `my_position`. Does
+    /// nothing if the map follows `my_position`, but it was not drawn yet.
+    pub fn pan_by(&mut self, delta: Vec2) {
+        if let Some(center) = self.center() {
+            self.center_mode.shift(delta, center, self.zoom.round());
+        }
+    }
+
+    /// Geographical area visible on a map occupying the `viewport`. `None` if the map follows
+    /// `my_position`, but it was not drawn yet.
+    pub fn visible_bounds(&self, viewport: Rect) -> Option<BoundingBox> {
+        let projector = self.projector(viewport)?;
+        Some(BoundingBox::from_corners(
+            projector.unproject(viewport.min),
+            projector.unproject(viewport.max),
+        ))
+    }
+
+    /// Positions of the north-west, north-east, south-east and south-west corners (in that order)
+    /// of a map occupying the `viewport`. As the map is not rotated, these are also the corners
+    /// of the [`MapMemory::visible_bounds`]. `None` if the map follows `my_position`, but it was
+    /// not drawn yet.
+    pub fn viewport_corners(&self, viewport: Rect) -> Option<[Position; 4]> {
+        let projector = self.projector(viewport)?;
+        Some(
+            [
+                viewport.left_top(),
+                viewport.right_top(),
+                viewport.right_bottom(),
+                viewport.left_bottom(),
+            ]
+            .map(|corner| projector.unproject(corner)),
+        )
+    }
+
+    /// Tiles needed to draw a map occupying the `viewport`. Empty if the map follows
+    /// `my_position`, but it was not drawn yet.
+    pub fn tiles_in_view(&self, viewport: Rect) -> Vec<TileId> {
+        self.visible_bounds(viewport)
+            .map(|bounds| bounds.tile_ids(self.zoom.round()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether the `position` is visible on a map occupying the `viewport`. Cheap enough to be
+    /// used for skipping the work related to positions which are off the screen.
+    pub fn is_visible(&self, position: Position, viewport: Rect) -> bool {
+        self.project_to_screen(position, viewport).is_some()
+    }
+}
+
+/// Largest rectangle of given `aspect_ratio` fitting in the middle of the `rect`.
+fn letterbox(rect: Rect, aspect_ratio: f32) -> Rect {
+    let size = if rect.width() > rect.height() * aspect_ratio {
+        vec2(rect.height() * aspect_ratio, rect.height())
+    } else {
+        vec2(rect.width(), rect.width() / aspect_ratio)
+    };
+    Rect::from_center_size(rect.center(), size)
+}
+
+/// Whether `rect` has a finite, non-zero size.
+fn has_area(rect: Rect) -> bool {
+    let size = rect.size();
+    size.x.is_finite() && size.y.is_finite() && size.x > 0. && size.y > 0.
+}
+
+/// Mesh covering the part of the `rect` which is outside of the `polygon`, made of horizontal
+/// strips, as egui can only clip to rectangles.
+fn outside_of_polygon(polygon: &[Pos2], rect: Rect, color: Color32) -> Mesh {
+    let mut mesh = Mesh::default();
+    let mut y = rect.top();
+    while y < rect.bottom() {
+        let bottom = (y + MASK_STRIP).min(rect.bottom());
+        let mut left = rect.left();
+        for (start, end) in inside_of_polygon(polygon, (y + bottom) / 2.) {
+            let (start, end) = (start.max(rect.left()), end.min(rect.right()));
+            if start > left {
+                mesh.add_colored_rect(Rect::from_x_y_ranges(left..=start, y..=bottom), color);
+            }
+            left = left.max(end);
+        }
+        if left < rect.right() {
+            mesh.add_colored_rect(
+                Rect::from_x_y_ranges(left..=rect.right(), y..=bottom),
+                color,
+            );
+        }
+        y = bottom;
+    }
+    mesh
+}
+
+/// Parts of the horizontal line at `y` which are inside of the `polygon`, using the even-odd
+/// rule, from left to right.
+fn inside_of_polygon(polygon: &[Pos2], y: f32) -> Vec<(f32, f32)> {
+    let mut crossings: Vec<f32> = polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .filter(|(a, b)| (a.y <= y) != (b.y <= y))
+        .map(|(a, b)| a.x + (y - a.y) / (b.y - a.y) * (b.x - a.x))
+        .collect();
+    crossings.sort_by(f32::total_cmp);
+    crossings
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
+}
+
+/// Draw the tile and, recursively, its neighbours, as long as they are visible. Tiles which are
+/// not there yet are replaced with a part of the nearest cached tile of a lower zoom level, so
+/// that there are no blank areas when e.g. the map gets resized.
+fn draw_tiles(
+    painter: &Painter,
+    tile_id: TileId,
+    map_center_projected_position: Pos2,
+    tiles: &mut Tiles,
+    download: bool,
+    ui: &mut Ui,
+    meshes: &mut HashMap<TileId, Mesh>,
+) {
+    let rect = tile_rect(tile_id, map_center_projected_position, painter.clip_rect());
+    let tile_screen_position = rect.min.to_vec2();
+
+    if !tile_id.is_valid() || !painter.clip_rect().intersects(rect) {
+        return;
+    }
+
+    let Entry::Vacant(vacant) = meshes.entry(tile_id) else {
+        return;
+    };
+
+    let tile = if download {
+        tiles.at(tile_id)
+    } else {
+        tiles.cached(tile_id).cloned()
+    };
+    let mesh = match tile {
+        Some(image) => image.mesh(tile_screen_position, ui.ctx()),
+        None => placeholder(tile_id, rect, tiles, ui.ctx()).unwrap_or_default(),
+    };
+    vacant.insert(mesh);
+
+    for coordinates in [
+        tile_id.north(),
+        tile_id.east(),
+        tile_id.south(),
+        tile_id.west(),
+    ]
+    .iter()
+    .flatten()
+    {
+        draw_tiles(
+            painter,
+            *coordinates,
+            map_center_projected_position,
+            tiles,
+            download,
+            ui,
+            meshes,
+        );
+    }
+}
+
+/// Where the tile is drawn on a map occupying the `viewport`.
+fn tile_rect(tile_id: TileId, map_center_projected_position: Pos2, viewport: Rect) -> Rect {
+    let tile_screen_position = viewport.center().to_vec2() + tile_id.project().to_vec2()
+        - map_center_projected_position.to_vec2();
+    Rect::from_min_size(
+        tile_screen_position.to_pos2(),
+        Vec2::splat(TILE_SIZE as f32),
+    )
+}
+
+/// Part of the nearest cached ancestor of `tile_id`, covering its `rect`.
+fn placeholder(tile_id: TileId, rect: Rect, tiles: &Tiles, ctx: &egui::Context) -> Option<Mesh> {
+    let mut ancestor = tile_id;
+    loop {
+        ancestor = ancestor.parent()?;
+        if let Some(tile) = tiles.cached(ancestor) {
+            let scale = (1u32 << (tile_id.zoom - ancestor.zoom)) as f32;
+            let offset = egui::vec2(
+                (tile_id.x as f32 % scale) / scale,
+                (tile_id.y as f32 % scale) / scale,
+            );
+            let uv = Rect::from_min_size(offset.to_pos2(), Vec2::splat(1. / scale));
+            return Some(tile.mesh_with_uv(rect, uv, ctx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::testing::{counting_tiles, mesh_colors, meshes, pointer_button, MapHarness};
+    use crate::Tile;
+    use egui::{ColorImage, Event, Modifiers};
+    use egui_extras::RetainedImage;
+
+    fn wroclaw() -> Position {
+        Position::new(17.03664, 51.09916)
+    }
+
+    #[test]
+    fn projecting_positions_to_screen() {
+        let mut harness = MapHarness::new(wroclaw());
+        let viewport = Rect::from_min_size(Pos2::ZERO, harness.screen_size);
+
+        // Map was not drawn yet, so it does not know where it is.
+        assert_eq!(None, harness.memory.project_to_screen(wroclaw(), viewport));
+
+        let _ = harness.run(Vec::new());
+        assert_eq!(
+            Some(harness.screen_center()),
+            harness.memory.project_to_screen(wroclaw(), viewport)
+        );
+
+        let far_away = Position::new(0., 0.);
+        assert_eq!(None, harness.memory.project_to_screen(far_away, viewport));
+        assert!(!harness.memory.is_visible(far_away, viewport));
+        assert!(harness.memory.is_visible(wroclaw(), viewport));
+    }
+
+    #[test]
+    fn visible_tiles_are_loaded_after_first_frame() {
+        let mut harness = MapHarness::new(wroclaw());
+        let viewport = Rect::from_min_size(Pos2::ZERO, harness.screen_size);
+        assert_eq!(None, harness.memory.visible_bounds(viewport));
+
+        let _ = harness.run(Vec::new());
+        let bounds = harness.memory.visible_bounds(viewport).unwrap();
+        assert!(bounds.west < wroclaw().x() && wroclaw().x() < bounds.east);
+        assert!(bounds.south < wroclaw().y() && wroclaw().y() < bounds.north);
+        assert!(harness.tiles.is_loaded(bounds, harness.zoom_level()));
+
+        // Nothing was drawn one level below.
+        assert!(!harness.tiles.is_loaded(bounds, harness.zoom_level() - 1));
+
+        let tile_ids = harness.memory.tiles_in_view(viewport);
+        assert!(tile_ids.contains(&wroclaw().tile_id(harness.zoom_level())));
+        assert!(harness.tiles.all_visible_loaded(&tile_ids));
+    }
+
+    #[test]
+    fn viewport_corners() {
+        let mut harness = MapHarness::new(wroclaw());
+        let viewport = Rect::from_min_size(Pos2::ZERO, harness.screen_size);
+        assert_eq!(None, harness.memory.viewport_corners(viewport));
+
+        let _ = harness.run(Vec::new());
+        let [north_west, north_east, south_east, south_west] =
+            harness.memory.viewport_corners(viewport).unwrap();
+        let bounds = harness.memory.visible_bounds(viewport).unwrap();
+        assert_eq!(north_west, Position::new(bounds.west, bounds.north));
+        assert_eq!(north_east, Position::new(bounds.east, bounds.north));
+        assert_eq!(south_east, Position::new(bounds.east, bounds.south));
+        assert_eq!(south_west, Position::new(bounds.west, bounds.south));
+    }
+
+    #[test]
+    fn map_without_area_draws_nothing() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.screen_size = Vec2::ZERO;
+        let _ = harness.run(Vec::new());
+        assert!(harness.tiles.cache().is_empty());
+
+        harness.screen_size = Vec2::new(f32::NAN, 100.);
+        let _ = harness.run(Vec::new());
+        assert!(harness.tiles.cache().is_empty());
+    }
+
+    #[test]
+    fn fitting_bounds() {
+        let mut memory = MapMemory::default();
+        let world = crate::BoundingBox {
+            west: -180.,
+            south: -crate::MAX_LATITUDE,
+            east: 180.,
+            north: crate::MAX_LATITUDE,
+        };
+
+        // Whole world takes 512 pixels at zoom 1, and 1024 at zoom 2.
+        memory.fit_bounds(world, Vec2::new(800., 600.));
+        assert_eq!(1, memory.zoom.round());
+        let center = memory.center_mode.position(wroclaw());
+        approx::assert_abs_diff_eq!(0., center.x(), epsilon = 1e-6);
+        approx::assert_abs_diff_eq!(0., center.y(), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn view_is_restored_from_hash_fragment() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.drag(Vec2::new(100., 50.));
+        let fragment = harness.memory.to_hash_fragment().unwrap();
+
+        let memory = MapMemory::from_hash_fragment(&fragment).unwrap();
+        assert_eq!(memory.zoom.round(), harness.zoom_level());
+        let (restored, dragged) = (memory.center_mode.position(wroclaw()), harness.center());
+        approx::assert_relative_eq!(restored.x(), dragged.x(), epsilon = 1e-4);
+        approx::assert_relative_eq!(restored.y(), dragged.y(), epsilon = 1e-4);
+
+        assert!(MapMemory::from_hash_fragment("map=14/51.0992").is_none());
+        assert!(MapMemory::from_hash_fragment("map=14/91/17").is_none());
+        assert!(MapMemory::from_hash_fragment("zoom=14/51.0992/17.0366").is_none());
+    }
+
+    #[test]
+    fn deltas_applied_in_frame_can_be_synced_to_another_map() {
+        let mut harness = MapHarness::new(wroclaw());
+        let mut minimap = MapMemory::with_view(wroclaw(), 16.).unwrap();
+        let center = harness.screen_center();
+
+        for events in [
+            vec![Event::PointerMoved(center)],
+            vec![pointer_button(center, true)],
+            vec![Event::PointerMoved(center + Vec2::new(50., 0.))],
+            vec![pointer_button(center + Vec2::new(50., 0.), false)],
+        ] {
+            let _ = harness.run(events);
+            minimap.pan_by(harness.memory.pan_delta());
+        }
+        assert_eq!(harness.center(), minimap.center_mode.position(wroclaw()));
+
+        harness.zoom(0.5);
+        assert_eq!(-1., harness.memory.zoom_delta());
+
+        // Only the most recent frame counts.
+        let _ = harness.run(Vec::new());
+        assert_eq!(Vec2::ZERO, harness.memory.pan_delta());
+        assert_eq!(0., harness.memory.zoom_delta());
+    }
+
+    #[test]
+    fn cursor_shows_that_map_can_be_grabbed() {
+        let mut harness = MapHarness::new(wroclaw());
+        let center = harness.screen_center();
+
+        let output = harness.run(vec![Event::PointerMoved(center)]);
+        assert_eq!(CursorIcon::Grab, output.platform_output.cursor_icon);
+
+        let _ = harness.run(vec![pointer_button(center, true)]);
+        let output = harness.run(vec![Event::PointerMoved(center + Vec2::new(50., 0.))]);
+        assert_eq!(CursorIcon::Grabbing, output.platform_output.cursor_icon);
+    }
+
+    #[test]
+    fn map_which_does_not_sense_dragging_is_static() {
+        let mut harness = MapHarness::new(wroclaw());
+        let center = harness.screen_center();
+
+        for events in [
+            vec![Event::PointerMoved(center)],
+            vec![pointer_button(center, true)],
+            vec![Event::PointerMoved(center + Vec2::new(50., 0.))],
+            vec![Event::Zoom(2.)],
+        ] {
+            let _ = harness.run_with(events, |map| map.with_sense(egui::Sense::hover()));
+        }
+
+        assert!(harness.memory.center_mode == Center::MyPosition);
+        assert_eq!(16, harness.zoom_level());
+    }
+
+    #[test]
+    fn map_with_zoom_disabled_still_pans() {
+        let mut harness = MapHarness::new(wroclaw());
+        let center = harness.screen_center();
+
+        for events in [
+            vec![Event::PointerMoved(center)],
+            vec![Event::Zoom(2.)],
+            vec![pointer_button(center, true)],
+            vec![Event::PointerMoved(center + Vec2::new(50., 0.))],
+            vec![pointer_button(center + Vec2::new(50., 0.), false)],
+        ] {
+            let _ = harness.run_with(events, |map| map.zoom_enabled(false));
+        }
+
+        assert_eq!(16, harness.zoom_level());
+        assert!(matches!(harness.memory.center_mode, Center::Exact(_)));
+    }
+
+    #[test]
+    fn guarded_map_needs_to_be_clicked_first() {
+        let mut harness = MapHarness::new(wroclaw());
+        let center = harness.screen_center();
+        let drag = |harness: &mut MapHarness| {
+            let to = center + Vec2::new(50., 0.);
+            for events in [
+                vec![Event::PointerMoved(center)],
+                vec![pointer_button(center, true)],
+                vec![Event::PointerMoved(to)],
+                vec![pointer_button(to, false)],
+            ] {
+                let _ = harness.run_with(events, |map| map.with_interaction_guard(true));
+            }
+        };
+
+        drag(&mut harness);
+        assert!(harness.memory.center_mode == Center::MyPosition);
+
+        for events in [
+            vec![pointer_button(center, true)],
+            vec![pointer_button(center, false)],
+        ] {
+            let _ = harness.run_with(events, |map| map.with_interaction_guard(true));
+        }
+
+        drag(&mut harness);
+        assert!(matches!(harness.memory.center_mode, Center::Exact(_)));
+    }
+
+    #[test]
+    fn map_keeps_following_my_position_when_barely_dragged() {
+        let mut harness = MapHarness::new(wroclaw());
+        let _ = harness.run(Vec::new());
+
+        let from = harness.screen_center();
+        let drag = |harness: &mut MapHarness, events| {
+            let _ = harness.run_with(events, |map| map.with_min_drag_distance(20.));
+        };
+        drag(&mut harness, vec![Event::PointerMoved(from)]);
+        drag(&mut harness, vec![pointer_button(from, true)]);
+        drag(
+            &mut harness,
+            vec![Event::PointerMoved(from + Vec2::new(8., 8.))],
+        );
+        assert!(harness.memory.center_mode == Center::MyPosition);
+        assert_eq!(harness.center(), wroclaw());
+
+        // Past the threshold, the map catches up with the pointer.
+        let to = from + Vec2::new(30., 0.);
+        drag(&mut harness, vec![Event::PointerMoved(to)]);
+        drag(&mut harness, vec![pointer_button(to, false)]);
+        assert!(matches!(harness.memory.center_mode, Center::Exact(_)));
+        let viewport = Rect::from_min_size(Pos2::ZERO, harness.screen_size);
+        assert_eq!(
+            harness.memory.project_to_screen(wroclaw(), viewport),
+            Some(to)
+        );
+    }
+
+    #[test]
+    fn box_zoom_selects_area_and_zooms_into_it() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.modifiers = Modifiers::SHIFT;
+        harness.drag(Vec2::new(90., 60.));
+
+        let selection = harness.memory.selection().expect("area should be selected");
+        // Dragging started in the middle of the map, so that's the north-west corner.
+        approx::assert_abs_diff_eq!(wroclaw().x(), selection.west, epsilon = 1e-4);
+        approx::assert_abs_diff_eq!(wroclaw().y(), selection.north, epsilon = 1e-4);
+        assert!(selection.east > selection.west);
+        assert!(selection.south < selection.north);
+
+        // Selected area is a bit less than 8 times smaller than the screen, and each zoom level
+        // doubles it.
+        assert_eq!(19, harness.zoom_level());
+
+        // Selection is gone in the next frame.
+        let _ = harness.run(Vec::new());
+        assert_eq!(None, harness.memory.selection());
+    }
+
+    #[test]
+    fn box_zoom_ignores_clicks() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.modifiers = Modifiers::SHIFT;
+        harness.drag(Vec2::ZERO);
+        harness.drag(Vec2::new(2., 1.));
+
+        assert_eq!(None, harness.memory.selection());
+        assert_eq!(16, harness.zoom_level());
+    }
+
+    #[test]
+    fn double_tap_and_drag_zooms() {
+        let mut harness = MapHarness::new(wroclaw());
+        let start = harness.memory.zoom.value();
+        let tap = harness.screen_center();
+        let drag = |harness: &mut MapHarness| {
+            for event in [
+                Event::PointerMoved(tap),
+                pointer_button(tap, true),
+                pointer_button(tap, false),
+                pointer_button(tap, true),
+                Event::PointerMoved(tap + Vec2::new(0., 50.)),
+                Event::PointerMoved(tap + Vec2::new(0., 100.)),
+                pointer_button(tap + Vec2::new(0., 100.), false),
+            ] {
+                let _ = harness.run_with(vec![event], |map| map.with_double_tap_zoom(true));
+            }
+        };
+
+        drag(&mut harness);
+        approx::assert_relative_eq!(harness.memory.zoom.value(), start + 1.);
+
+        // Zoom is anchored at the tap, which is in the middle of the map, so it did not move
+        // (more than a pixel).
+        approx::assert_relative_eq!(harness.center().x(), wroclaw().x(), epsilon = 1e-5);
+        approx::assert_relative_eq!(harness.center().y(), wroclaw().y(), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn double_tap_and_drag_pans_by_default() {
+        let mut harness = MapHarness::new(wroclaw());
+        let start = harness.memory.zoom.value();
+        let tap = harness.screen_center();
+
+        for event in [
+            Event::PointerMoved(tap),
+            pointer_button(tap, true),
+            pointer_button(tap, false),
+            pointer_button(tap, true),
+            Event::PointerMoved(tap + Vec2::new(0., 100.)),
+        ] {
+            let _ = harness.run(vec![event]);
+        }
+
+        assert_eq!(harness.memory.zoom.value(), start);
+        assert!(harness.center().y() > wroclaw().y());
+    }
+
+    #[test]
+    fn zoom_glides_to_a_stop_with_inertia() {
+        let mut harness = MapHarness::new(wroclaw());
+        let center = harness.screen_center();
+
+        let _ = harness.run_with(vec![Event::PointerMoved(center)], |map| {
+            map.with_zoom_inertia(0.2)
+        });
+        let _ = harness.run_with(vec![Event::Zoom(1.05)], |map| map.with_zoom_inertia(0.2));
+        let after_gesture = harness.memory.zoom.value();
+        approx::assert_relative_eq!(16.1, after_gesture);
+
+        for _ in 0..60 {
+            let _ = harness.run_with(Vec::new(), |map| map.with_zoom_inertia(0.2));
+        }
+        let after_glide = harness.memory.zoom.value();
+
+        // Slightly, and less than the gesture itself, which was just a single step.
+        let overshoot = after_glide - after_gesture;
+        assert!(overshoot > 0.01);
+        assert!(overshoot < 0.1);
+
+        // It stopped eventually, and kept following my position.
+        let _ = harness.run_with(Vec::new(), |map| map.with_zoom_inertia(0.2));
+        approx::assert_relative_eq!(after_glide, harness.memory.zoom.value(), epsilon = 0.01);
+        assert!(harness.memory.center_mode == Center::MyPosition);
+    }
+
+    #[test]
+    fn panning_stops_the_zoom_inertia() {
+        let mut harness = MapHarness::new(wroclaw());
+        let center = harness.screen_center();
+        let run = |harness: &mut MapHarness, events| {
+            let _ = harness.run_with(events, |map| map.with_zoom_inertia(0.2));
+        };
+
+        run(&mut harness, vec![Event::PointerMoved(center)]);
+        for _ in 0..10 {
+            run(&mut harness, vec![Event::Zoom(1.05)]);
+        }
+        run(&mut harness, vec![pointer_button(center, true)]);
+        run(
+            &mut harness,
+            vec![Event::PointerMoved(center + Vec2::new(50., 0.))],
+        );
+
+        let panned = harness.memory.zoom.value();
+        for _ in 0..30 {
+            run(&mut harness, Vec::new());
+        }
+        assert_eq!(panned, harness.memory.zoom.value());
+    }
+
+    #[test]
+    fn map_glides_to_new_my_position() {
+        let mut harness = MapHarness::new(wroclaw());
+        let viewport = Rect::from_min_size(Pos2::ZERO, harness.screen_size);
+        let _ = harness.run(Vec::new());
+
+        let new_position = Position::new(17.04, 51.1);
+        harness.my_position = new_position;
+        let _ = harness.run(Vec::new());
+
+        // It just started moving.
+        assert_ne!(
+            Some(harness.screen_center()),
+            harness.memory.project_to_screen(new_position, viewport)
+        );
+
+        // Each frame takes 1/60 of a second, so it's there after a second.
+        for _ in 0..60 {
+            let _ = harness.run(Vec::new());
+        }
+        assert_eq!(
+            Some(harness.screen_center()),
+            harness.memory.project_to_screen(new_position, viewport)
+        );
+    }
+
+    #[test]
+    fn sub_pixel_position_updates_do_not_repaint() {
+        let mut harness = MapHarness::new(wroclaw());
+        let _ = harness.run(Vec::new());
+        let _ = harness.run(Vec::new());
+
+        // Less than a pixel at the default zoom level.
+        harness.my_position = Position::new(wroclaw().x() + 0.000001, wroclaw().y());
+        let output = harness.run(Vec::new());
+        assert_ne!(std::time::Duration::ZERO, output.repaint_after);
+        assert_eq!(Some(wroclaw()), harness.memory.center());
+
+        // These add up, though.
+        harness.my_position = Position::new(wroclaw().x() + 0.0001, wroclaw().y());
+        let output = harness.run(Vec::new());
+        assert_eq!(std::time::Duration::ZERO, output.repaint_after);
+    }
+
+    #[test]
+    fn map_flies_back_home() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.drag(Vec2::new(100., 50.));
+        harness.zoom(2.);
+        assert!(matches!(harness.memory.center_mode, Center::Exact(_)));
+
+        harness.memory.go_home();
+        let _ = harness.run(Vec::new());
+        let _ = harness.run(Vec::new());
+
+        // Still on its way.
+        assert!(matches!(harness.memory.center_mode, Center::Exact(_)));
+
+        for _ in 0..30 {
+            let _ = harness.run(Vec::new());
+        }
+        assert!(harness.memory.center_mode == Center::MyPosition);
+        assert_eq!(harness.memory.zoom.value(), Zoom::default().value());
+    }
+
+    #[test]
+    fn flight_animation_is_configurable() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.drag(Vec2::new(100., 50.));
+        let from = harness.center();
+
+        // Frames are 1/60 s apart, so the linear flight is a tenth of the way there after the
+        // first one.
+        harness
+            .memory
+            .set_flight_animation(10. / 60., Easing::Linear);
+        harness.memory.go_home();
+        let _ = harness.run(Vec::new());
+        let _ = harness.run(Vec::new());
+        approx::assert_relative_eq!(
+            from.x() + (wroclaw().x() - from.x()) / 10.,
+            harness.center().x(),
+            epsilon = 1e-6
+        );
+
+        // Zero duration makes it jump right away.
+        harness.drag(Vec2::new(100., 50.));
+        harness.memory.set_flight_animation(0., Easing::Linear);
+        harness.memory.go_home();
+        let _ = harness.run(Vec::new());
+        assert!(harness.memory.center_mode == Center::MyPosition);
+    }
+
+    #[test]
+    fn flying_home_with_own_animation() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.drag(Vec2::new(100., 50.));
+        harness.zoom(2.);
+
+        harness.memory.go_home_with(0., Easing::Linear);
+        let _ = harness.run(Vec::new());
+        assert!(harness.memory.center_mode == Center::MyPosition);
+        assert_eq!(harness.memory.zoom.value(), Zoom::default().value());
+    }
+
+    #[test]
+    fn map_glides_back_to_my_position() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.drag(Vec2::new(100., 50.));
+        let zoom = harness.memory.zoom.value();
+
+        harness.memory.follow_my_position();
+        let _ = harness.run(Vec::new());
+        let _ = harness.run(Vec::new());
+
+        // Still on its way.
+        assert!(matches!(harness.memory.center_mode, Center::Exact(_)));
+        assert_ne!(harness.center(), wroclaw());
+
+        for _ in 0..30 {
+            let _ = harness.run(Vec::new());
+        }
+        assert!(harness.memory.center_mode == Center::MyPosition);
+        assert_eq!(harness.memory.zoom.value(), zoom);
+    }
+
+    #[test]
+    fn glide_back_to_my_position_is_configurable() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.drag(Vec2::new(100., 50.));
+
+        harness.memory.follow_my_position_with(0., Easing::Linear);
+        let _ = harness.run(Vec::new());
+        assert!(harness.memory.center_mode == Center::MyPosition);
+
+        // Auto-recenter goes through the same flight animation.
+        harness.memory.set_flight_animation(0., Easing::Linear);
+        harness.memory.set_auto_recenter(Some(0.5));
+        harness.drag(Vec2::new(100., 50.));
+        for _ in 0..31 {
+            let _ = harness.run(Vec::new());
+        }
+        assert!(harness.memory.center_mode == Center::MyPosition);
+    }
+
+    #[test]
+    fn map_recenters_after_being_left_alone() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.memory.set_auto_recenter(Some(1.));
+        harness.drag(Vec2::new(100., 50.));
+        let zoom = harness.memory.zoom.value();
+
+        // Frames are 1/60 s apart.
+        for _ in 0..50 {
+            let _ = harness.run(Vec::new());
+        }
+        assert!(matches!(harness.memory.center_mode, Center::Exact(_)));
+
+        for _ in 0..50 {
+            let _ = harness.run(Vec::new());
+        }
+        assert!(harness.memory.center_mode == Center::MyPosition);
+        assert_eq!(harness.memory.zoom.value(), zoom);
+    }
+
+    #[test]
+    fn layers_are_painted_in_fixed_order() {
+        use std::cell::RefCell;
+
+        struct Record<'a>(&'a RefCell<Vec<&'static str>>);
+
+        impl Plugin for Record<'_> {
+            fn draw(&mut self, _: &egui::Response, _: egui::Painter, _: &crate::Projector) {
+                self.0.borrow_mut().push("plugin");
+            }
+        }
+
+        let order = RefCell::new(Vec::new());
+        let mut harness = MapHarness::new(wroclaw());
+        let _ = harness.run_with(Vec::new(), |map| {
+            map.on_paint(PaintStage::AboveAttribution, |_, _| {
+                order.borrow_mut().push("above attribution")
+            })
+            .on_paint(PaintStage::AbovePlugins, |_, _| {
+                order.borrow_mut().push("above plugins")
+            })
+            .with_plugin(Record(&order))
+            .with_attribution(Attribution::new("OpenStreetMap contributors"))
+            .on_paint(PaintStage::AboveTiles, |_, _| {
+                order.borrow_mut().push("above tiles")
+            })
+        });
+
+        assert_eq!(
+            order.into_inner(),
+            [
+                "above tiles",
+                "plugin",
+                "above plugins",
+                "above attribution"
+            ]
+        );
+    }
+
+    #[test]
+    fn plugins_are_drawn_only_within_their_zoom_range() {
+        use crate::overlays::ZoomRange;
+        use std::cell::Cell;
+
+        struct Count<'a>(&'a Cell<usize>);
+
+        impl Plugin for Count<'_> {
+            fn draw(&mut self, _: &egui::Response, _: egui::Painter, _: &crate::Projector) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let draws = Cell::new(0);
+        let mut harness = MapHarness::new(wroclaw());
+
+        harness.memory.zoom = Zoom::try_from(10.).unwrap();
+        let _ = harness.run_with(Vec::new(), |map| {
+            map.with_plugin(ZoomRange::new(Count(&draws), 12..=18))
+        });
+        assert_eq!(draws.get(), 0);
+
+        harness.memory.zoom = Zoom::try_from(14.).unwrap();
+        let _ = harness.run_with(Vec::new(), |map| {
+            map.with_plugin(ZoomRange::new(Count(&draws), 12..=18))
+        });
+        assert_eq!(draws.get(), 1);
+    }
+
+    #[test]
+    fn plugins_can_be_clipped_differently_than_tiles() {
+        use std::cell::Cell;
+
+        struct ClipRect<'a>(&'a Cell<Rect>);
+
+        impl Plugin for ClipRect<'_> {
+            fn draw(&mut self, _: &egui::Response, painter: egui::Painter, _: &crate::Projector) {
+                self.0.set(painter.clip_rect());
+            }
+        }
+
+        let clip_rect = Cell::new(Rect::NOTHING);
+        let mut harness = MapHarness::new(wroclaw());
+        let _ = harness.run_with(Vec::new(), |map| {
+            map.with_plugins_clip_margin(-10.)
+                .with_plugin(ClipRect(&clip_rect))
+        });
+
+        assert_eq!(harness.map_rect().shrink(10.), clip_rect.get());
+    }
+
+    #[test]
+    fn map_keeps_its_aspect_ratio() {
+        let mut harness = MapHarness::new(wroclaw());
+        let mut clip_rect = Rect::NOTHING;
+        let _ = harness.run_with(Vec::new(), |map| {
+            map.with_aspect_ratio(1.)
+                .on_paint(PaintStage::AboveTiles, |_, projector| {
+                    clip_rect = projector.clip_rect();
+                })
+        });
+
+        assert_eq!(clip_rect.width(), clip_rect.height());
+        assert_eq!(clip_rect.center(), harness.screen_center());
+        assert!(clip_rect.width() < harness.screen_size.x);
+    }
+
+    #[test]
+    fn map_is_masked_outside_of_clip_polygon() {
+        let mut harness = MapHarness::new(wroclaw());
+        let polygon = vec![
+            Position::new(wroclaw().x() - 0.001, wroclaw().y() - 0.001),
+            Position::new(wroclaw().x() + 0.001, wroclaw().y() - 0.001),
+            Position::new(wroclaw().x(), wroclaw().y() + 0.001),
+        ];
+
+        let output = harness.run_with(Vec::new(), |map| {
+            map.with_clip_polygon(polygon, Color32::RED)
+        });
+
+        let mask: Vec<&egui::Mesh> = output
+            .shapes
+            .iter()
+            .filter_map(|clipped| match &clipped.1 {
+                egui::Shape::Mesh(mesh) if mesh.vertices[0].color == Color32::RED => Some(mesh),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(mask.len(), 1);
+
+        let covered = |position: Pos2| {
+            mask[0]
+                .vertices
+                .chunks_exact(4)
+                .any(|rect| Rect::from_two_pos(rect[0].pos, rect[3].pos).contains(position))
+        };
+
+        // Middle of the triangle is shown, corners of the map are not.
+        assert!(!covered(harness.screen_center()));
+        assert!(covered(Pos2::new(20., 20.)));
+        assert!(covered(Pos2::new(780., 580.)));
+    }
+
+    #[test]
+    fn tiles_are_tinted() {
+        let mut harness = MapHarness::new(wroclaw());
+
+        let output = harness.run_with(Vec::new(), |map| map.with_tint(Color32::RED));
+        let colors = mesh_colors(&output);
+        assert!(!colors.is_empty());
+        assert!(colors.iter().all(|color| *color == Color32::RED));
+
+        let output = harness.run_with(Vec::new(), |map| {
+            map.with_tint(Color32::RED)
+                .with_tile_tint(|tile_id| Color32::from_gray(tile_id.zoom))
+        });
+        assert!(mesh_colors(&output)
+            .iter()
+            .all(|color| *color == Color32::from_gray(16)));
+    }
+
+    #[test]
+    fn missing_tiles_are_replaced_by_lower_zoom_ones() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.tiles = Tiles::new(
+            // Downloading fails right away.
+            |_| String::new(),
+            harness.ctx.clone(),
+            |tile_id: &TileId| {
+                if tile_id.zoom == 15 {
+                    Ok(Tile::from_retained_image(RetainedImage::from_color_image(
+                        "zoom 15",
+                        ColorImage::new([256, 256], Color32::GRAY),
+                    )))
+                } else {
+                    Err(String::new())
+                }
+            },
+        );
+        harness.memory.zoom = Zoom::try_from(15.).unwrap();
+        let _ = harness.run(Vec::new());
+
+        // Zooming drops the tiles which are not needed anymore, but not their parents.
+        harness.zoom(1.5);
+        assert_eq!(16, harness.zoom_level());
+        let output = harness.run(Vec::new());
+
+        // 800x600 screen needs at least 4x3 tiles, each showing a quarter of its parent.
+        let meshes = meshes(&output);
+        assert!(meshes.len() >= 12);
+        for mesh in meshes {
+            assert_eq!(Vec2::splat(0.5), uv_size(mesh));
+        }
+    }
+
+    /// Part of the texture drawn by the `mesh`, `1.` for the whole tile.
+    fn uv_size(mesh: &Mesh) -> Vec2 {
+        Rect::from_points(&mesh.vertices.iter().map(|v| v.uv).collect::<Vec<_>>()).size()
+    }
+
+    #[test]
+    fn tiles_are_not_loaded_while_panning_fast() {
+        let mut harness = MapHarness::new(wroclaw());
+        let from = harness.screen_center();
+        let to = from + Vec2::new(300., 0.);
+        let run = |harness: &mut MapHarness, event| {
+            let _ = harness.run_with(vec![event], |map| map.with_fast_pan_speed(1000.));
+            harness.tiles.cache().len()
+        };
+
+        let _ = harness.run(Vec::new());
+        run(&mut harness, Event::PointerMoved(from));
+        let loaded = run(&mut harness, pointer_button(from, true));
+
+        // 300 pixels in 1/60 of a second.
+        assert_eq!(loaded, run(&mut harness, Event::PointerMoved(to)));
+
+        // Stopped, so the tiles which came into view get loaded.
+        assert!(loaded < run(&mut harness, Event::PointerMoved(to)));
+    }
+
+    #[test]
+    fn tiles_of_lower_zoom_are_drawn_while_panning_fast() {
+        let mut harness = MapHarness::new(wroclaw());
+        harness.memory.zoom = Zoom::try_from(15.).unwrap();
+        let _ = harness.run(Vec::new());
+        harness.zoom(1.5);
+
+        let from = harness.screen_center();
+        let to = from + Vec2::new(300., 0.);
+        let mut run = |event| harness.run_with(vec![event], |map| map.with_fast_pan_speed(1000.));
+        let _ = run(pointer_button(from, true));
+        let output = run(Event::PointerMoved(to));
+
+        // Tiles which came into view are not loaded, parts of their parents are drawn instead.
+        let meshes = meshes(&output);
+        assert!(meshes.iter().any(|mesh| uv_size(mesh) == Vec2::splat(0.5)));
+        assert!(meshes.iter().any(|mesh| uv_size(mesh) == Vec2::splat(1.)));
+    }
+
+    #[test]
+    fn tiles_far_away_are_dropped() {
+        let pan_away_and_back = |buffer| {
+            let mut harness = MapHarness::new(wroclaw());
+            let (tiles, rendered) = counting_tiles(harness.ctx.clone());
+            harness.tiles = tiles;
+
+            let from = harness.screen_center();
+            let mut run = |event| {
+                let _ = harness.run_with(vec![event], |map| map.with_tile_buffer(buffer));
+            };
+            run(Event::PointerMoved(from));
+            run(pointer_button(from, true));
+            for step in 1..=10 {
+                run(Event::PointerMoved(from - Vec2::new(30. * step as f32, 0.)));
+            }
+            let cached = harness.tiles.cache().len();
+
+            let before = rendered.load(Ordering::Relaxed);
+            let _ = harness.run_with(vec![Event::PointerMoved(from)], |map| {
+                map.with_tile_buffer(buffer)
+            });
+            (cached, rendered.load(Ordering::Relaxed) - before)
+        };
+
+        let (tight, tight_rendered_again) = pan_away_and_back(0.);
+        let (loose, loose_rendered_again) = pan_away_and_back(1000.);
+        assert!(tight < loose);
+        assert!(tight_rendered_again > 0);
+        assert_eq!(0, loose_rendered_again);
+    }
+
+    #[test]
+    fn warmed_tiles_are_kept_until_shown() {
+        let mut harness = MapHarness::new(wroclaw());
+        let (tiles, rendered) = counting_tiles(harness.ctx.clone());
+        harness.tiles = tiles;
+        let _ = harness.run_with(Vec::new(), |map| map.with_tile_buffer(0.));
+
+        let far_away = Position::new(wroclaw().x() + 1., wroclaw().y());
+        let before = rendered.load(Ordering::Relaxed);
+        harness.tiles.warm(
+            far_away,
+            harness.zoom_level(),
+            harness.screen_size + Vec2::splat(256.),
+        );
+        assert!(rendered.load(Ordering::Relaxed) > before);
+
+        // Tile buffer does not drop them, even though they are far away.
+        for _ in 0..3 {
+            let _ = harness.run_with(Vec::new(), |map| map.with_tile_buffer(0.));
+        }
+
+        let before = rendered.load(Ordering::Relaxed);
+        harness.memory.center_mode = Center::Exact(far_away);
+        let _ = harness.run_with(Vec::new(), |map| map.with_tile_buffer(0.));
+        assert_eq!(before, rendered.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn only_most_recently_warmed_tiles_are_kept() {
+        let mut harness = MapHarness::new(wroclaw());
+        let (tiles, rendered) = counting_tiles(harness.ctx.clone());
+        harness.tiles = tiles;
+        let _ = harness.run_with(Vec::new(), |map| map.with_tile_buffer(0.));
+
+        let east = Position::new(wroclaw().x() + 1., wroclaw().y());
+        let west = Position::new(wroclaw().x() - 1., wroclaw().y());
+        let size = harness.screen_size + Vec2::splat(256.);
+        harness.tiles.warm(east, harness.zoom_level(), size);
+        harness.tiles.warm(west, harness.zoom_level(), size);
+        let _ = harness.run_with(Vec::new(), |map| map.with_tile_buffer(0.));
+
+        // Tiles warmed before are dropped like any other ones far away...
+        let before = rendered.load(Ordering::Relaxed);
+        harness.memory.center_mode = Center::Exact(east);
+        let _ = harness.run_with(Vec::new(), |map| map.with_tile_buffer(0.));
+        assert!(rendered.load(Ordering::Relaxed) > before);
+
+        // ...but the most recent ones are still kept until shown.
+        let before = rendered.load(Ordering::Relaxed);
+        harness.memory.center_mode = Center::Exact(west);
+        let _ = harness.run_with(Vec::new(), |map| map.with_tile_buffer(0.));
+        assert_eq!(before, rendered.load(Ordering::Relaxed));
     }
 }
@@ -14,19 +14,82 @@ pub type Position = geo_types::Point;
 /// Location projected on the screen or an abstract bitmap.
 pub type Pixels = Pos2;
 
-use egui::Pos2;
+use crate::format::CoordinateFormat;
+use emath::Pos2;
 use std::f64::consts::PI;
 
+/// Latitude (in degrees) at which Web Mercator ends, beyond it projection goes to infinity.
+/// This is `atan(sinh(π))`, so that the whole world fits into a square.
+pub const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// Latitude in degrees, see [`PositionExt::from_degrees`].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Latitude(pub f64);
+
+/// Longitude in degrees, see [`PositionExt::from_degrees`].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Longitude(pub f64);
+
+/// Extra methods for the [`Position`]. Note that [`Position::new`] takes the longitude first,
+/// use [`PositionExt::from_lat_lon`] or [`PositionExt::from_degrees`] if that's confusing.
+/// Positions with latitude beyond ±90°, which usually means that the coordinates got swapped,
+/// trigger a debug assertion once they are projected.
 pub trait PositionExt {
-    /// Project geographical position into a 2D plane using Mercator.
+    /// Construct a position from its latitude and longitude, in that order.
+    fn from_lat_lon(latitude: f64, longitude: f64) -> Self
+    where
+        Self: Sized;
+
+    /// Like [`PositionExt::from_lat_lon`], but swapping the coordinates does not compile.
+    ///
+    /// ```
+    /// # use walkers::{Latitude, Longitude, Position, PositionExt};
+    /// let wroclaw = Position::from_degrees(Latitude(51.09916), Longitude(17.03664));
+    /// assert_eq!(wroclaw, Position::new(17.03664, 51.09916));
+    /// ```
+    fn from_degrees(latitude: Latitude, longitude: Longitude) -> Self
+    where
+        Self: Sized;
+
+    /// Latitude in degrees, same as `y()`.
+    fn latitude(&self) -> f64;
+
+    /// Longitude in degrees, same as `x()`.
+    fn longitude(&self) -> f64;
+
+    /// Project geographical position into a 2D plane using Mercator. Latitude is clamped to
+    /// [`MAX_LATITUDE`] first.
     fn project(&self, zoom: u8) -> Pixels;
 
     /// Tile this position is on.
     fn tile_id(&self, zoom: u8) -> TileId;
+
+    /// Position with latitude pinned to the range supported by Web Mercator, that is
+    /// `-MAX_LATITUDE..=MAX_LATITUDE`.
+    fn clamp_latitude(&self) -> Position;
+
+    /// Human readable representation of the position, e.g. `51.09916 17.03664`.
+    fn format(&self, format: CoordinateFormat) -> String;
+
+    /// Web Mercator coordinates ([EPSG:3857](https://epsg.io/3857)) in meters, `(x, y)`, as used
+    /// by many GIS tools. Latitude is clamped to [`MAX_LATITUDE`] first.
+    fn to_mercator_meters(&self) -> (f64, f64);
+
+    /// Inverse of the [`PositionExt::to_mercator_meters`].
+    fn from_mercator_meters(x: f64, y: f64) -> Self
+    where
+        Self: Sized;
+
+    /// Position a fraction `t` (`0.` to `1.`) of the way to `other`, along the great circle, that
+    /// is the shortest path on the globe. Interpolation is spherical, not linear in the
+    /// projection, so over long distances the path is curved on the map, and it crosses the
+    /// antimeridian when it's shorter that way. Antipodal positions, with infinitely many
+    /// shortest paths, are interpolated linearly in latitude and longitude.
+    fn lerp(&self, other: Position, t: f64) -> Position;
 }
 
 /// Size of the tiles used by the services like the OSM.
-const TILE_SIZE: u32 = 256;
+pub(crate) const TILE_SIZE: u32 = 256;
 
 fn mercator_normalized((x, y): (f64, f64)) -> (f64, f64) {
     // Project into Mercator (cylindrical map projection).
@@ -41,8 +104,28 @@ fn mercator_normalized((x, y): (f64, f64)) -> (f64, f64) {
 }
 
 impl PositionExt for Position {
+    fn from_lat_lon(latitude: f64, longitude: f64) -> Self {
+        debug_assert!(
+            latitude.abs() <= 90.,
+            "latitude of {latitude}° is out of range, are the coordinates swapped?"
+        );
+        Position::new(longitude, latitude)
+    }
+
+    fn from_degrees(latitude: Latitude, longitude: Longitude) -> Self {
+        Self::from_lat_lon(latitude.0, longitude.0)
+    }
+
+    fn latitude(&self) -> f64 {
+        self.y()
+    }
+
+    fn longitude(&self) -> f64 {
+        self.x()
+    }
+
     fn project(&self, zoom: u8) -> Pixels {
-        let (x, y) = mercator_normalized((*self).into());
+        let (x, y) = mercator_normalized(self.clamp_latitude().into());
 
         // Map that into a big bitmap made out of web tiles.
         let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
@@ -53,15 +136,80 @@ impl PositionExt for Position {
     }
 
     fn tile_id(&self, zoom: u8) -> TileId {
-        let (x, y) = mercator_normalized((*self).into());
+        let (x, y) = mercator_normalized(self.clamp_latitude().into());
 
-        // Map that into a big bitmap made out of web tiles.
+        // Map that into a big bitmap made out of web tiles. Southern edge of the world would
+        // otherwise land on a non-existent tile.
         let number_of_tiles = 2u32.pow(zoom as u32);
-        let x = (x * number_of_tiles as f64).floor() as u32;
-        let y = (y * number_of_tiles as f64).floor() as u32;
+        let x = ((x * number_of_tiles as f64).floor() as u32).min(number_of_tiles - 1);
+        let y = ((y * number_of_tiles as f64).floor() as u32).min(number_of_tiles - 1);
 
         TileId { x, y, zoom }
     }
+
+    fn clamp_latitude(&self) -> Position {
+        debug_assert!(
+            self.y().abs() <= 90. || self.y().is_nan(),
+            "latitude of {}° is out of range, are the coordinates swapped?",
+            self.y()
+        );
+        Position::new(self.x(), self.y().clamp(-MAX_LATITUDE, MAX_LATITUDE))
+    }
+
+    fn format(&self, format: CoordinateFormat) -> String {
+        crate::format::format(self, format)
+    }
+
+    fn to_mercator_meters(&self) -> (f64, f64) {
+        let position = self.clamp_latitude();
+        (
+            position.x().to_radians() * EARTH_RADIUS,
+            position.y().to_radians().tan().asinh() * EARTH_RADIUS,
+        )
+    }
+
+    fn from_mercator_meters(x: f64, y: f64) -> Self {
+        Position::new(
+            (x / EARTH_RADIUS).to_degrees(),
+            (y / EARTH_RADIUS).sinh().atan().to_degrees(),
+        )
+    }
+
+    fn lerp(&self, other: Position, t: f64) -> Position {
+        let (from, to) = (unit_vector(*self), unit_vector(other));
+        let cross = [
+            from[1] * to[2] - from[2] * to[1],
+            from[2] * to[0] - from[0] * to[2],
+            from[0] * to[1] - from[1] * to[0],
+        ];
+        let dot: f64 = from.iter().zip(&to).map(|(a, b)| a * b).sum();
+        let angle = cross.iter().map(|c| c * c).sum::<f64>().sqrt().atan2(dot);
+
+        if angle.sin().abs() < 1e-12 {
+            return Position::new(
+                self.x() + (other.x() - self.x()) * t,
+                self.y() + (other.y() - self.y()) * t,
+            );
+        }
+
+        let a = ((1. - t) * angle).sin() / angle.sin();
+        let b = (t * angle).sin() / angle.sin();
+        let [x, y, z] = [0, 1, 2].map(|i| a * from[i] + b * to[i]);
+        Position::from_lat_lon(
+            z.atan2((x * x + y * y).sqrt()).to_degrees(),
+            y.atan2(x).to_degrees(),
+        )
+    }
+}
+
+/// Point on a unit sphere.
+fn unit_vector(position: Position) -> [f64; 3] {
+    let (latitude, longitude) = (position.y().to_radians(), position.x().to_radians());
+    [
+        latitude.cos() * longitude.cos(),
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+    ]
 }
 
 /// Coordinates of the OSM-like tile.
@@ -79,6 +227,21 @@ pub struct TileId {
 }
 
 impl TileId {
+    /// Tile the `position` is on, at given `zoom` level. Same as [`PositionExt::tile_id`].
+    pub fn from_position(position: Position, zoom: u8) -> Self {
+        position.tile_id(zoom)
+    }
+
+    /// Geographical area covered by this tile.
+    pub fn to_bounds(&self) -> BoundingBox {
+        let number_of_tiles = 2u32.pow(self.zoom as u32) as f64;
+        let corner = |x: u32, y: u32| {
+            position_from_normalized((x as f64 / number_of_tiles, y as f64 / number_of_tiles))
+        };
+
+        BoundingBox::from_corners(corner(self.x, self.y), corner(self.x + 1, self.y + 1))
+    }
+
     /// Tile position (in pixels) on the "World bitmap".
     pub fn project(&self) -> Pixels {
         Pixels::new((self.x * TILE_SIZE) as f32, (self.y * TILE_SIZE) as f32)
@@ -115,6 +278,107 @@ impl TileId {
             zoom: self.zoom,
         })
     }
+
+    /// Tile of one zoom level lower, which covers this one.
+    pub fn parent(&self) -> Option<TileId> {
+        Some(TileId {
+            x: self.x / 2,
+            y: self.y / 2,
+            zoom: self.zoom.checked_sub(1)?,
+        })
+    }
+
+    /// Whether the tile is within the world, as [`TileId::east`] and [`TileId::south`] can go
+    /// past its edge.
+    pub fn is_valid(&self) -> bool {
+        let tiles = 1u64 << self.zoom;
+        (self.x as u64) < tiles && (self.y as u64) < tiles
+    }
+}
+
+/// Geographical area delimited by two meridians and two parallels, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+}
+
+impl BoundingBox {
+    /// Smallest box containing both positions, which are its opposite corners.
+    pub fn from_corners(a: Position, b: Position) -> Self {
+        Self {
+            west: a.x().min(b.x()),
+            south: a.y().min(b.y()),
+            east: a.x().max(b.x()),
+            north: a.y().max(b.y()),
+        }
+    }
+
+    /// Smallest box containing all of the `positions`, `None` if there are none.
+    pub fn from_positions(positions: &[Position]) -> Option<Self> {
+        let (first, rest) = positions.split_first()?;
+        Some(
+            rest.iter()
+                .fold(Self::from_corners(*first, *first), |bounds, position| {
+                    Self {
+                        west: bounds.west.min(position.x()),
+                        south: bounds.south.min(position.y()),
+                        east: bounds.east.max(position.x()),
+                        north: bounds.north.max(position.y()),
+                    }
+                }),
+        )
+    }
+
+    /// Whether the two boxes have any common part.
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.west <= other.east
+            && other.west <= self.east
+            && self.south <= other.north
+            && other.south <= self.north
+    }
+
+    /// Tiles covering this area at given zoom level, row by row, starting from the north-west.
+    pub fn tile_ids(&self, zoom: u8) -> impl Iterator<Item = TileId> {
+        let north_west = Position::new(self.west, self.north).tile_id(zoom);
+        let south_east = Position::new(self.east, self.south).tile_id(zoom);
+
+        (north_west.y..=south_east.y)
+            .flat_map(move |y| (north_west.x..=south_east.x).map(move |x| TileId { x, y, zoom }))
+    }
+}
+
+/// Earth's radius used by the Web Mercator, in meters.
+const EARTH_RADIUS: f64 = 6_378_137.;
+
+/// Size of the ground (in meters) represented by a single pixel at given latitude and zoom level.
+/// <https://learn.microsoft.com/en-us/bingmaps/articles/bing-maps-tile-system#ground-resolution-and-map-scale>
+pub fn ground_resolution(latitude: f64, zoom: u8) -> f64 {
+    let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
+    latitude
+        .clamp(-MAX_LATITUDE, MAX_LATITUDE)
+        .to_radians()
+        .cos()
+        * 2.
+        * PI
+        * EARTH_RADIUS
+        / number_of_pixels as f64
+}
+
+/// Zoom level at which a single pixel represents `meters_per_pixel` of the ground at given
+/// latitude, an inverse of [`ground_resolution`]. Usually it's between the integer levels. It's
+/// not clamped to the supported zoom levels, see [`crate::Zoom::range`].
+pub fn zoom_for_resolution(meters_per_pixel: f64, latitude: f64) -> f64 {
+    let circumference = latitude
+        .clamp(-MAX_LATITUDE, MAX_LATITUDE)
+        .to_radians()
+        .cos()
+        * 2.
+        * PI
+        * EARTH_RADIUS;
+    (circumference / (meters_per_pixel * TILE_SIZE as f64)).log2()
 }
 
 /// Transforms screen pixels into a geographical position.
@@ -122,14 +386,18 @@ pub fn screen_to_position(pixels: &Pixels, zoom: u8) -> Position {
     let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
     let number_of_pixels: f64 = number_of_pixels.into();
 
-    let lon = pixels.x as f64;
-    let lon = lon / number_of_pixels;
-    let lon = (lon * 2. - 1.) * PI;
+    position_from_normalized((
+        pixels.x as f64 / number_of_pixels,
+        pixels.y as f64 / number_of_pixels,
+    ))
+}
+
+/// Inverse of [`mercator_normalized`], turns 0-1 range coordinates into a geographical position.
+pub(crate) fn position_from_normalized((x, y): (f64, f64)) -> Position {
+    let lon = (x * 2. - 1.) * PI;
     let lon = lon.to_degrees();
 
-    let lat = pixels.y as f64;
-    let lat = lat / number_of_pixels;
-    let lat = (-lat * 2. + 1.) * PI;
+    let lat = (-y * 2. + 1.) * PI;
     let lat = lat.sinh().atan().to_degrees();
 
     Position::new(lon, lat)
@@ -139,6 +407,75 @@ pub fn screen_to_position(pixels: &Pixels, zoom: u8) -> Position {
 mod tests {
     use super::*;
 
+    #[test]
+    fn zoom_for_resolution_is_inverse_of_ground_resolution() {
+        for zoom in [0, 10, 19] {
+            for latitude in [0., 51.1, -70.] {
+                approx::assert_relative_eq!(
+                    zoom as f64,
+                    zoom_for_resolution(ground_resolution(latitude, zoom), latitude),
+                    epsilon = 1e-9
+                );
+            }
+        }
+
+        // Half the meters per pixel is one level more.
+        let zoom = zoom_for_resolution(2., 51.1);
+        approx::assert_relative_eq!(zoom + 1., zoom_for_resolution(1., 51.1), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn converting_to_and_from_mercator_meters() {
+        let citadel = Position::new(21.00027, 52.26470);
+
+        let (x, y) = citadel.to_mercator_meters();
+        approx::assert_relative_eq!(x, 2_337_739.36, epsilon = 0.01);
+        approx::assert_relative_eq!(y, 6_848_128.85, epsilon = 0.01);
+
+        let back = Position::from_mercator_meters(x, y);
+        approx::assert_relative_eq!(back.x(), citadel.x(), epsilon = 1e-9);
+        approx::assert_relative_eq!(back.y(), citadel.y(), epsilon = 1e-9);
+
+        // Corner of the world.
+        let (x, y) = Position::new(180., 90.).to_mercator_meters();
+        approx::assert_relative_eq!(x, 20_037_508.34, epsilon = 0.01);
+        approx::assert_relative_eq!(y, 20_037_508.34, epsilon = 0.01);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "are the coordinates swapped?")]
+    fn projecting_swapped_coordinates() {
+        // Latitude first, as in many other places.
+        Position::new(51.09916, 117.03664).project(10);
+    }
+
+    #[test]
+    fn interpolating_along_great_circle() {
+        let citadel = Position::new(21.00027, 52.26470);
+        let wroclaw = Position::new(17.03664, 51.09916);
+
+        let start = citadel.lerp(wroclaw, 0.);
+        approx::assert_relative_eq!(start.x(), citadel.x(), epsilon = 1e-9);
+        approx::assert_relative_eq!(start.y(), citadel.y(), epsilon = 1e-9);
+        let end = citadel.lerp(wroclaw, 1.);
+        approx::assert_relative_eq!(end.x(), wroclaw.x(), epsilon = 1e-9);
+        approx::assert_relative_eq!(end.y(), wroclaw.y(), epsilon = 1e-9);
+
+        // Along the equator, it's just the middle.
+        let middle = Position::new(0., 0.).lerp(Position::new(90., 0.), 0.5);
+        approx::assert_relative_eq!(middle.x(), 45., epsilon = 1e-9);
+        approx::assert_relative_eq!(middle.y(), 0., epsilon = 1e-9);
+
+        // Shorter way is across the antimeridian.
+        let middle = Position::new(170., 0.).lerp(Position::new(-170., 0.), 0.5);
+        approx::assert_relative_eq!(middle.x().abs(), 180., epsilon = 1e-9);
+
+        // Great circle bends towards the pole.
+        let middle = Position::new(-60., 45.).lerp(Position::new(60., 45.), 0.5);
+        assert!(middle.y() > 45.);
+    }
+
     #[test]
     fn projecting_position_and_tile() {
         let citadel = Position::new(21.00027, 52.26470);
@@ -168,6 +505,114 @@ mod tests {
         );
     }
 
+    #[test]
+    fn constructing_from_latitude_and_longitude() {
+        let citadel = Position::from_lat_lon(52.26470, 21.00027);
+        assert_eq!(Position::new(21.00027, 52.26470), citadel);
+        assert_eq!(52.26470, citadel.latitude());
+        assert_eq!(21.00027, citadel.longitude());
+    }
+
+    #[test]
+    fn latitude_is_clamped_to_mercator_range() {
+        let north_pole = Position::new(21.00027, 90.);
+        let south_pole = Position::new(21.00027, -90.);
+        let zoom = 3;
+
+        assert_eq!(MAX_LATITUDE, north_pole.clamp_latitude().y());
+        assert_eq!(-MAX_LATITUDE, south_pole.clamp_latitude().y());
+        assert_eq!(21.00027, north_pole.clamp_latitude().x());
+
+        // Poles end up at the very edges of the world bitmap.
+        approx::assert_relative_eq!(0., north_pole.project(zoom).y, epsilon = 0.01);
+        approx::assert_relative_eq!(2048., south_pole.project(zoom).y, epsilon = 0.01);
+
+        assert_eq!(0, north_pole.tile_id(zoom).y);
+        assert_eq!(7, south_pole.tile_id(zoom).y);
+    }
+
+    #[test]
+    fn tiles_covering_bounding_box() {
+        let world = BoundingBox {
+            west: -180.,
+            south: -90.,
+            east: 180.,
+            north: 90.,
+        };
+        assert_eq!(1, world.tile_ids(0).count());
+        assert_eq!(16, world.tile_ids(2).count());
+
+        // Single point is still covered by a single tile.
+        let citadel = Position::new(21.00027, 52.26470);
+        let point = BoundingBox {
+            west: citadel.x(),
+            south: citadel.y(),
+            east: citadel.x(),
+            north: citadel.y(),
+        };
+        assert_eq!(
+            vec![citadel.tile_id(16)],
+            point.tile_ids(16).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn tile_bounds() {
+        let world = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        }
+        .to_bounds();
+        approx::assert_relative_eq!(-180., world.west);
+        approx::assert_relative_eq!(180., world.east);
+        approx::assert_relative_eq!(MAX_LATITUDE, world.north);
+        approx::assert_relative_eq!(-MAX_LATITUDE, world.south);
+
+        // Tile contains the position it was created from.
+        let citadel = Position::new(21.00027, 52.26470);
+        let bounds = TileId::from_position(citadel, 16).to_bounds();
+        assert!(bounds.west <= citadel.x() && citadel.x() <= bounds.east);
+        assert!(bounds.south <= citadel.y() && citadel.y() <= bounds.north);
+    }
+
+    #[test]
+    fn bounding_box_of_positions() {
+        assert_eq!(None, BoundingBox::from_positions(&[]));
+
+        let citadel = Position::new(21.00027, 52.26470);
+        assert_eq!(
+            Some(BoundingBox::from_corners(citadel, citadel)),
+            BoundingBox::from_positions(&[citadel])
+        );
+
+        assert_eq!(
+            Some(BoundingBox {
+                west: -1.,
+                south: -4.,
+                east: 3.,
+                north: 2.,
+            }),
+            BoundingBox::from_positions(&[
+                Position::new(-1., 2.),
+                Position::new(3., 0.),
+                Position::new(0., -4.),
+            ])
+        );
+    }
+
+    #[test]
+    fn ground_resolution_at_equator() {
+        // Whole equator fits into a single tile at zoom 0.
+        approx::assert_relative_eq!(156_543.03, ground_resolution(0., 0), epsilon = 0.01);
+        approx::assert_relative_eq!(
+            ground_resolution(0., 0) / 2.,
+            ground_resolution(60., 0),
+            epsilon = 0.01
+        );
+        approx::assert_relative_eq!(0.2986, ground_resolution(0., 19), epsilon = 0.0001);
+    }
+
     #[test]
     fn project_there_and_back() {
         let citadel = Position::new(21.00027, 52.26470);
@@ -0,0 +1,145 @@
+use egui::{Painter, Rect, Response, Sense, Ui, Widget};
+
+use crate::{
+    zoom::InvalidZoom, BoundingBox, Center, Map, MapMemory, Plugin, Position, Projector, Tiles,
+    Zoom,
+};
+
+/// Small overview of another ("main") map. It stays centered at the main map, at a lower zoom
+/// level, shows the area visible in the main map with a rectangle, and recenters the main map
+/// when clicked. It must persist between frames.
+pub struct Minimap {
+    memory: MapMemory,
+
+    /// How many zoom levels is the minimap below the main map.
+    zoom_difference: u8,
+}
+
+impl Minimap {
+    /// Minimap showing the main map `zoom_difference` levels lower.
+    pub fn new(zoom_difference: u8) -> Self {
+        Self {
+            memory: MapMemory::default(),
+            zoom_difference,
+        }
+    }
+
+    /// Show the minimap of the `main` map, which was drawn in the `main_viewport` (typically the
+    /// `rect` of the main map's [`Response`]).
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        tiles: Option<&mut Tiles>,
+        main: &mut MapMemory,
+        main_viewport: Rect,
+    ) -> Response {
+        // Main map was not drawn yet, so there is nothing to follow.
+        let Some(center) = main.center() else {
+            return ui.allocate_response(ui.available_size(), Sense::hover());
+        };
+
+        self.memory.center_mode = Center::Exact(center);
+        if let Ok(zoom) = zoom_below(main.zoom, self.zoom_difference) {
+            self.memory.zoom = zoom;
+        }
+
+        let main_view = MainView {
+            bounds: main.visible_bounds(main_viewport),
+        };
+
+        let response = Map::new(tiles, &mut self.memory, center)
+            .with_sense(Sense::click())
+            .with_plugin(main_view)
+            .ui(ui);
+
+        if response.clicked() {
+            if let (Some(pointer), Some(projector)) = (
+                response.interact_pointer_pos(),
+                self.memory.projector(response.rect),
+            ) {
+                main.center_mode = Center::Exact(projector.unproject(pointer));
+            }
+        }
+
+        response
+    }
+}
+
+/// Zoom level `difference` levels below `zoom`, but not below zero.
+fn zoom_below(zoom: Zoom, difference: u8) -> Result<Zoom, InvalidZoom> {
+    Zoom::try_from((zoom.value() - difference as f32).max(0.))
+}
+
+/// Rectangle showing what is visible in the main map.
+struct MainView {
+    bounds: Option<BoundingBox>,
+}
+
+impl Plugin for MainView {
+    fn draw(&mut self, response: &Response, painter: Painter, projector: &Projector) {
+        let Some(bounds) = self.bounds else {
+            return;
+        };
+
+        let rect = Rect::from_two_pos(
+            projector
+                .project(Position::new(bounds.west, bounds.north))
+                .to_pos2(),
+            projector
+                .project(Position::new(bounds.east, bounds.south))
+                .to_pos2(),
+        );
+        painter.rect_stroke(rect, 0., response.ctx.style().visuals.selection.stroke);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::solid_color_tiles, PositionExt};
+    use egui::{CentralPanel, Color32, Context, Event, Modifiers, PointerButton, Pos2, RawInput};
+
+    #[test]
+    fn minimap_follows_main_map_and_recenters_it() {
+        let ctx = Context::default();
+        let mut tiles = solid_color_tiles(Color32::GRAY, ctx.clone());
+        let wroclaw = Position::new(17.03664, 51.09916);
+        let mut main = MapMemory::with_view(wroclaw, 16.).unwrap();
+        let main_viewport = Rect::from_min_size(Pos2::ZERO, egui::vec2(800., 600.));
+        let mut minimap = Minimap::new(4);
+
+        let mut run = |events: Vec<Event>| {
+            let input = RawInput {
+                screen_rect: Some(Rect::from_min_size(Pos2::ZERO, egui::vec2(200., 200.))),
+                events,
+                ..Default::default()
+            };
+            let _ = ctx.run(input, |ctx| {
+                CentralPanel::default().show(ctx, |ui| {
+                    minimap.show(ui, Some(&mut tiles), &mut main, main_viewport);
+                });
+            });
+            (minimap.memory.zoom.round(), main.center_mode.clone())
+        };
+
+        let (zoom, _) = run(Vec::new());
+        assert_eq!(12, zoom);
+
+        // Click to the east of the minimap's center.
+        let click = Pos2::new(150., 100.);
+        let button = |pressed| Event::PointerButton {
+            pos: click,
+            button: PointerButton::Primary,
+            pressed,
+            modifiers: Modifiers::default(),
+        };
+        run(vec![Event::PointerMoved(click)]);
+        run(vec![button(true)]);
+        let (_, center) = run(vec![button(false)]);
+
+        let Center::Exact(center) = center else {
+            panic!("main map should be detached");
+        };
+        assert!(center.longitude() > wroclaw.longitude());
+    }
+}
@@ -0,0 +1,562 @@
+//! Mapbox Vector Tiles.
+//! <https://github.com/mapbox/vector-tile-spec/tree/master/2.1>
+//!
+//! [`VectorTiles`] downloads `.pbf` tiles, decompresses them if needed and decodes their features
+//! into [`geo_types`] geometries, with coordinates already turned into a [`Position`]. Styling is
+//! left to the application, which can draw them on top of the [`crate::Map`] the same way as any
+//! other custom shape.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+
+use egui::Context;
+use geo_types::{
+    Coord, Geometry, LineString, MultiLineString, MultiPoint, MultiPolygon, Point, Polygon,
+};
+use reqwest::header::USER_AGENT;
+use tokio::sync::mpsc::error::TryRecvError;
+
+use crate::mercator::{position_from_normalized, TileId};
+use crate::tokio::TokioRuntimeThread;
+use crate::Position;
+
+/// Decoded vector tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorTile {
+    pub layers: Vec<Layer>,
+}
+
+/// Named layer of a [`VectorTile`], such as "water" or "roads".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub features: Vec<Feature>,
+}
+
+/// Single geometry along with its properties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feature {
+    pub id: Option<u64>,
+
+    /// Geometry with geographical coordinates.
+    pub geometry: Geometry,
+
+    pub properties: HashMap<String, Value>,
+}
+
+/// Value of the [`Feature`]'s property.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Float(f32),
+    Double(f64),
+    Int(i64),
+    UInt(u64),
+    Bool(bool),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Http(reqwest::Error),
+
+    #[error("could not decompress the tile: {0}")]
+    Decompress(std::io::Error),
+
+    #[error("could not decode the tile: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("malformed geometry")]
+    Geometry,
+}
+
+/// Messages as defined by the `vector_tile.proto`, only what is needed for decoding.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Tile {
+        #[prost(message, repeated, tag = "3")]
+        pub layers: Vec<Layer>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Value {
+        #[prost(string, optional, tag = "1")]
+        pub string_value: Option<String>,
+        #[prost(float, optional, tag = "2")]
+        pub float_value: Option<f32>,
+        #[prost(double, optional, tag = "3")]
+        pub double_value: Option<f64>,
+        #[prost(int64, optional, tag = "4")]
+        pub int_value: Option<i64>,
+        #[prost(uint64, optional, tag = "5")]
+        pub uint_value: Option<u64>,
+        #[prost(sint64, optional, tag = "6")]
+        pub sint_value: Option<i64>,
+        #[prost(bool, optional, tag = "7")]
+        pub bool_value: Option<bool>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Feature {
+        #[prost(uint64, optional, tag = "1")]
+        pub id: Option<u64>,
+        #[prost(uint32, repeated, tag = "2")]
+        pub tags: Vec<u32>,
+        #[prost(int32, optional, tag = "3")]
+        pub r#type: Option<i32>,
+        #[prost(uint32, repeated, tag = "4")]
+        pub geometry: Vec<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Layer {
+        #[prost(uint32, required, tag = "15")]
+        pub version: u32,
+        #[prost(string, required, tag = "1")]
+        pub name: String,
+        #[prost(message, repeated, tag = "2")]
+        pub features: Vec<Feature>,
+        #[prost(string, repeated, tag = "3")]
+        pub keys: Vec<String>,
+        #[prost(message, repeated, tag = "4")]
+        pub values: Vec<Value>,
+        #[prost(uint32, optional, tag = "5")]
+        pub extent: Option<u32>,
+    }
+
+    pub const POINT: i32 = 1;
+    pub const LINESTRING: i32 = 2;
+    pub const POLYGON: i32 = 3;
+}
+
+impl From<proto::Value> for Option<Value> {
+    fn from(value: proto::Value) -> Self {
+        value
+            .string_value
+            .map(Value::String)
+            .or(value.float_value.map(Value::Float))
+            .or(value.double_value.map(Value::Double))
+            .or(value.int_value.map(Value::Int))
+            .or(value.uint_value.map(Value::UInt))
+            .or(value.sint_value.map(Value::Int))
+            .or(value.bool_value.map(Value::Bool))
+    }
+}
+
+/// Decompress (if it's gzip or zlib) and decode a vector tile.
+pub fn decode(tile_id: TileId, bytes: &[u8]) -> Result<VectorTile, Error> {
+    let bytes = decompress(bytes).map_err(Error::Decompress)?;
+    let tile: proto::Tile = prost::Message::decode(bytes.as_slice())?;
+
+    let layers = tile
+        .layers
+        .into_iter()
+        .map(|layer| decode_layer(tile_id, layer))
+        .collect::<Result<_, _>>()?;
+
+    Ok(VectorTile { layers })
+}
+
+fn decompress(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    match bytes {
+        [0x1f, 0x8b, ..] => {
+            flate2::read::GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+        }
+        // zlib header, the second byte is a checksum for the first.
+        [0x78, second, ..] if u16::from_be_bytes([0x78, *second]).is_multiple_of(31) => {
+            flate2::read::ZlibDecoder::new(bytes).read_to_end(&mut decompressed)?;
+        }
+        _ => decompressed.extend_from_slice(bytes),
+    }
+    Ok(decompressed)
+}
+
+fn decode_layer(tile_id: TileId, layer: proto::Layer) -> Result<Layer, Error> {
+    let extent = layer.extent.unwrap_or(4096) as f64;
+    let values: Vec<Option<Value>> = layer.values.into_iter().map(Into::into).collect();
+
+    let to_position = |x: i64, y: i64| {
+        let number_of_tiles = 2u32.pow(tile_id.zoom as u32) as f64;
+        position_from_normalized((
+            (tile_id.x as f64 + x as f64 / extent) / number_of_tiles,
+            (tile_id.y as f64 + y as f64 / extent) / number_of_tiles,
+        ))
+    };
+
+    let features = layer
+        .features
+        .into_iter()
+        .filter_map(|feature| {
+            let geometry = match decode_geometry(&feature, to_position) {
+                Ok(Some(geometry)) => geometry,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+
+            let properties = feature
+                .tags
+                .chunks_exact(2)
+                .filter_map(|tag| {
+                    let key = layer.keys.get(tag[0] as usize)?;
+                    let value = values.get(tag[1] as usize)?.clone()?;
+                    Some((key.to_owned(), value))
+                })
+                .collect();
+
+            Some(Ok(Feature {
+                id: feature.id,
+                geometry,
+                properties,
+            }))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Layer {
+        name: layer.name,
+        features,
+    })
+}
+
+/// Turn MVT's command stream into a list of rings (or lines, or points) in tile-local
+/// coordinates.
+fn decode_commands(geometry: &[u32]) -> Result<Vec<Vec<(i64, i64)>>, Error> {
+    const MOVE_TO: u32 = 1;
+    const LINE_TO: u32 = 2;
+    const CLOSE_PATH: u32 = 7;
+
+    let zigzag = |value: u32| ((value >> 1) as i64) ^ -((value & 1) as i64);
+
+    let mut parts: Vec<Vec<(i64, i64)>> = Vec::new();
+    let (mut x, mut y) = (0i64, 0i64);
+    let mut iter = geometry.iter();
+
+    while let Some(command) = iter.next() {
+        let id = command & 0x7;
+        let count = command >> 3;
+
+        match id {
+            MOVE_TO | LINE_TO => {
+                for _ in 0..count {
+                    let dx = zigzag(*iter.next().ok_or(Error::Geometry)?);
+                    let dy = zigzag(*iter.next().ok_or(Error::Geometry)?);
+                    x += dx;
+                    y += dy;
+
+                    if id == MOVE_TO {
+                        parts.push(Vec::new());
+                    }
+                    parts.last_mut().ok_or(Error::Geometry)?.push((x, y));
+                }
+            }
+            CLOSE_PATH => {
+                let part = parts.last_mut().ok_or(Error::Geometry)?;
+                let first = *part.first().ok_or(Error::Geometry)?;
+                part.push(first);
+            }
+            _ => return Err(Error::Geometry),
+        }
+    }
+
+    Ok(parts)
+}
+
+fn decode_geometry(
+    feature: &proto::Feature,
+    to_position: impl Fn(i64, i64) -> Position,
+) -> Result<Option<Geometry>, Error> {
+    let parts = decode_commands(&feature.geometry)?;
+    let line_string = |part: &[(i64, i64)]| -> LineString {
+        part.iter()
+            .map(|(x, y)| Coord::from(to_position(*x, *y)))
+            .collect()
+    };
+
+    Ok(match feature.r#type {
+        Some(proto::POINT) => {
+            let mut points: Vec<Point> = parts
+                .iter()
+                .flatten()
+                .map(|(x, y)| to_position(*x, *y))
+                .collect();
+
+            if points.len() == 1 {
+                points.pop().map(Geometry::Point)
+            } else {
+                Some(Geometry::MultiPoint(MultiPoint(points)))
+            }
+        }
+        Some(proto::LINESTRING) => {
+            let mut lines: Vec<LineString> = parts.iter().map(|part| line_string(part)).collect();
+
+            if lines.len() == 1 {
+                lines.pop().map(Geometry::LineString)
+            } else {
+                Some(Geometry::MultiLineString(MultiLineString(lines)))
+            }
+        }
+        Some(proto::POLYGON) => {
+            // Exterior rings are clockwise (positive area in tile coordinates, where y goes
+            // down), interior rings belong to the preceding exterior one.
+            let mut polygons: Vec<Polygon> = Vec::new();
+            for part in &parts {
+                if signed_area(part) > 0 {
+                    polygons.push(Polygon::new(line_string(part), Vec::new()));
+                } else if let Some(polygon) = polygons.last_mut() {
+                    polygon.interiors_push(line_string(part));
+                }
+            }
+
+            if polygons.len() == 1 {
+                polygons.pop().map(Geometry::Polygon)
+            } else {
+                Some(Geometry::MultiPolygon(MultiPolygon(polygons)))
+            }
+        }
+        // Unknown geometries are to be ignored, according to the spec.
+        _ => None,
+    })
+}
+
+/// Doubled signed area of a ring, using the shoelace formula.
+fn signed_area(ring: &[(i64, i64)]) -> i64 {
+    ring.windows(2)
+        .map(|pair| pair[0].0 * pair[1].1 - pair[1].0 * pair[0].1)
+        .sum()
+}
+
+/// Downloads and keeps cache of the vector tiles. It must persist between frames.
+pub struct VectorTiles {
+    cache: HashMap<TileId, Option<Arc<VectorTile>>>,
+
+    /// Tiles to be downloaded by the IO thread.
+    request_tx: tokio::sync::mpsc::Sender<TileId>,
+
+    /// Tiles that got downloaded and should be put in the cache.
+    tile_rx: tokio::sync::mpsc::Receiver<(TileId, VectorTile)>,
+
+    #[allow(dead_code)] // Significant Drop
+    tokio_runtime_thread: TokioRuntimeThread,
+}
+
+impl VectorTiles {
+    /// Create a new cache. `source` should return an URL of the `.pbf` tile.
+    pub fn new<S>(source: S, egui_ctx: Context) -> Self
+    where
+        S: Fn(TileId) -> String + Send + 'static,
+    {
+        let tokio_runtime_thread = TokioRuntimeThread::new();
+
+        // Same as for the raster tiles.
+        let channel_size = 20;
+
+        let (request_tx, request_rx) = tokio::sync::mpsc::channel(channel_size);
+        let (tile_tx, tile_rx) = tokio::sync::mpsc::channel(channel_size);
+        tokio_runtime_thread
+            .runtime
+            .spawn(download(source, request_rx, tile_tx, egui_ctx));
+
+        Self {
+            cache: Default::default(),
+            request_tx,
+            tile_rx,
+            tokio_runtime_thread,
+        }
+    }
+
+    /// Return a tile if already in cache, schedule a download otherwise.
+    pub fn at(&mut self, tile_id: TileId) -> Option<Arc<VectorTile>> {
+        // Take everything that got downloaded, vector tiles are usually requested in bulk.
+        loop {
+            match self.tile_rx.try_recv() {
+                Ok((tile_id, tile)) => {
+                    self.cache.insert(tile_id, Some(Arc::new(tile)));
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => panic!("IO thread is dead"),
+            }
+        }
+
+        match self.cache.entry(tile_id) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                if let Ok(()) = self.request_tx.try_send(tile_id) {
+                    log::debug!("Requested vector tile: {:?}", tile_id);
+                    entry.insert(None);
+                } else {
+                    log::debug!("Request queue is full.");
+                }
+                None
+            }
+        }
+    }
+}
+
+async fn download_single(
+    client: &reqwest::Client,
+    tile_id: TileId,
+    url: &str,
+) -> Result<VectorTile, Error> {
+    let bytes = client
+        .get(url)
+        .header(USER_AGENT, "Walkers")
+        .send()
+        .await
+        .map_err(Error::Http)?
+        .error_for_status()
+        .map_err(Error::Http)?
+        .bytes()
+        .await
+        .map_err(Error::Http)?;
+
+    decode(tile_id, &bytes)
+}
+
+async fn download<S>(
+    source: S,
+    mut request_rx: tokio::sync::mpsc::Receiver<TileId>,
+    tile_tx: tokio::sync::mpsc::Sender<(TileId, VectorTile)>,
+    egui_ctx: Context,
+) -> Result<(), ()>
+where
+    S: Fn(TileId) -> String + Send + 'static,
+{
+    // Keep outside the loop to reuse it as much as possible.
+    let client = reqwest::Client::new();
+
+    loop {
+        let request = request_rx.recv().await.ok_or(())?;
+        let url = source(request);
+
+        log::debug!("Getting {:?} from {}.", request, url);
+
+        match download_single(&client, request, &url).await {
+            Ok(tile) => {
+                tile_tx.send((request, tile)).await.map_err(|_| ())?;
+                egui_ctx.request_repaint();
+            }
+            Err(e) => {
+                log::warn!("Could not download '{}': {}", &url, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    static TILE_ID: TileId = TileId {
+        x: 0,
+        y: 0,
+        zoom: 0,
+    };
+
+    fn command(id: u32, count: u32) -> u32 {
+        (id & 0x7) | (count << 3)
+    }
+
+    fn zigzag(value: i32) -> u32 {
+        ((value << 1) ^ (value >> 31)) as u32
+    }
+
+    fn tile() -> proto::Tile {
+        proto::Tile {
+            layers: vec![proto::Layer {
+                version: 2,
+                name: "roads".to_owned(),
+                features: vec![
+                    proto::Feature {
+                        id: Some(1),
+                        tags: vec![0, 0],
+                        r#type: Some(proto::LINESTRING),
+                        // From the center of the world to its south-east corner.
+                        geometry: vec![
+                            command(1, 1),
+                            zigzag(2048),
+                            zigzag(2048),
+                            command(2, 1),
+                            zigzag(2048),
+                            zigzag(2048),
+                        ],
+                    },
+                    proto::Feature {
+                        id: None,
+                        tags: vec![],
+                        r#type: Some(proto::POLYGON),
+                        geometry: vec![
+                            command(1, 1),
+                            zigzag(0),
+                            zigzag(0),
+                            command(2, 2),
+                            zigzag(2048),
+                            zigzag(0),
+                            zigzag(0),
+                            zigzag(2048),
+                            command(7, 1),
+                        ],
+                    },
+                ],
+                keys: vec!["name".to_owned()],
+                values: vec![proto::Value {
+                    string_value: Some("Main Street".to_owned()),
+                    ..Default::default()
+                }],
+                extent: Some(4096),
+            }],
+        }
+    }
+
+    fn assert_decoded_correctly(tile: VectorTile) {
+        let layer = &tile.layers[0];
+        assert_eq!("roads", layer.name);
+
+        let road = &layer.features[0];
+        assert_eq!(Some(1), road.id);
+        assert_eq!(
+            Some(&Value::String("Main Street".to_owned())),
+            road.properties.get("name")
+        );
+
+        let Geometry::LineString(line) = &road.geometry else {
+            panic!("expected a line string, got {:?}", road.geometry);
+        };
+        let points: Vec<Point> = line.points().collect();
+        approx::assert_relative_eq!(0., points[0].x());
+        approx::assert_relative_eq!(0., points[0].y(), epsilon = 1e-9);
+        approx::assert_relative_eq!(180., points[1].x());
+        approx::assert_relative_eq!(-crate::MAX_LATITUDE, points[1].y(), epsilon = 1e-9);
+
+        let Geometry::Polygon(polygon) = &layer.features[1].geometry else {
+            panic!("expected a polygon, got {:?}", layer.features[1].geometry);
+        };
+        assert!(polygon.exterior().is_closed());
+        assert_eq!(4, polygon.exterior().0.len());
+    }
+
+    #[test]
+    fn decoding_plain_tile() {
+        let bytes = prost::Message::encode_to_vec(&tile());
+        assert_decoded_correctly(decode(TILE_ID, &bytes).unwrap());
+    }
+
+    #[test]
+    fn decoding_gzipped_tile() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(&prost::Message::encode_to_vec(&tile()))
+            .unwrap();
+        let bytes = encoder.finish().unwrap();
+
+        assert_decoded_correctly(decode(TILE_ID, &bytes).unwrap());
+    }
+
+    #[test]
+    fn garbage_is_an_error() {
+        assert!(decode(TILE_ID, b"definitely not a tile").is_err());
+    }
+}
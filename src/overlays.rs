@@ -0,0 +1,1847 @@
+//! Ready-made [`Plugin`]s drawing common shapes on the map.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::RangeInclusive;
+
+use egui::{
+    epaint::{CircleShape, Tessellator, Vertex},
+    pos2, Align2, Area, Color32, FontId, Id, Mesh, Order, Painter, Pos2, Rect, Response, Sense,
+    Shape, Stroke, TextureId, Ui, Vec2,
+};
+
+use crate::format::CoordinateFormat;
+use crate::mercator::TILE_SIZE;
+use crate::{BoundingBox, Easing, Plugin, Position, PositionExt, Projector, MAX_LATITUDE};
+
+/// How the size of a shape behaves when the map gets zoomed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sizing {
+    /// Size is given in screen pixels and stays the same regardless of the zoom, like the size
+    /// of a marker icon.
+    #[default]
+    Screen,
+
+    /// Size is given in meters, so the shape grows and shrinks along with the map, like the
+    /// width of a road.
+    Geographic,
+}
+
+impl Sizing {
+    /// Turn `size` into screen pixels, at given `position`.
+    pub fn to_pixels(&self, size: f32, position: Position, projector: &Projector) -> f32 {
+        match self {
+            Sizing::Screen => size,
+            Sizing::Geographic => size * projector.pixels_per_meter(position),
+        }
+    }
+}
+
+/// Circle marking a single position, or an arrowhead if it has a `rotation`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub position: Position,
+
+    /// Radius, interpreted according to `sizing`.
+    pub radius: f32,
+    pub sizing: Sizing,
+
+    pub fill: Color32,
+
+    /// Outline, always in screen pixels.
+    pub stroke: Stroke,
+
+    /// Heading in degrees, clockwise from the north. If set, the marker is drawn as an
+    /// arrowhead pointing that way, e.g. the direction a vehicle is moving in.
+    pub rotation: Option<f32>,
+}
+
+impl Marker {
+    /// Marker of a fixed, screen size.
+    pub fn new(position: Position) -> Self {
+        Self {
+            position,
+            radius: 5.,
+            sizing: Sizing::Screen,
+            fill: Color32::from_rgb(0, 92, 175),
+            stroke: Stroke::new(2., Color32::WHITE),
+            rotation: None,
+        }
+    }
+
+    /// Point the marker towards given heading, in degrees clockwise from the north.
+    pub fn with_rotation(self, rotation: f32) -> Self {
+        Self {
+            rotation: Some(rotation),
+            ..self
+        }
+    }
+}
+
+impl Plugin for Marker {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        painter.add(self.shape(projector));
+    }
+}
+
+impl ToShape for Marker {
+    fn shape(&self, projector: &Projector) -> Shape {
+        let center = projector.project(self.position).to_pos2();
+        let radius = self.sizing.to_pixels(self.radius, self.position, projector);
+        let fill = self.fill.gamma_multiply(projector.opacity());
+        let stroke = Stroke::new(
+            self.stroke.width,
+            self.stroke.color.gamma_multiply(projector.opacity()),
+        );
+
+        match self.rotation {
+            Some(rotation) => {
+                Shape::convex_polygon(arrowhead(center, rotation, radius).to_vec(), fill, stroke)
+            }
+            None => Shape::Circle(CircleShape {
+                center,
+                radius,
+                fill,
+                stroke,
+            }),
+        }
+    }
+}
+
+/// Triangle inscribed in a circle of given `radius`, with its tip pointing towards `rotation`
+/// degrees clockwise from the north.
+fn arrowhead(center: Pos2, rotation: f32, radius: f32) -> [Pos2; 3] {
+    let corner = |angle: f32| {
+        let (sin, cos) = (rotation + angle).to_radians().sin_cos();
+        // Screen's y axis points to the south.
+        center + egui::vec2(sin, -cos) * radius
+    };
+    [corner(0.), corner(140.), corner(-140.)]
+}
+
+impl Marker {
+    /// Whether the marker, as drawn by the `projector`, covers given screen position.
+    fn contains(&self, screen_position: Pos2, projector: &Projector) -> bool {
+        let radius =
+            self.sizing.to_pixels(self.radius, self.position, projector) + self.stroke.width / 2.;
+        projector
+            .project(self.position)
+            .to_pos2()
+            .distance(screen_position)
+            <= radius
+    }
+}
+
+/// [`Marker`]s which can be clicked. Each one carries a key, e.g. an identifier of the object it
+/// represents, which ends up in `clicked` when it's clicked. Map needs to sense the clicks for
+/// that, see [`crate::Map::with_sense`].
+pub struct ClickableMarkers<'a, K> {
+    pub markers: Vec<(K, Marker)>,
+
+    /// Key of the marker clicked in this frame, if any.
+    pub clicked: &'a mut Option<K>,
+}
+
+impl<K> Plugin for ClickableMarkers<'_, K> {
+    fn draw(&mut self, response: &Response, painter: Painter, projector: &Projector) {
+        let click = response
+            .clicked()
+            .then(|| response.interact_pointer_pos())
+            .flatten();
+
+        for (key, mut marker) in self.markers.drain(..) {
+            marker.draw(response, painter.clone(), projector);
+
+            // Markers drawn later are on top, so they win.
+            if let Some(click) = click {
+                if marker.contains(click, projector) {
+                    *self.clicked = Some(key);
+                }
+            }
+        }
+    }
+}
+
+/// [`Marker`]s which are egui widgets, taking part in egui's interaction like buttons do: they
+/// can be hovered, clicked and focused. Each one carries a key, which ends up in `responses`
+/// along with the marker's [`Response`], e.g. to handle the clicks or to show a tooltip with
+/// [`Response::on_hover_text`] once the map is drawn. Like [`WidgetAnchor`], they are shown in
+/// separate [`Area`]s, so interacting with them does not pan the map.
+pub struct InteractiveMarkers<'a, K> {
+    pub markers: Vec<(K, Marker)>,
+
+    /// Fill of the marker which is hovered or has the keyboard focus.
+    pub highlight: Color32,
+
+    /// Responses of the markers drawn in this frame. Markers off the map are left out.
+    pub responses: &'a mut Vec<(K, Response)>,
+}
+
+impl<K: Hash> Plugin for InteractiveMarkers<'_, K> {
+    fn draw(&mut self, response: &Response, _painter: Painter, projector: &Projector) {
+        self.responses.clear();
+
+        for (key, marker) in self.markers.drain(..) {
+            let screen_position = projector.project(marker.position).to_pos2();
+            if !projector.clip_rect().contains(screen_position) {
+                continue;
+            }
+
+            let radius = marker
+                .sizing
+                .to_pixels(marker.radius, marker.position, projector)
+                + marker.stroke.width / 2.;
+            let highlight = self.highlight;
+            let id = Id::new(("walkers_interactive_marker", &key));
+            let marker_response =
+                area_over_map(id, response, screen_position, Align2::CENTER_CENTER)
+                    .show(&response.ctx, |ui| {
+                        ui.set_clip_rect(projector.clip_rect());
+                        let (_, marker_response) =
+                            ui.allocate_exact_size(Vec2::splat(2. * radius), Sense::click());
+                        let fill = if marker_response.hovered() || marker_response.has_focus() {
+                            highlight
+                        } else {
+                            marker.fill
+                        };
+                        ui.painter().add(Marker { fill, ..marker }.shape(projector));
+                        marker_response
+                    })
+                    .inner;
+            self.responses.push((key, marker_response));
+        }
+    }
+}
+
+/// [`Area`] placed at `screen_position` over the map which gave the `response`. It goes right
+/// above the map, so that it does not cover e.g. the windows in front of it. `id` only needs to
+/// be unique within the map.
+fn area_over_map(id: Id, response: &Response, screen_position: Pos2, pivot: Align2) -> Area {
+    let order = match response.layer_id.order {
+        // Panels are painted after the areas of the same order, so go one above.
+        Order::Background => Order::PanelResizeLine,
+        order => order,
+    };
+    Area::new(response.id.with(id))
+        .order(order)
+        .fixed_pos(screen_position)
+        .pivot(pivot)
+        .constrain(false)
+}
+
+/// Remembers where the markers were, so that they can glide to their new positions instead of
+/// jumping. It must persist between frames. See [`AnimatedMarkers`].
+pub struct MarkerTransitions<K> {
+    /// How long it takes for a marker to reach its new position, in seconds.
+    pub duration: f64,
+
+    /// How markers speed up and slow down while moving. [`Easing::Linear`] by default.
+    pub easing: Easing,
+    transitions: HashMap<K, Transition>,
+}
+
+struct Transition {
+    from: Position,
+    to: Position,
+    started: f64,
+}
+
+impl Transition {
+    fn progress(&self, now: f64, duration: f64) -> f64 {
+        if duration > 0. {
+            ((now - self.started) / duration).clamp(0., 1.)
+        } else {
+            1.
+        }
+    }
+
+    fn position(&self, now: f64, duration: f64, easing: Easing) -> Position {
+        let t = easing.apply(self.progress(now, duration));
+        Position::new(
+            self.from.x() + (self.to.x() - self.from.x()) * t,
+            self.from.y() + (self.to.y() - self.from.y()) * t,
+        )
+    }
+}
+
+impl<K: Hash + Eq> MarkerTransitions<K> {
+    /// `duration` is in seconds.
+    pub fn new(duration: f64) -> Self {
+        Self {
+            duration,
+            easing: Easing::default(),
+            transitions: HashMap::new(),
+        }
+    }
+
+    /// Use given `easing` for the markers' movement.
+    pub fn with_easing(self, easing: Easing) -> Self {
+        Self { easing, ..self }
+    }
+
+    /// Position where marker `key` should be drawn at the time `now` (in seconds), given that it
+    /// should end up at `target`. Also returns whether it's still moving.
+    pub fn update(&mut self, key: K, target: Position, now: f64) -> (Position, bool) {
+        let duration = self.duration;
+        let easing = self.easing;
+        let transition = self.transitions.entry(key).or_insert(Transition {
+            from: target,
+            to: target,
+            started: now,
+        });
+
+        if transition.to != target {
+            *transition = Transition {
+                from: transition.position(now, duration, easing),
+                to: target,
+                started: now,
+            };
+        }
+
+        (
+            transition.position(now, duration, easing),
+            transition.from != transition.to && transition.progress(now, duration) < 1.,
+        )
+    }
+
+    /// Position the marker `key` is moving to.
+    pub(crate) fn target(&self, key: &K) -> Option<Position> {
+        self.transitions.get(key).map(|transition| transition.to)
+    }
+
+    /// Forget markers for which `keep` returns `false`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&K) -> bool) {
+        self.transitions.retain(|key, _| keep(key));
+    }
+}
+
+/// [`Marker`]s which move smoothly whenever their positions change. Each one is identified by a
+/// key, so that [`MarkerTransitions`] can track it between frames.
+pub struct AnimatedMarkers<'a, K> {
+    pub transitions: &'a mut MarkerTransitions<K>,
+    pub markers: Vec<(K, Marker)>,
+}
+
+impl<K: Hash + Eq> Plugin for AnimatedMarkers<'_, K> {
+    fn draw(&mut self, response: &Response, painter: Painter, projector: &Projector) {
+        let now = response.ctx.input(|input| input.time);
+        let mut moving = false;
+
+        for (key, marker) in self.markers.drain(..) {
+            let (position, marker_moving) = self.transitions.update(key, marker.position, now);
+            moving |= marker_moving;
+
+            Marker { position, ..marker }.draw(response, painter.clone(), projector);
+        }
+
+        if moving {
+            response.ctx.request_repaint();
+        }
+    }
+}
+
+/// Text placed at a position, like a name of a place. See [`Labels`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub position: Position,
+    pub text: String,
+    pub font: FontId,
+    pub color: Color32,
+
+    /// When labels overlap, the ones with higher priority are shown.
+    pub priority: i32,
+}
+
+impl Label {
+    pub fn new(position: Position, text: impl Into<String>) -> Self {
+        Self {
+            position,
+            text: text.into(),
+            font: FontId::default(),
+            color: Color32::BLACK,
+            priority: 0,
+        }
+    }
+}
+
+/// Layer of [`Label`]s, drawn to the right of their positions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Labels {
+    pub labels: Vec<Label>,
+
+    /// Hide labels which would overlap the ones with a higher priority, so that dense maps
+    /// stay legible. Enabled by default.
+    pub avoid_collisions: bool,
+}
+
+impl Labels {
+    pub fn new(labels: Vec<Label>) -> Self {
+        Self {
+            labels,
+            avoid_collisions: true,
+        }
+    }
+}
+
+/// Which of the labels, occupying given screen `rects` and having given `priorities`, are shown
+/// if they can not overlap. Ties are resolved in favour of the label which comes first.
+fn non_overlapping(rects: &[Rect], priorities: &[i32]) -> Vec<bool> {
+    let mut order: Vec<usize> = (0..rects.len()).collect();
+    order.sort_by_key(|&n| std::cmp::Reverse(priorities[n]));
+
+    let mut shown = vec![false; rects.len()];
+    let mut taken: Vec<Rect> = Vec::new();
+    for n in order {
+        if !taken.iter().any(|rect| rect.intersects(rects[n])) {
+            taken.push(rects[n]);
+            shown[n] = true;
+        }
+    }
+    shown
+}
+
+impl Plugin for Labels {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        // Labels need to be laid out to know how much space they take.
+        let shapes: Vec<Shape> = painter.fonts(|fonts| {
+            self.labels
+                .iter()
+                .map(|label| {
+                    Shape::text(
+                        fonts,
+                        projector.project(label.position).to_pos2(),
+                        Align2::LEFT_CENTER,
+                        &label.text,
+                        label.font.clone(),
+                        label.color.gamma_multiply(projector.opacity()),
+                    )
+                })
+                .collect()
+        });
+
+        let shown = if self.avoid_collisions {
+            let rects: Vec<Rect> = shapes.iter().map(Shape::visual_bounding_rect).collect();
+            let priorities: Vec<i32> = self.labels.iter().map(|label| label.priority).collect();
+            non_overlapping(&rects, &priorities)
+        } else {
+            vec![true; shapes.len()]
+        };
+
+        for (shape, shown) in shapes.into_iter().zip(shown) {
+            if shown {
+                painter.add(shape);
+            }
+        }
+    }
+}
+
+/// Line going through a number of positions, like a route.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polyline {
+    pub positions: Vec<Position>,
+
+    /// Line's width is interpreted according to `sizing`.
+    pub stroke: Stroke,
+    pub sizing: Sizing,
+
+    /// How segments are joined, which matters for thick lines.
+    pub join: LineJoin,
+
+    /// Color of each of the `positions`, blended along the segments between them, e.g. to show
+    /// the speed along a track. Overrides the `stroke` color and the `join`, as the segments are
+    /// joined with bevels then.
+    pub colors: Option<Vec<Color32>>,
+
+    /// Text drawn along the line, like a name of a road.
+    pub label: Option<LineLabel>,
+}
+
+/// Text of a [`Polyline`], placed over the middle of its longest visible segment and rotated to
+/// follow it, but never upside down. It is not drawn if the segment is too short to fit it, nor
+/// when the line is drawn as a part of a [`Batch`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineLabel {
+    pub text: String,
+    pub font: FontId,
+    pub color: Color32,
+}
+
+impl LineLabel {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            font: FontId::default(),
+            color: Color32::BLACK,
+        }
+    }
+}
+
+/// Shape of the corners of a [`Polyline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineJoin {
+    /// Sharp corners, extended until the outer edges meet.
+    #[default]
+    Miter,
+
+    /// Rounded corners and ends.
+    Round,
+
+    /// Corners cut off straight.
+    Bevel,
+}
+
+impl Polyline {
+    pub fn new(positions: Vec<Position>, stroke: Stroke) -> Self {
+        Self {
+            positions,
+            stroke,
+            sizing: Sizing::Screen,
+            join: LineJoin::Miter,
+            colors: None,
+            label: None,
+        }
+    }
+
+    pub fn with_join(self, join: LineJoin) -> Self {
+        Self { join, ..self }
+    }
+
+    /// Color the line with a gradient, see [`Polyline::colors`].
+    pub fn with_colors(self, colors: Vec<Color32>) -> Self {
+        Self {
+            colors: Some(colors),
+            ..self
+        }
+    }
+
+    pub fn with_label(self, label: LineLabel) -> Self {
+        Self {
+            label: Some(label),
+            ..self
+        }
+    }
+}
+
+/// Where text of given `size` goes along a line going through `points`: the top left corner
+/// of the text and its rotation. It's centered over the longest segment whose middle is within
+/// the `clip` rectangle, if the text fits there.
+fn label_placement(points: &[Pos2], clip: Rect, size: Vec2) -> Option<(Pos2, f32)> {
+    let (a, b) = points
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .filter(|(a, b)| clip.contains(a.lerp(*b, 0.5)))
+        .max_by(|(a, b), (c, d)| a.distance_sq(*b).total_cmp(&c.distance_sq(*d)))?;
+
+    if a.distance(b) < size.x {
+        return None;
+    }
+
+    let direction = b - a;
+    let mut angle = direction.y.atan2(direction.x);
+    // Keep the text upright.
+    if angle.abs() > std::f32::consts::FRAC_PI_2 {
+        angle -= std::f32::consts::PI.copysign(angle);
+    }
+
+    let corner = a.lerp(b, 0.5) - egui::emath::Rot2::from_angle(angle) * (size / 2.);
+    Some((corner, angle))
+}
+
+/// Mesh of a line of given `width` going through `points`, with each of them having its own
+/// color, blended along the segments. Segments are joined with bevels.
+fn gradient_line(points: &[Pos2], colors: &[Color32], width: f32) -> Mesh {
+    let radius = width / 2.;
+    let normal = |a: Pos2, b: Pos2| (b - a).normalized().rot90() * radius;
+    let mut mesh = Mesh::default();
+
+    for (pair, colors) in points.windows(2).zip(colors.windows(2)) {
+        let offset = normal(pair[0], pair[1]);
+        let index = mesh.vertices.len() as u32;
+        mesh.colored_vertex(pair[0] + offset, colors[0]);
+        mesh.colored_vertex(pair[0] - offset, colors[0]);
+        mesh.colored_vertex(pair[1] + offset, colors[1]);
+        mesh.colored_vertex(pair[1] - offset, colors[1]);
+        mesh.add_triangle(index, index + 1, index + 2);
+        mesh.add_triangle(index + 1, index + 2, index + 3);
+    }
+
+    for (triple, color) in points.windows(3).zip(colors.iter().skip(1)) {
+        let [before, corner, after] = [triple[0], triple[1], triple[2]];
+        let (incoming, outgoing) = (normal(before, corner), normal(corner, after));
+        for side in [1., -1.] {
+            let index = mesh.vertices.len() as u32;
+            mesh.colored_vertex(corner, *color);
+            mesh.colored_vertex(corner + incoming * side, *color);
+            mesh.colored_vertex(corner + outgoing * side, *color);
+            mesh.add_triangle(index, index + 1, index + 2);
+        }
+    }
+
+    mesh
+}
+
+/// Shapes of a thick line going through `points`, with segments joined according to `join`.
+/// Unless it's a miter, segments have flat ends, and the joins fill the gaps between them, so
+/// that each is antialiased by egui.
+fn joined_line(points: &[Pos2], stroke: Stroke, join: LineJoin) -> Vec<Shape> {
+    if join == LineJoin::Miter {
+        return vec![Shape::line(points.to_vec(), stroke)];
+    }
+
+    let radius = stroke.width / 2.;
+    let mut shapes: Vec<Shape> = points
+        .windows(2)
+        .map(|pair| Shape::line_segment([pair[0], pair[1]], stroke))
+        .collect();
+
+    match join {
+        LineJoin::Miter => {}
+        LineJoin::Round => {
+            shapes.extend(
+                points
+                    .iter()
+                    .map(|point| Shape::circle_filled(*point, radius, stroke.color)),
+            );
+        }
+        LineJoin::Bevel => {
+            for triple in points.windows(3) {
+                let [before, corner, after] = [triple[0], triple[1], triple[2]];
+                let normal = |a: Pos2, b: Pos2| (b - a).normalized().rot90() * radius;
+                let (incoming, outgoing) = (normal(before, corner), normal(corner, after));
+
+                // Gap is on the outer side of the corner, but filling both is simpler than
+                // checking which one is it.
+                for side in [1., -1.] {
+                    shapes.push(Shape::convex_polygon(
+                        vec![corner, corner + incoming * side, corner + outgoing * side],
+                        stroke.color,
+                        Stroke::NONE,
+                    ));
+                }
+            }
+        }
+    }
+
+    shapes
+}
+
+/// Result of [`Polyline::hit_test`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolylineHit {
+    /// Index of the hit segment, which goes from the position of this index to the next one.
+    pub segment: usize,
+
+    /// Point of the segment nearest to the tested one.
+    pub position: Position,
+
+    /// Distance to it, in screen pixels.
+    pub distance: f32,
+}
+
+impl Polyline {
+    /// Check whether `screen_position` (e.g. of a click) is within `tolerance` pixels of the line,
+    /// as drawn by the `projector`. If so, returns the nearest segment and its point.
+    pub fn hit_test(
+        &self,
+        screen_position: Pos2,
+        projector: &Projector,
+        tolerance: f32,
+    ) -> Option<PolylineHit> {
+        let points: Vec<Pos2> = self
+            .positions
+            .iter()
+            .map(|position| projector.project(*position).to_pos2())
+            .collect();
+
+        points
+            .windows(2)
+            .enumerate()
+            .map(|(segment, pair)| {
+                let nearest = nearest_on_segment(screen_position, pair[0], pair[1]);
+                (segment, nearest, nearest.distance(screen_position))
+            })
+            .filter(|(_, _, distance)| *distance <= tolerance)
+            .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+            .map(|(segment, nearest, distance)| PolylineHit {
+                segment,
+                position: projector.unproject(nearest),
+                distance,
+            })
+    }
+}
+
+/// Point of the segment from `a` to `b` which is the nearest to `point`.
+fn nearest_on_segment(point: Pos2, a: Pos2, b: Pos2) -> Pos2 {
+    let ab = b - a;
+    let length_sq = ab.length_sq();
+    if length_sq == 0. {
+        return a;
+    }
+    let t = ((point - a).dot(ab) / length_sq).clamp(0., 1.);
+    a + ab * t
+}
+
+impl Plugin for Polyline {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        painter.add(self.shape(projector));
+
+        let Some(label) = &self.label else {
+            return;
+        };
+
+        let galley = painter.layout_no_wrap(
+            label.text.clone(),
+            label.font.clone(),
+            label.color.gamma_multiply(projector.opacity()),
+        );
+        let points: Vec<Pos2> = self
+            .positions
+            .iter()
+            .map(|position| projector.project(*position).to_pos2())
+            .collect();
+
+        if let Some((corner, angle)) = label_placement(&points, painter.clip_rect(), galley.size())
+        {
+            painter.add(egui::epaint::TextShape {
+                angle,
+                ..egui::epaint::TextShape::new(corner, galley)
+            });
+        }
+    }
+}
+
+impl ToShape for Polyline {
+    fn shape(&self, projector: &Projector) -> Shape {
+        let Some(first) = self.positions.first() else {
+            return Shape::Noop;
+        };
+
+        let stroke = Stroke::new(
+            self.sizing.to_pixels(self.stroke.width, *first, projector),
+            self.stroke.color.gamma_multiply(projector.opacity()),
+        );
+
+        let points: Vec<Pos2> = self
+            .positions
+            .iter()
+            .map(|position| projector.project(*position).to_pos2())
+            .collect();
+
+        if let Some(colors) = &self.colors {
+            let colors: Vec<Color32> = colors
+                .iter()
+                .map(|color| color.gamma_multiply(projector.opacity()))
+                .collect();
+            return Shape::mesh(gradient_line(&points, &colors, stroke.width));
+        }
+
+        match self.join {
+            LineJoin::Miter => Shape::line(points, stroke),
+            join => Shape::Vec(joined_line(&points, stroke, join)),
+        }
+    }
+}
+
+/// Filled area, like a building's footprint. Vertices are geographical positions, so the area
+/// itself always scales with the map, `sizing` applies to the outline's width.
+///
+/// Note that only convex polygons are filled correctly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub positions: Vec<Position>,
+    pub fill: Color32,
+    pub stroke: Stroke,
+    pub sizing: Sizing,
+}
+
+impl Polygon {
+    pub fn new(positions: Vec<Position>, fill: Color32, stroke: Stroke) -> Self {
+        Self {
+            positions,
+            fill,
+            stroke,
+            sizing: Sizing::Screen,
+        }
+    }
+}
+
+impl Plugin for Polygon {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        painter.add(self.shape(projector));
+    }
+}
+
+impl ToShape for Polygon {
+    fn shape(&self, projector: &Projector) -> Shape {
+        let Some(first) = self.positions.first() else {
+            return Shape::Noop;
+        };
+
+        let stroke = Stroke::new(
+            self.sizing.to_pixels(self.stroke.width, *first, projector),
+            self.stroke.color.gamma_multiply(projector.opacity()),
+        );
+
+        Shape::convex_polygon(
+            self.positions
+                .iter()
+                .map(|position| projector.project(*position).to_pos2())
+                .collect(),
+            self.fill.gamma_multiply(projector.opacity()),
+            stroke,
+        )
+    }
+}
+
+/// Fading trail of recently visited positions, like a GPS history. Oldest position comes first,
+/// and it fades to fully transparent towards it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trail<'a> {
+    pub positions: &'a [Position],
+
+    /// Color and width of the newest segment.
+    pub stroke: Stroke,
+
+    /// Only this many newest positions are drawn.
+    pub max_points: usize,
+}
+
+impl<'a> Trail<'a> {
+    pub fn new(positions: &'a [Position], stroke: Stroke) -> Self {
+        Self {
+            positions,
+            stroke,
+            max_points: 100,
+        }
+    }
+
+    /// Positions which will actually be drawn.
+    fn visible(&self) -> &'a [Position] {
+        let skip = self.positions.len().saturating_sub(self.max_points);
+        &self.positions[skip..]
+    }
+}
+
+/// Opacity of `n`-th out of `count` segments, linearly growing from transparent to opaque. A
+/// lone segment is opaque.
+fn trail_alpha(n: usize, count: usize) -> f32 {
+    if count < 2 {
+        1.
+    } else {
+        n as f32 / (count - 1) as f32
+    }
+}
+
+impl Plugin for Trail<'_> {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        let positions = self.visible();
+        let segments = positions.len().saturating_sub(1);
+
+        for (n, pair) in positions.windows(2).enumerate() {
+            let alpha = trail_alpha(n, segments) * projector.opacity();
+            painter.line_segment(
+                [
+                    projector.project(pair[0]).to_pos2(),
+                    projector.project(pair[1]).to_pos2(),
+                ],
+                Stroke::new(self.stroke.width, self.stroke.color.gamma_multiply(alpha)),
+            );
+        }
+    }
+}
+
+/// Image draped over the map, like a scanned paper map or an aerial photo (often called a ground
+/// overlay). Image is warped to fit its corners, which makes it possible to also use rotated or
+/// skewed images.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageOverlay {
+    pub texture: TextureId,
+
+    /// Positions of image's north-west, north-east, south-east and south-west corners.
+    pub corners: [Position; 4],
+
+    /// Color which image is multiplied by. Use it to make the image semi-transparent.
+    pub tint: Color32,
+}
+
+impl ImageOverlay {
+    /// Image covering the `bounds`, with its top edge at the north.
+    pub fn new(texture: TextureId, bounds: BoundingBox) -> Self {
+        Self::from_corners(
+            texture,
+            [
+                Position::new(bounds.west, bounds.north),
+                Position::new(bounds.east, bounds.north),
+                Position::new(bounds.east, bounds.south),
+                Position::new(bounds.west, bounds.south),
+            ],
+        )
+    }
+
+    /// Image pinned to north-west, north-east, south-east and south-west `corners`.
+    pub fn from_corners(texture: TextureId, corners: [Position; 4]) -> Self {
+        Self {
+            texture,
+            corners,
+            tint: Color32::WHITE,
+        }
+    }
+}
+
+impl Plugin for ImageOverlay {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        let uvs = [pos2(0., 0.), pos2(1., 0.), pos2(1., 1.), pos2(0., 1.)];
+
+        let mut mesh = Mesh::with_texture(self.texture);
+        for (corner, uv) in self.corners.iter().zip(uvs) {
+            mesh.vertices.push(Vertex {
+                pos: projector.project(*corner).to_pos2(),
+                uv,
+                color: self.tint.gamma_multiply(projector.opacity()),
+            });
+        }
+        mesh.add_triangle(0, 1, 2);
+        mesh.add_triangle(0, 2, 3);
+
+        painter.add(mesh);
+    }
+}
+
+/// Grid of arrows, like a wind or a sea current map. Each vector is a `(position, direction,
+/// magnitude)` tuple, where direction is given in degrees, clockwise from the north, and points
+/// where the arrow points to. Arrows are centered at their positions.
+#[derive(Debug, Clone)]
+pub struct VectorFieldLayer<'a> {
+    pub vectors: &'a [(Position, f32, f32)],
+
+    /// Arrow's length per unit of magnitude, interpreted according to `sizing`.
+    pub scale: f32,
+    pub sizing: Sizing,
+
+    /// Color and width of arrows which are not colored by magnitude.
+    pub stroke: Stroke,
+
+    /// If set, arrows are colored by their magnitude using this function instead.
+    pub color_by_magnitude: Option<fn(f32) -> Color32>,
+}
+
+impl<'a> VectorFieldLayer<'a> {
+    pub fn new(vectors: &'a [(Position, f32, f32)], stroke: Stroke) -> Self {
+        Self {
+            vectors,
+            scale: 10.,
+            sizing: Sizing::Screen,
+            stroke,
+            color_by_magnitude: None,
+        }
+    }
+}
+
+/// Line segments of an arrow of given `length`, centered at `center`. Shaft comes first.
+fn arrow(center: Pos2, direction: f32, length: f32) -> [[Pos2; 2]; 3] {
+    let (sin, cos) = direction.to_radians().sin_cos();
+    // Screen's y axis points to the south.
+    let along = egui::vec2(sin, -cos) * length;
+    let tail = center - along / 2.;
+    let tip = center + along / 2.;
+
+    let head = along * 0.3;
+    let rotate = |v: egui::Vec2, angle: f32| {
+        let (sin, cos) = angle.sin_cos();
+        egui::vec2(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+    };
+    let barb = std::f32::consts::FRAC_PI_6;
+
+    [
+        [tail, tip],
+        [tip, tip - rotate(head, barb)],
+        [tip, tip - rotate(head, -barb)],
+    ]
+}
+
+impl Plugin for VectorFieldLayer<'_> {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        for (position, direction, magnitude) in self.vectors {
+            let length = self
+                .sizing
+                .to_pixels(self.scale * magnitude, *position, projector);
+            let center = projector.project(*position).to_pos2();
+
+            if !projector.clip_rect().expand(length).contains(center) {
+                continue;
+            }
+
+            let color = match self.color_by_magnitude {
+                Some(color) => color(*magnitude),
+                None => self.stroke.color,
+            };
+            let stroke = Stroke::new(self.stroke.width, color.gamma_multiply(projector.opacity()));
+
+            for segment in arrow(center, *direction, length) {
+                painter.line_segment(segment, stroke);
+            }
+        }
+    }
+}
+
+/// Grid of meridians and parallels, labeled along the top and left edges of the map. The
+/// interval between the lines (e.g. 10°, 1°, 0.1°) is chosen according to the zoom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Graticule {
+    /// Lines. Labels are drawn in the same color.
+    pub stroke: Stroke,
+    pub font: FontId,
+}
+
+impl Graticule {
+    pub fn new(stroke: Stroke) -> Self {
+        Self {
+            stroke,
+            font: FontId::proportional(10.),
+        }
+    }
+}
+
+/// Minimum distance between the lines of a [`Graticule`], in screen pixels.
+const GRATICULE_SPACING: f64 = 100.;
+
+/// Intervals of the [`Graticule`] lines, in degrees, from the widest, along with the number of
+/// fractional digits their labels need.
+const GRATICULE_INTERVALS: [(f64, usize); 13] = [
+    (30., 0),
+    (10., 0),
+    (5., 0),
+    (1., 0),
+    (0.5, 1),
+    (0.1, 1),
+    (0.05, 2),
+    (0.01, 2),
+    (0.005, 3),
+    (0.001, 3),
+    (0.0005, 4),
+    (0.0001, 4),
+    (0.00005, 5),
+];
+
+/// Narrowest of the [`GRATICULE_INTERVALS`] which still keeps the lines [`GRATICULE_SPACING`]
+/// apart at given zoom.
+fn graticule_interval(zoom: u8) -> (f64, usize) {
+    let pixels_per_degree = TILE_SIZE as f64 * 2f64.powi(zoom as i32) / 360.;
+    GRATICULE_INTERVALS
+        .into_iter()
+        .take_while(|(interval, _)| interval * pixels_per_degree >= GRATICULE_SPACING)
+        .last()
+        .unwrap_or(GRATICULE_INTERVALS[0])
+}
+
+/// Multiples of `interval` between `from` and `to`.
+fn multiples(from: f64, to: f64, interval: f64) -> impl Iterator<Item = f64> {
+    let first = (from / interval).ceil() as i64;
+    let last = (to / interval).floor() as i64;
+    (first..=last).map(move |n| n as f64 * interval)
+}
+
+impl Plugin for Graticule {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        let rect = projector.clip_rect();
+        let north_west = projector.unproject(rect.left_top());
+        let south_east = projector.unproject(rect.right_bottom());
+        let (interval, precision) = graticule_interval(projector.zoom());
+
+        let color = self.stroke.color.gamma_multiply(projector.opacity());
+        let stroke = Stroke::new(self.stroke.width, color);
+
+        // Meridians and parallels are straight lines in the Web Mercator.
+        for longitude in multiples(north_west.x(), south_east.x(), interval) {
+            let x = projector.project(Position::new(longitude, 0.)).x;
+            painter.vline(x, rect.y_range(), stroke);
+            painter.text(
+                pos2(x + 2., rect.top() + 2.),
+                Align2::LEFT_TOP,
+                format!("{longitude:.precision$}°"),
+                self.font.clone(),
+                color,
+            );
+        }
+
+        let south = south_east.y().max(-MAX_LATITUDE);
+        let north = north_west.y().min(MAX_LATITUDE);
+        for latitude in multiples(south, north, interval) {
+            let y = projector.project(Position::new(0., latitude)).y;
+            painter.hline(rect.x_range(), y, stroke);
+            painter.text(
+                pos2(rect.left() + 2., y - 2.),
+                Align2::LEFT_BOTTOM,
+                format!("{latitude:.precision$}°"),
+                self.font.clone(),
+                color,
+            );
+        }
+    }
+}
+
+/// Arrow pointing to the north, with an "N" above it, in the top-right corner of the map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NorthArrow {
+    /// Length of the arrow, in screen pixels.
+    pub size: f32,
+    pub stroke: Stroke,
+}
+
+impl NorthArrow {
+    pub fn new() -> Self {
+        Self {
+            size: 24.,
+            stroke: Stroke::new(2., Color32::BLACK),
+        }
+    }
+}
+
+impl Default for NorthArrow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for NorthArrow {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        let rect = projector.clip_rect();
+        let font = FontId::proportional(self.size / 2.);
+        let margin = 8.;
+        let center = rect.right_top()
+            + egui::vec2(-margin - font.size / 2., margin + font.size)
+            + egui::vec2(0., self.size / 2.);
+
+        let color = self.stroke.color.gamma_multiply(projector.opacity());
+        let stroke = Stroke::new(self.stroke.width, color);
+        for segment in arrow(center, 0., self.size) {
+            painter.line_segment(segment, stroke);
+        }
+
+        painter.text(
+            center - egui::vec2(0., self.size / 2.),
+            Align2::CENTER_BOTTOM,
+            "N",
+            font,
+            color,
+        );
+    }
+}
+
+/// Fixed crosshair in the middle of the map, e.g. for placing a pin precisely, without clicking.
+/// Position it points at ends up in `center`.
+pub struct Crosshair<'a> {
+    /// Length of each of the arms, in screen pixels.
+    pub size: f32,
+    pub stroke: Stroke,
+
+    /// Show the coordinates of the center below the crosshair, written in given format.
+    pub coordinates: Option<CoordinateFormat>,
+
+    /// Position in the middle of the map, as of the most recent frame.
+    pub center: &'a mut Option<Position>,
+}
+
+impl<'a> Crosshair<'a> {
+    pub fn new(center: &'a mut Option<Position>) -> Self {
+        Self {
+            size: 12.,
+            stroke: Stroke::new(2., Color32::BLACK),
+            coordinates: None,
+            center,
+        }
+    }
+
+    /// Show the coordinates of the center, see [`Crosshair::coordinates`].
+    pub fn with_coordinates(self, format: CoordinateFormat) -> Self {
+        Self {
+            coordinates: Some(format),
+            ..self
+        }
+    }
+}
+
+impl Plugin for Crosshair<'_> {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        let center = projector.clip_rect().center();
+        let position = projector.unproject(center);
+        *self.center = Some(position);
+
+        // Leave the very center empty, so that it's visible what's under it.
+        let gap = self.size / 3.;
+        let stroke = Stroke::new(
+            self.stroke.width,
+            self.stroke.color.gamma_multiply(projector.opacity()),
+        );
+        for direction in [
+            egui::vec2(1., 0.),
+            egui::vec2(-1., 0.),
+            egui::vec2(0., 1.),
+            egui::vec2(0., -1.),
+        ] {
+            painter.line_segment(
+                [
+                    center + direction * gap,
+                    center + direction * (gap + self.size),
+                ],
+                stroke,
+            );
+        }
+
+        if let Some(format) = self.coordinates {
+            let text = painter.layout_no_wrap(
+                position.format(format),
+                FontId::proportional(12.),
+                stroke.color,
+            );
+            let rect = Align2::CENTER_TOP
+                .anchor_rect(Rect::from_min_size(
+                    center + egui::vec2(0., gap + self.size + 4.),
+                    text.size(),
+                ))
+                .expand(2.);
+            painter.rect_filled(rect, 2., Color32::WHITE.gamma_multiply(0.7));
+            painter.galley(rect.min + egui::vec2(2., 2.), text);
+        }
+    }
+}
+
+/// Live egui widgets (e.g. buttons or sliders) placed at a geographical position, moving along
+/// with the map. They are shown in a separate [`Area`], so interacting with them does not pan
+/// the map. Hidden when the position is off the map.
+pub struct WidgetAnchor<'a> {
+    pub id: Id,
+    pub position: Position,
+
+    /// Which point of the widgets is placed at the `position`. [`Align2::CENTER_CENTER`] by
+    /// default.
+    pub pivot: Align2,
+
+    add_contents: Option<Box<AddContents<'a>>>,
+}
+
+type AddContents<'a> = dyn FnOnce(&mut Ui) + 'a;
+
+impl<'a> WidgetAnchor<'a> {
+    /// `id_source` needs to be unique among all widgets anchored to the map.
+    pub fn new(
+        id_source: impl Hash,
+        position: Position,
+        add_contents: impl FnOnce(&mut Ui) + 'a,
+    ) -> Self {
+        Self {
+            id: Id::new(("walkers_widget_anchor", id_source)),
+            position,
+            pivot: Align2::CENTER_CENTER,
+            add_contents: Some(Box::new(add_contents)),
+        }
+    }
+
+    pub fn with_pivot(self, pivot: Align2) -> Self {
+        Self { pivot, ..self }
+    }
+}
+
+impl Plugin for WidgetAnchor<'_> {
+    fn draw(&mut self, response: &Response, _painter: Painter, projector: &Projector) {
+        let screen_position = projector.project(self.position).to_pos2();
+        if !projector.clip_rect().contains(screen_position) {
+            return;
+        }
+
+        if let Some(add_contents) = self.add_contents.take() {
+            area_over_map(self.id, response, screen_position, self.pivot).show(
+                &response.ctx,
+                |ui| {
+                    ui.set_clip_rect(projector.clip_rect());
+                    add_contents(ui);
+                },
+            );
+        }
+    }
+}
+
+/// Overlay which can be turned into a plain egui [`Shape`], and therefore drawn in a [`Batch`].
+pub trait ToShape {
+    /// Shape in screen coordinates, as drawn by the `projector`.
+    fn shape(&self, projector: &Projector) -> Shape;
+}
+
+/// Layer of many shapes, tessellated into a single [`Mesh`]. It draws the same as a `Vec` of them,
+/// but is much cheaper when there are thousands: the mesh is kept between frames and only moved
+/// when the map is panned. It gets tessellated again only when zoom or opacity changes, or the
+/// shapes are modified via [`Batch::shapes_mut`].
+///
+/// Keep the batch between frames and add it with `Map::with_plugin(&mut batch)`, otherwise the
+/// mesh is not reused.
+pub struct Batch<T> {
+    shapes: Vec<T>,
+    cache: Option<BatchCache>,
+}
+
+/// Mesh of a [`Batch`] and what it was tessellated for.
+struct BatchCache {
+    zoom: u8,
+    opacity: f32,
+    pixels_per_point: f32,
+
+    /// Position which was at `screen_anchor` when tessellating.
+    anchor: Position,
+    screen_anchor: Pos2,
+
+    mesh: Mesh,
+}
+
+impl<T> Batch<T> {
+    pub fn new(shapes: Vec<T>) -> Self {
+        Self {
+            shapes,
+            cache: None,
+        }
+    }
+
+    pub fn shapes(&self) -> &[T] {
+        &self.shapes
+    }
+
+    /// Modify the shapes, which makes them be tessellated again in the next frame.
+    pub fn shapes_mut(&mut self) -> &mut Vec<T> {
+        self.cache = None;
+        &mut self.shapes
+    }
+}
+
+impl<T: ToShape> Batch<T> {
+    fn tessellate(&self, painter: &Painter, projector: &Projector) -> Mesh {
+        let ctx = painter.ctx();
+        let mut tessellator = Tessellator::new(
+            ctx.pixels_per_point(),
+            ctx.tessellation_options(|options| *options),
+            ctx.fonts(|fonts| fonts.font_image_size()),
+            Vec::new(),
+        );
+
+        let mut mesh = Mesh::default();
+        for shape in &self.shapes {
+            tessellator.tessellate_shape(shape.shape(projector), &mut mesh);
+        }
+        mesh
+    }
+}
+
+impl<T: ToShape> Plugin for Batch<T> {
+    fn draw(&mut self, _response: &Response, painter: Painter, projector: &Projector) {
+        let pixels_per_point = painter.ctx().pixels_per_point();
+        let valid = self.cache.as_ref().is_some_and(|cache| {
+            cache.zoom == projector.zoom()
+                && cache.opacity == projector.opacity()
+                && cache.pixels_per_point == pixels_per_point
+        });
+
+        if !valid {
+            let screen_anchor = projector.clip_rect().center();
+            self.cache = Some(BatchCache {
+                zoom: projector.zoom(),
+                opacity: projector.opacity(),
+                pixels_per_point,
+                anchor: projector.unproject(screen_anchor),
+                screen_anchor,
+                mesh: self.tessellate(&painter, projector),
+            });
+        }
+
+        if let Some(cache) = &self.cache {
+            let mut mesh = cache.mesh.clone();
+            mesh.translate(projector.project(cache.anchor).to_pos2() - cache.screen_anchor);
+            painter.add(mesh);
+        }
+    }
+}
+
+/// Draws the `plugin` only at some zoom levels, e.g. detailed labels when zoomed in far enough.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoomRange<T> {
+    pub plugin: T,
+    pub zooms: RangeInclusive<u8>,
+}
+
+impl<T> ZoomRange<T> {
+    pub fn new(plugin: T, zooms: RangeInclusive<u8>) -> Self {
+        Self { plugin, zooms }
+    }
+}
+
+impl<T: Plugin> Plugin for ZoomRange<T> {
+    fn draw(&mut self, response: &Response, painter: Painter, projector: &Projector) {
+        self.plugin.draw(response, painter, projector);
+    }
+
+    fn visible_zooms(&self) -> RangeInclusive<u8> {
+        let inner = self.plugin.visible_zooms();
+        *self.zooms.start().max(inner.start())..=*self.zooms.end().min(inner.end())
+    }
+}
+
+/// Layer of many shapes of the same kind.
+impl<T: Plugin> Plugin for Vec<T> {
+    fn draw(&mut self, response: &Response, painter: Painter, projector: &Projector) {
+        for plugin in self {
+            if plugin.visible_zooms().contains(&projector.zoom()) {
+                plugin.draw(response, painter.clone(), projector);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mesh_colors, pointer_button, texts, MapHarness};
+    use crate::Center;
+    use egui::{Event, FullOutput};
+
+    #[test]
+    fn joining_thick_lines() {
+        let points = [pos2(0., 0.), pos2(10., 0.), pos2(10., 10.)];
+        let stroke = Stroke::new(4., Color32::RED);
+
+        let shapes = joined_line(&points, stroke, LineJoin::Round);
+        let circles: Vec<_> = shapes
+            .iter()
+            .filter_map(|shape| match shape {
+                Shape::Circle(circle) => Some(circle.center),
+                _ => None,
+            })
+            .collect();
+        // Both ends and the corner.
+        assert_eq!(points.to_vec(), circles);
+
+        let shapes = joined_line(&points, stroke, LineJoin::Bevel);
+        let outer = shapes.iter().find_map(|shape| match shape {
+            Shape::Path(path) if path.points.contains(&pos2(12., 0.)) => Some(path),
+            _ => None,
+        });
+        // Triangle cutting the outer corner.
+        assert_eq!(
+            vec![pos2(10., 0.), pos2(10., -2.), pos2(12., 0.)],
+            outer.unwrap().points
+        );
+    }
+
+    #[test]
+    fn gradient_line_blends_colors_of_points() {
+        let points = [pos2(0., 0.), pos2(10., 0.), pos2(10., 10.)];
+        let colors = [Color32::RED, Color32::GREEN, Color32::BLUE];
+
+        let mesh = gradient_line(&points, &colors, 4.);
+        assert!(mesh.is_valid());
+
+        // Two quads, and two triangles filling the corner.
+        assert_eq!(4 * 2 + 3 * 2, mesh.vertices.len());
+        let color_at = |pos: Pos2| {
+            mesh.vertices
+                .iter()
+                .find(|vertex| vertex.pos == pos)
+                .map(|vertex| vertex.color)
+        };
+        assert_eq!(Some(Color32::RED), color_at(pos2(0., 2.)));
+        assert_eq!(Some(Color32::BLUE), color_at(pos2(12., 10.)));
+        assert!(mesh.vertices[2..4]
+            .iter()
+            .all(|vertex| vertex.color == Color32::GREEN));
+    }
+
+    #[test]
+    fn hit_testing_polyline() {
+        let memory = crate::MapMemory::with_view(Position::new(0., 0.), 10.).unwrap();
+        let projector = memory
+            .projector(Rect::from_min_size(Pos2::ZERO, egui::vec2(800., 600.)))
+            .unwrap();
+        let center = pos2(400., 300.);
+
+        // L-shaped line, with the corner in the middle of the screen.
+        let polyline = Polyline::new(
+            vec![
+                projector.unproject(center - egui::vec2(100., 0.)),
+                projector.unproject(center),
+                projector.unproject(center + egui::vec2(0., 100.)),
+            ],
+            Stroke::new(2., Color32::RED),
+        );
+
+        let hit = polyline
+            .hit_test(center + egui::vec2(-50., 3.), &projector, 5.)
+            .unwrap();
+        assert_eq!(0, hit.segment);
+        approx::assert_relative_eq!(3., hit.distance, epsilon = 0.01);
+
+        let hit = polyline
+            .hit_test(center + egui::vec2(4., 60.), &projector, 5.)
+            .unwrap();
+        assert_eq!(1, hit.segment);
+
+        assert_eq!(
+            None,
+            polyline.hit_test(center + egui::vec2(-50., 10.), &projector, 5.)
+        );
+    }
+
+    #[test]
+    fn arrows_point_in_their_direction() {
+        let center = pos2(100., 100.);
+
+        let [shaft, ..] = arrow(center, 0., 20.);
+        approx::assert_relative_eq!(shaft[0].y, 110.);
+        approx::assert_relative_eq!(shaft[1].y, 90.);
+
+        let [shaft, left, right] = arrow(center, 90., 20.);
+        approx::assert_relative_eq!(shaft[0].x, 90.);
+        approx::assert_relative_eq!(shaft[1].x, 110.);
+
+        // Head's barbs go back from the tip, on both sides of the shaft.
+        assert!(left[1].x < 110. && right[1].x < 110.);
+        assert!((left[1].y - 100.) * (right[1].y - 100.) < 0.);
+    }
+
+    #[test]
+    fn rotated_marker_points_towards_its_heading() {
+        let center = pos2(100., 100.);
+
+        let [tip, left, right] = arrowhead(center, 0., 10.);
+        approx::assert_relative_eq!(tip.y, 90.);
+        assert!(left.y > 100. && right.y > 100.);
+
+        let [tip, left, right] = arrowhead(center, 90., 10.);
+        approx::assert_relative_eq!(tip.x, 110.);
+        assert!(left.x < 100. && right.x < 100.);
+    }
+
+    #[test]
+    fn graticule_gets_denser_when_zooming_in() {
+        assert_eq!((30., 0), graticule_interval(0));
+        assert_eq!((30., 0), graticule_interval(3));
+        assert_eq!((5., 0), graticule_interval(5));
+        assert_eq!((1., 0), graticule_interval(8));
+        assert_eq!((0.5, 1), graticule_interval(10));
+        assert_eq!((0.05, 2), graticule_interval(12));
+        assert_eq!((0.005, 3), graticule_interval(15));
+
+        assert_eq!(
+            [10., 20., 30.],
+            multiples(5., 35., 10.).collect::<Vec<_>>().as_slice()
+        );
+        assert_eq!(
+            [-10., 0.],
+            multiples(-15., 0., 10.).collect::<Vec<_>>().as_slice()
+        );
+    }
+
+    #[test]
+    fn marker_moves_smoothly_to_new_position() {
+        let mut transitions = MarkerTransitions::new(2.);
+        let start = Position::new(10., 20.);
+        let end = Position::new(20., 40.);
+
+        assert_eq!((start, false), transitions.update("car", start, 0.));
+        assert_eq!((start, false), transitions.update("car", start, 1.));
+
+        // Target changes, so the marker starts moving.
+        assert_eq!((start, true), transitions.update("car", end, 1.));
+        assert_eq!(
+            (Position::new(15., 30.), true),
+            transitions.update("car", end, 2.)
+        );
+        assert_eq!((end, false), transitions.update("car", end, 3.));
+    }
+
+    #[test]
+    fn marker_movement_is_eased() {
+        let mut transitions = MarkerTransitions::new(2.).with_easing(Easing::EaseOut);
+        let start = Position::new(0., 0.);
+        let end = Position::new(8., 0.);
+
+        transitions.update("car", start, 0.);
+        transitions.update("car", end, 0.);
+
+        // Half of the time passed, but it's already past the half of the way.
+        let (position, moving) = transitions.update("car", end, 1.);
+        approx::assert_relative_eq!(7., position.x());
+        assert!(moving);
+    }
+
+    #[test]
+    fn line_label_goes_over_the_longest_visible_segment() {
+        let clip = Rect::from_min_max(pos2(0., 0.), pos2(100., 100.));
+        let size = Vec2::new(20., 10.);
+
+        // Longest segment is outside of the clip rectangle, and the text goes right to left.
+        let points = [pos2(90., 50.), pos2(50., 50.), pos2(300., 300.)];
+        let (corner, angle) = label_placement(&points, clip, size).unwrap();
+        assert_eq!(angle, 0.);
+        assert_eq!(corner, pos2(60., 45.));
+
+        // Going straight down.
+        let (corner, angle) =
+            label_placement(&[pos2(50., 10.), pos2(50., 90.)], clip, size).unwrap();
+        approx::assert_relative_eq!(angle, std::f32::consts::FRAC_PI_2);
+        approx::assert_relative_eq!(corner.x, 55.);
+        approx::assert_relative_eq!(corner.y, 40.);
+
+        // Too short to fit the text.
+        assert!(label_placement(&[pos2(50., 50.), pos2(60., 50.)], clip, size).is_none());
+    }
+
+    #[test]
+    fn overlapping_labels_with_lower_priority_are_hidden() {
+        let rect = |x: f32| Rect::from_min_size(pos2(x, 0.), egui::vec2(10., 10.));
+        let rects = [rect(0.), rect(5.), rect(12.), rect(20.)];
+
+        // Second one overlaps the first and the third one, and wins with both.
+        assert_eq!(
+            vec![false, true, false, true],
+            non_overlapping(&rects, &[0, 1, 0, 0])
+        );
+
+        // Equal priorities, so the earlier ones win.
+        assert_eq!(
+            vec![true, false, true, false],
+            non_overlapping(&rects, &[0, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn trail_is_capped_and_fades_out() {
+        let positions: Vec<_> = (0..10).map(|n| Position::new(n as f64, 0.)).collect();
+        let trail = Trail {
+            max_points: 4,
+            ..Trail::new(&positions, Stroke::new(1., Color32::RED))
+        };
+
+        assert_eq!(&positions[6..], trail.visible());
+
+        // Three segments, fading from fully transparent to fully opaque.
+        approx::assert_relative_eq!(0., trail_alpha(0, 3));
+        approx::assert_relative_eq!(0.5, trail_alpha(1, 3));
+        approx::assert_relative_eq!(1., trail_alpha(2, 3));
+        approx::assert_relative_eq!(1., trail_alpha(0, 1));
+    }
+
+    fn wroclaw() -> Position {
+        Position::new(17.03664, 51.09916)
+    }
+
+    #[test]
+    fn crosshair_points_at_the_center() {
+        let mut harness = MapHarness::new(wroclaw());
+        let mut center = None;
+        let output = harness.run_with(Vec::new(), |map| {
+            map.with_plugin(
+                Crosshair::new(&mut center).with_coordinates(CoordinateFormat::default()),
+            )
+        });
+
+        let center = center.unwrap();
+        approx::assert_relative_eq!(center.x(), wroclaw().x(), epsilon = 1e-5);
+        approx::assert_relative_eq!(center.y(), wroclaw().y(), epsilon = 1e-5);
+        assert!(texts(&output)
+            .iter()
+            .any(|(text, _)| text == &center.format(CoordinateFormat::default())));
+    }
+
+    #[test]
+    fn batch_is_drawn_as_a_single_mesh() {
+        let lines: Vec<_> = (0..100)
+            .map(|n| {
+                let offset = n as f64 * 0.0001;
+                Polyline::new(
+                    vec![
+                        Position::new(17.03 + offset, 51.09),
+                        Position::new(17.04 + offset, 51.1),
+                    ],
+                    egui::Stroke::new(2., Color32::RED),
+                )
+            })
+            .collect();
+
+        let mut harness = MapHarness::new(wroclaw());
+        let tiles = harness.run(Vec::new()).shapes.len();
+
+        let output = harness.run_with(Vec::new(), |map| map.with_plugin(lines.clone()));
+        assert_eq!(tiles + 100, output.shapes.len());
+
+        let mut batch = Batch::new(lines);
+        let output = harness.run_with(Vec::new(), |map| map.with_plugin(&mut batch));
+        assert_eq!(tiles + 1, output.shapes.len());
+        assert!(mesh_colors(&output).contains(&Color32::RED));
+
+        // Cached mesh follows the map when it's panned.
+        let vertex = |output: &FullOutput| match &output.shapes.last().unwrap().1 {
+            egui::Shape::Mesh(mesh) => mesh.vertices[0].pos,
+            _ => panic!("batch is not a mesh"),
+        };
+        let before = vertex(&harness.run_with(Vec::new(), |map| map.with_plugin(&mut batch)));
+        harness.memory.pan_by(Vec2::new(10., 5.));
+        let after = vertex(&harness.run_with(Vec::new(), |map| map.with_plugin(&mut batch)));
+        approx::assert_abs_diff_eq!(before.x - 10., after.x, epsilon = 0.1);
+        approx::assert_abs_diff_eq!(before.y - 5., after.y, epsilon = 0.1);
+    }
+
+    #[test]
+    fn clicking_marker() {
+        let mut harness = MapHarness::new(wroclaw());
+        let center = harness.screen_center();
+        let mut clicked = None;
+
+        for events in [
+            vec![Event::PointerMoved(center)],
+            vec![pointer_button(center, true)],
+            vec![pointer_button(center, false)],
+        ] {
+            let _ = harness.run_with(events, |map| {
+                map.with_sense(egui::Sense::click_and_drag())
+                    .with_plugin(ClickableMarkers {
+                        markers: vec![
+                            ("far away", Marker::new(Position::new(0., 0.))),
+                            ("wroclaw", Marker::new(wroclaw())),
+                        ],
+                        clicked: &mut clicked,
+                    })
+            });
+        }
+
+        assert_eq!(Some("wroclaw"), clicked);
+    }
+
+    #[test]
+    fn interacting_with_marker_widgets() {
+        let mut harness = MapHarness::new(wroclaw());
+        let center = harness.screen_center();
+        let mut responses = Vec::new();
+        let mut run = |events| {
+            let _ = harness.run_with(events, |map| {
+                map.with_plugin(InteractiveMarkers {
+                    markers: vec![
+                        ("far away", Marker::new(Position::new(0., 0.))),
+                        ("wroclaw", Marker::new(wroclaw())),
+                    ],
+                    highlight: Color32::RED,
+                    responses: &mut responses,
+                })
+            });
+            responses.clone()
+        };
+
+        let _ = run(Vec::new());
+        let responses = run(vec![Event::PointerMoved(center)]);
+        assert_eq!(1, responses.len());
+        assert_eq!("wroclaw", responses[0].0);
+        assert!(responses[0].1.hovered());
+
+        let _ = run(vec![pointer_button(center, true)]);
+        let responses = run(vec![pointer_button(center, false)]);
+        assert!(responses[0].1.clicked());
+
+        // Marker took the drag, not the map.
+        assert!(harness.memory.center_mode == Center::MyPosition);
+    }
+
+    #[test]
+    fn marker_widgets_stay_within_the_map() {
+        let mut harness = MapHarness::new(wroclaw());
+        let _ = harness.run(Vec::new());
+        let map_rect = harness.map_rect();
+        let [north_west, ..] = harness.memory.viewport_corners(map_rect).unwrap();
+        let corner = Position::new(north_west.x() + 0.0001, north_west.y() - 0.0001);
+
+        let mut responses = Vec::new();
+        let mut run = || {
+            harness.run_with(Vec::new(), |map| {
+                map.with_plugin(InteractiveMarkers {
+                    markers: vec![("corner", Marker::new(corner))],
+                    highlight: Color32::RED,
+                    responses: &mut responses,
+                })
+            })
+        };
+        // New areas are invisible in their first frame.
+        let _ = run();
+        let output = run();
+
+        let circles: Vec<Rect> = output
+            .shapes
+            .iter()
+            .filter(|clipped| matches!(clipped.1, egui::Shape::Circle(_)))
+            .map(|clipped| clipped.0)
+            .collect();
+        assert_eq!(1, circles.len());
+        assert!(map_rect.contains_rect(circles[0]));
+
+        // Right above the map, below the windows.
+        let orders: Vec<egui::Order> = harness
+            .ctx
+            .memory(|memory| memory.layer_ids().map(|layer| layer.order).collect());
+        assert!(orders.contains(&egui::Order::PanelResizeLine));
+        assert!(!orders.contains(&egui::Order::Foreground));
+    }
+
+    #[test]
+    fn widget_anchored_to_position() {
+        let mut harness = MapHarness::new(wroclaw());
+        let center = harness.screen_center();
+        let mut clicked = false;
+
+        let moved = center + Vec2::new(30., 30.);
+        for events in [
+            vec![Event::PointerMoved(center)],
+            vec![pointer_button(center, true)],
+            vec![pointer_button(center, false)],
+            // Drag starting at the button.
+            vec![pointer_button(center, true)],
+            vec![Event::PointerMoved(moved)],
+            vec![pointer_button(moved, false)],
+        ] {
+            let _ = harness.run_with(events, |map| {
+                map.with_plugin(WidgetAnchor::new("button", wroclaw(), |ui| {
+                    if ui.button("Click me").clicked() {
+                        clicked = true;
+                    }
+                }))
+            });
+        }
+
+        assert!(clicked);
+
+        // Map did not get dragged.
+        assert!(harness.memory.center_mode == Center::MyPosition);
+    }
+
+    #[test]
+    fn anchored_widgets_are_clipped_to_the_map() {
+        let mut harness = MapHarness::new(wroclaw());
+        let _ = harness.run(Vec::new());
+        let map_rect = harness.map_rect();
+        let [north_west, ..] = harness.memory.viewport_corners(map_rect).unwrap();
+        let corner = Position::new(north_west.x() + 0.0001, north_west.y() - 0.0001);
+
+        let mut run = || {
+            harness.run_with(Vec::new(), |map| {
+                map.with_plugin(WidgetAnchor::new("label", corner, |ui| {
+                    ui.label("Straddling the edge");
+                }))
+            })
+        };
+        // New areas are invisible in their first frame.
+        let _ = run();
+        let output = run();
+
+        let label = output
+            .shapes
+            .iter()
+            .find(|clipped| match &clipped.1 {
+                egui::Shape::Text(text) => text.galley.text() == "Straddling the edge",
+                _ => false,
+            })
+            .unwrap();
+        assert!(map_rect.contains_rect(label.0));
+    }
+}
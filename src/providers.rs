@@ -1,6 +1,157 @@
 //! Some common tile map providers.
 
+use egui::{Image, Response, Ui, Widget};
+
 use crate::mercator::TileId;
+use crate::Tiles;
+
+/// Credits to the tile provider, which most of them require to be shown next to the map. Some
+/// providers need more than a text, so it can also have a link and a logo. Add it to the
+/// [`Ui`] to render all of the parts.
+#[derive(Debug, Clone)]
+pub struct Attribution {
+    pub text: String,
+
+    /// Where the text links to.
+    pub url: Option<String>,
+
+    /// Shown before the text.
+    pub logo: Option<Image>,
+
+    /// Render as a small "ⓘ" button, which shows the full attribution when hovered, and keeps
+    /// showing it once clicked. Useful for small maps.
+    pub collapsible: bool,
+}
+
+impl Attribution {
+    /// Plain text attribution, without a link and a logo.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            url: None,
+            logo: None,
+            collapsible: false,
+        }
+    }
+
+    pub fn with_url(self, url: impl Into<String>) -> Self {
+        Self {
+            url: Some(url.into()),
+            ..self
+        }
+    }
+
+    pub fn with_logo(self, logo: Image) -> Self {
+        Self {
+            logo: Some(logo),
+            ..self
+        }
+    }
+
+    /// Make the attribution collapsible, see [`Attribution::collapsible`].
+    pub fn collapsible(self) -> Self {
+        Self {
+            collapsible: true,
+            ..self
+        }
+    }
+
+    fn full(&self, ui: &mut Ui) -> Response {
+        ui.horizontal(|ui| {
+            if let Some(logo) = self.logo {
+                ui.add(logo);
+            }
+
+            match &self.url {
+                Some(url) => ui.hyperlink_to(&self.text, url),
+                None => ui.label(&self.text),
+            };
+        })
+        .response
+    }
+}
+
+impl Widget for &Attribution {
+    fn ui(self, ui: &mut Ui) -> Response {
+        Attributions::new([self]).ui(ui)
+    }
+}
+
+/// Credits to a number of providers, e.g. of stacked tile layers, rendered in a single line.
+/// Ones with the same text and link are shown once. It's collapsible if any of them is.
+#[derive(Debug, Clone)]
+pub struct Attributions<'a> {
+    attributions: Vec<&'a Attribution>,
+}
+
+impl<'a> Attributions<'a> {
+    pub fn new(attributions: impl IntoIterator<Item = &'a Attribution>) -> Self {
+        let mut unique: Vec<&Attribution> = Vec::new();
+        for attribution in attributions {
+            if !unique
+                .iter()
+                .any(|other| other.text == attribution.text && other.url == attribution.url)
+            {
+                unique.push(attribution);
+            }
+        }
+        Self {
+            attributions: unique,
+        }
+    }
+
+    /// Attributions of the tile layers, see [`Tiles::attribution`].
+    pub fn of_tiles(tiles: impl IntoIterator<Item = &'a Tiles>) -> Self {
+        Self::new(tiles.into_iter().filter_map(Tiles::attribution))
+    }
+
+    fn full(&self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            for (n, attribution) in self.attributions.iter().enumerate() {
+                if n > 0 {
+                    ui.label("|");
+                }
+                attribution.full(ui);
+            }
+        });
+    }
+}
+
+impl Widget for Attributions<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        if !self
+            .attributions
+            .iter()
+            .any(|attribution| attribution.collapsible)
+        {
+            return ui.horizontal(|ui| self.full(ui)).response;
+        }
+
+        let texts: Vec<&str> = self
+            .attributions
+            .iter()
+            .map(|attribution| attribution.text.as_str())
+            .collect();
+        let id = ui.id().with(("walkers_attribution", texts.join(" | ")));
+        let expanded = ui.data(|data| data.get_temp::<bool>(id).unwrap_or(false));
+
+        ui.horizontal(|ui| {
+            let button = ui.small_button("ⓘ");
+            if button.clicked() {
+                ui.data_mut(|data| data.insert_temp(id, !expanded));
+            }
+
+            if expanded {
+                self.full(ui);
+            } else {
+                button.on_hover_ui(|ui| {
+                    self.full(ui);
+                });
+            }
+        })
+        .response
+    }
+}
 
 /// <https://www.openstreetmap.org/about>
 pub fn openstreetmap(tile_id: TileId) -> String {
@@ -10,6 +161,13 @@ pub fn openstreetmap(tile_id: TileId) -> String {
     )
 }
 
+/// Attribution required by [`openstreetmap`].
+pub fn openstreetmap_attribution() -> Attribution {
+    Attribution::new("© OpenStreetMap contributors")
+        .with_url("https://www.openstreetmap.org/copyright")
+}
+
+/// Topographic map, good for the outdoor use. <https://opentopomap.org/about>
 pub fn opentopomap(tile_id: TileId) -> String {
     format!(
         "https://tile.opentopomap.org/{}/{}/{}.png",
@@ -17,6 +175,31 @@ pub fn opentopomap(tile_id: TileId) -> String {
     )
 }
 
+/// Attribution required by [`opentopomap`].
+pub fn opentopomap_attribution() -> Attribution {
+    Attribution::new("© OpenStreetMap contributors, SRTM | © OpenTopoMap (CC-BY-SA)")
+        .with_url("https://opentopomap.org/about")
+}
+
+/// Satellite and aerial imagery of the whole world.
+/// <https://www.arcgis.com/home/item.html?id=10df2279f9684e4a9f6a7f08febac2a9>
+pub fn esri_world_imagery(tile_id: TileId) -> String {
+    // Note that, unlike in most of the providers, `y` comes before `x`.
+    format!(
+        "https://server.arcgisonline.com/ArcGIS/rest/services/World_Imagery/MapServer/tile/{}/{}/{}",
+        tile_id.zoom, tile_id.y, tile_id.x
+    )
+}
+
+/// Attribution required by [`esri_world_imagery`].
+pub fn esri_world_imagery_attribution() -> Attribution {
+    Attribution::new(
+        "Tiles © Esri — Source: Esri, i-cubed, USDA, USGS, AEX, GeoEye, Getmapping, Aerogrid, \
+        IGN, IGP, UPR-EGP, and the GIS User Community",
+    )
+    .with_url("https://www.esri.com")
+}
+
 /// Orthophotomap layer from Poland's Geoportal.
 /// <https://www.geoportal.gov.pl/uslugi/usluga-przegladania-wms>
 pub fn geoportal(tile_id: TileId) -> String {
@@ -33,3 +216,72 @@ pub fn geoportal(tile_id: TileId) -> String {
         tile_id.zoom, tile_id.y, tile_id.x
     )
 }
+
+/// Tiles bundled with the application, or exported for offline use, laid out in a plain directory
+/// tree. `template` is a path with `{z}`, `{x}` and `{y}` placeholders, e.g.
+/// `assets/tiles/{z}/{x}/{y}.png`. Relative paths are resolved against the current working
+/// directory.
+pub fn filesystem(template: impl Into<String>) -> impl Fn(TileId) -> String + Send + Sync {
+    let template = template.into();
+    move |tile_id| {
+        let path = template
+            .replace("{z}", &tile_id.zoom.to_string())
+            .replace("{x}", &tile_id.x.to_string())
+            .replace("{y}", &tile_id.y.to_string());
+        format!("{FILE_SCHEME}{path}")
+    }
+}
+
+/// Prefix of the URLs which are read from the disk instead of downloaded.
+pub(crate) const FILE_SCHEME: &str = "file://";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{pointer_button, texts, MapHarness};
+    use crate::Position;
+    use egui::{Event, Pos2, Vec2};
+
+    fn wroclaw() -> Position {
+        Position::new(17.03664, 51.09916)
+    }
+
+    #[test]
+    fn collapsible_attribution_expands_when_clicked() {
+        let attribution = Attribution::new("OpenStreetMap contributors").collapsible();
+        let mut harness = MapHarness::new(wroclaw());
+        let frame = |harness: &mut MapHarness, events| {
+            texts(&harness.run_with(events, |map| map.with_attribution(attribution.clone())))
+        };
+
+        let texts = frame(&mut harness, Vec::new());
+        assert!(!texts
+            .iter()
+            .any(|(text, _)| text == "OpenStreetMap contributors"));
+
+        let (_, button) = texts.iter().find(|(text, _)| text == "ⓘ").unwrap();
+        let button = *button + Vec2::splat(2.);
+        let _ = frame(&mut harness, vec![Event::PointerMoved(button)]);
+        let _ = frame(&mut harness, vec![pointer_button(button, true)]);
+        let _ = frame(&mut harness, vec![pointer_button(button, false)]);
+
+        // Moved the pointer away, so that it's not just hovered.
+        let texts = frame(&mut harness, vec![Event::PointerMoved(Pos2::ZERO)]);
+        assert!(texts
+            .iter()
+            .any(|(text, _)| text == "OpenStreetMap contributors"));
+    }
+
+    #[test]
+    fn attributions_of_stacked_layers_are_merged() {
+        let mut harness = MapHarness::new(wroclaw());
+        let texts = texts(&harness.run_with(Vec::new(), |map| {
+            map.with_attribution(Attribution::new("OpenStreetMap contributors"))
+                .with_attribution(Attribution::new("Esri"))
+                .with_attribution(Attribution::new("OpenStreetMap contributors"))
+        }));
+
+        let texts: Vec<&str> = texts.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(texts, ["OpenStreetMap contributors", "|", "Esri"]);
+    }
+}
@@ -0,0 +1,347 @@
+//! Utilities for testing applications embedding the [`Map`], without network and a real window.
+//!
+//! # Examples
+//!
+//! ```
+//! # use walkers::{testing::MapHarness, Position};
+//! let mut harness = MapHarness::new(Position::new(17.03664, 51.09916));
+//!
+//! // Drag the map to the right, which moves its center to the west.
+//! harness.drag(egui::vec2(100., 0.));
+//! assert!(harness.center().x() < 17.03664);
+//! ```
+
+use egui::{
+    epaint::ClippedPrimitive, CentralPanel, Color32, ColorImage, Context, Event, FullOutput,
+    Modifiers, PointerButton, Pos2, RawInput, Rect, Vec2,
+};
+
+use crate::{mercator::TILE_SIZE, Map, MapMemory, Plugin, Position, Tiles};
+
+#[cfg(test)]
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// [`Tiles`] which never touch the network. Every tile is a solid `color` square and is available
+/// right away, on the first request.
+pub fn solid_color_tiles(color: Color32, egui_ctx: Context) -> Tiles {
+    solid_color_tiles_of_size(color, TILE_SIZE, egui_ctx)
+}
+
+/// Like [`solid_color_tiles`], but with images of `size` pixels, e.g. `512` for a screen with two
+/// pixels per point. They are still drawn as regular, 256 points wide tiles.
+pub fn solid_color_tiles_of_size(color: Color32, size: u32, egui_ctx: Context) -> Tiles {
+    let size = size as usize;
+    Tiles::rendered(move |_| ColorImage::new([size, size], color), egui_ctx)
+}
+
+/// Runs a [`Map`] in a headless egui context and lets you drive it with synthetic input.
+pub struct MapHarness {
+    pub ctx: Context,
+    pub tiles: Tiles,
+    pub memory: MapMemory,
+    pub my_position: Position,
+
+    /// Size of the simulated screen. Map takes all of it.
+    pub screen_size: Vec2,
+
+    /// Scale of the simulated screen, `1.` by default. Fixed, so that the frames come out the same
+    /// regardless of the machine, e.g. for comparing them with reference images. See
+    /// [`MapHarness::with_resolution`].
+    pub pixels_per_point: f32,
+
+    /// Keyboard modifiers held while running the frames.
+    pub modifiers: Modifiers,
+}
+
+impl MapHarness {
+    pub fn new(my_position: Position) -> Self {
+        let ctx = Context::default();
+        Self {
+            tiles: solid_color_tiles(Color32::GRAY, ctx.clone()),
+            ctx,
+            memory: MapMemory::default(),
+            my_position,
+            screen_size: Vec2::new(800., 600.),
+            pixels_per_point: 1.,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    /// Harness with a screen of exactly `output_size` pixels at `pixels_per_point`, drawing solid
+    /// gray tiles of `tile_size` pixels (see [`solid_color_tiles_of_size`]), so that the frames
+    /// come out the same regardless of the machine, e.g. for comparing them with reference
+    /// images.
+    pub fn with_resolution(
+        my_position: Position,
+        output_size: [u32; 2],
+        pixels_per_point: f32,
+        tile_size: u32,
+    ) -> Self {
+        let harness = Self::new(my_position);
+        Self {
+            tiles: solid_color_tiles_of_size(Color32::GRAY, tile_size, harness.ctx.clone()),
+            screen_size: Vec2::new(output_size[0] as f32, output_size[1] as f32) / pixels_per_point,
+            pixels_per_point,
+            ..harness
+        }
+    }
+
+    /// Size of the simulated screen in pixels.
+    pub fn output_size(&self) -> Vec2 {
+        self.screen_size * self.pixels_per_point
+    }
+
+    /// Run a single frame, feeding given input `events` to egui.
+    pub fn run(&mut self, events: Vec<Event>) -> FullOutput {
+        self.run_with_plugins(events, Vec::new())
+    }
+
+    /// Run a single frame, drawing the map with `plugins`.
+    pub fn run_with_plugins(
+        &mut self,
+        events: Vec<Event>,
+        plugins: Vec<Box<dyn Plugin + '_>>,
+    ) -> FullOutput {
+        self.run_with(events, |map| {
+            plugins
+                .into_iter()
+                .fold(map, |map, plugin| map.with_plugin(plugin))
+        })
+    }
+
+    /// Run a single frame, letting `configure` set up the [`Map`] before it's drawn.
+    pub fn run_with<'c>(
+        &'c mut self,
+        events: Vec<Event>,
+        configure: impl FnOnce(Map<'c, 'c, 'c>) -> Map<'c, 'c, 'c>,
+    ) -> FullOutput {
+        let input = RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, self.screen_size)),
+            pixels_per_point: Some(self.pixels_per_point),
+            events,
+            modifiers: self.modifiers,
+            ..Default::default()
+        };
+
+        let Self {
+            ctx,
+            tiles,
+            memory,
+            my_position,
+            ..
+        } = self;
+
+        ctx.run(input, |ctx| {
+            CentralPanel::default().show(ctx, |ui| {
+                ui.add(configure(Map::new(Some(tiles), memory, *my_position)));
+            });
+        })
+    }
+
+    /// Turn the `output` of a frame into triangles, like a renderer would, at the
+    /// [`MapHarness::pixels_per_point`].
+    pub fn tessellate(&self, output: FullOutput) -> Vec<ClippedPrimitive> {
+        self.ctx.tessellate(output.shapes)
+    }
+
+    /// Area taken by the map, which is the simulated screen without the margin of the panel the
+    /// map is in.
+    pub fn map_rect(&self) -> Rect {
+        Rect::from_min_size(Pos2::ZERO, self.screen_size).shrink(8.)
+    }
+
+    /// Middle of the simulated screen.
+    pub fn screen_center(&self) -> Pos2 {
+        (self.screen_size / 2.).to_pos2()
+    }
+
+    /// Press the primary mouse button in the middle of the map, move the pointer by `delta` and
+    /// release it.
+    pub fn drag(&mut self, delta: Vec2) {
+        let from = self.screen_center();
+        let to = from + delta;
+
+        let _ = self.run(vec![Event::PointerMoved(from)]);
+        let _ = self.run(vec![pointer_button(from, true)]);
+        let _ = self.run(vec![Event::PointerMoved(to)]);
+        let _ = self.run(vec![pointer_button(to, false)]);
+    }
+
+    /// Zoom by `factor` (e.g. pinch gesture or CTRL + mouse wheel) with the pointer in the middle
+    /// of the map.
+    pub fn zoom(&mut self, factor: f32) {
+        let center = self.screen_center();
+        let _ = self.run(vec![Event::PointerMoved(center)]);
+        let _ = self.run(vec![Event::Zoom(factor)]);
+    }
+
+    /// Map's current center.
+    pub fn center(&self) -> Position {
+        self.memory.center_mode.position(self.my_position)
+    }
+
+    /// Map's current zoom level.
+    pub fn zoom_level(&self) -> u8 {
+        self.memory.zoom.round()
+    }
+}
+
+/// Press or release of the primary mouse button at `pos`.
+pub(crate) fn pointer_button(pos: Pos2, pressed: bool) -> Event {
+    Event::PointerButton {
+        pos,
+        button: PointerButton::Primary,
+        pressed,
+        modifiers: Modifiers::default(),
+    }
+}
+
+/// Like [`solid_color_tiles`], but also counts the tiles rendered so far, e.g. to tell whether
+/// some were dropped and loaded again.
+#[cfg(test)]
+pub(crate) fn counting_tiles(egui_ctx: Context) -> (Tiles, Arc<AtomicUsize>) {
+    let rendered = Arc::new(AtomicUsize::new(0));
+    let counter = rendered.clone();
+    let tiles = Tiles::rendered(
+        move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            ColorImage::new([256, 256], Color32::GRAY)
+        },
+        egui_ctx,
+    );
+    (tiles, rendered)
+}
+
+/// Meshes drawn in the frame, e.g. the tiles.
+#[cfg(test)]
+pub(crate) fn meshes(output: &FullOutput) -> Vec<&egui::Mesh> {
+    output
+        .shapes
+        .iter()
+        .filter_map(|clipped| match &clipped.1 {
+            egui::Shape::Mesh(mesh) => Some(mesh),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Colors of the vertices of all meshes drawn in the frame.
+#[cfg(test)]
+pub(crate) fn mesh_colors(output: &FullOutput) -> Vec<Color32> {
+    meshes(output)
+        .into_iter()
+        .flat_map(|mesh| mesh.vertices.iter().map(|vertex| vertex.color))
+        .collect()
+}
+
+/// Texts drawn in the frame, with their screen positions.
+#[cfg(test)]
+pub(crate) fn texts(output: &FullOutput) -> Vec<(String, Pos2)> {
+    output
+        .shapes
+        .iter()
+        .filter_map(|clipped| match &clipped.1 {
+            egui::Shape::Text(text) => Some((text.galley.text().to_owned(), text.pos)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mercator::TileId, Center};
+
+    fn wroclaw() -> Position {
+        Position::new(17.03664, 51.09916)
+    }
+
+    #[test]
+    fn solid_color_tiles_are_available_immediately() {
+        let mut tiles = solid_color_tiles(Color32::RED, Context::default());
+        assert!(tiles
+            .at(TileId {
+                x: 1,
+                y: 2,
+                zoom: 3
+            })
+            .is_some());
+    }
+
+    #[test]
+    fn map_follows_my_position_until_dragged() {
+        let mut harness = MapHarness::new(wroclaw());
+        let _ = harness.run(Vec::new());
+        assert!(harness.memory.center_mode == Center::MyPosition);
+
+        harness.drag(Vec2::new(100., -100.));
+
+        // Dragging to the right and up moves the center to the west and south.
+        assert!(matches!(harness.memory.center_mode, Center::Exact(_)));
+        assert!(harness.center().x() < wroclaw().x());
+        assert!(harness.center().y() < wroclaw().y());
+    }
+
+    #[test]
+    fn zooming_with_gesture() {
+        let mut harness = MapHarness::new(wroclaw());
+        assert_eq!(16, harness.zoom_level());
+
+        harness.zoom(0.5);
+        assert_eq!(15, harness.zoom_level());
+
+        // Zooming does not move the map.
+        assert!(harness.memory.center_mode == Center::MyPosition);
+    }
+
+    #[test]
+    fn frames_at_fixed_resolution_match_the_reference() {
+        let mut harness = MapHarness::with_resolution(wroclaw(), [1200, 800], 2., 512);
+        assert_eq!(Vec2::new(1200., 800.), harness.output_size());
+
+        // Tiles are uploaded in their full resolution...
+        let first = harness.run(Vec::new());
+        let tile_textures = first
+            .textures_delta
+            .set
+            .iter()
+            .filter(|(_, delta)| delta.image.size() == [512, 512])
+            .count();
+        assert_eq!(9, tile_textures);
+
+        // ...but drawn as 256 points wide squares, in the same place on every machine.
+        let output = harness.run(Vec::new());
+        assert_eq!(2., harness.ctx.pixels_per_point());
+
+        let mut tiles: Vec<Rect> = harness
+            .tessellate(output)
+            .into_iter()
+            .filter(|primitive| primitive.clip_rect == harness.map_rect())
+            .map(|primitive| {
+                let egui::epaint::Primitive::Mesh(mesh) = primitive.primitive else {
+                    panic!("tiles are drawn as meshes");
+                };
+                let uv = Rect::from_points(&mesh.vertices.iter().map(|v| v.uv).collect::<Vec<_>>());
+                assert_eq!(Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.)), uv);
+                Rect::from_points(&mesh.vertices.iter().map(|v| v.pos).collect::<Vec<_>>())
+            })
+            .collect();
+        tiles.sort_by(|a, b| (a.min.x, a.min.y).partial_cmp(&(b.min.x, b.min.y)).unwrap());
+
+        // Wrocław is 109 × 68 points into its tile, which is offset from the middle of the map.
+        let columns = [(-65., 191.), (191., 447.), (447., 703.)];
+        let rows = [(-123.5, 132.5), (132.5, 388.5), (388.5, 644.5)];
+        let expected: Vec<Rect> = columns
+            .iter()
+            .flat_map(|&(left, right)| {
+                rows.iter().map(move |&(top, bottom)| {
+                    Rect::from_min_max(Pos2::new(left, top), Pos2::new(right, bottom))
+                })
+            })
+            .collect();
+        assert_eq!(expected, tiles);
+    }
+}
@@ -0,0 +1,177 @@
+//! [TileJSON](https://github.com/mapbox/tilejson-spec) documents, which describe tile providers.
+
+use std::ops::RangeInclusive;
+
+use crate::{mercator::TileId, zoom::MAX_ZOOM, BoundingBox};
+
+/// Parts of a TileJSON document needed to show the tiles. See [`crate::Tiles::from_tilejson`].
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(try_from = "Document")]
+pub struct TileJson {
+    /// Never empty, see [`TileJson::tiles`].
+    tiles: Vec<String>,
+
+    pub attribution: Option<String>,
+    pub minzoom: u8,
+    pub maxzoom: u8,
+
+    /// West, south, east and north edges.
+    pub bounds: Option<[f64; 4]>,
+
+    pub scheme: Scheme,
+}
+
+/// How the rows of the tiles are numbered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    /// From the north, like in OpenStreetMap.
+    #[default]
+    Xyz,
+
+    /// From the south, as in the Tile Map Service specification.
+    Tms,
+}
+
+/// TileJSON document as it is, before it's validated.
+#[derive(serde::Deserialize)]
+struct Document {
+    tiles: Vec<String>,
+    attribution: Option<String>,
+    #[serde(default)]
+    minzoom: u8,
+    #[serde(default = "default_maxzoom")]
+    maxzoom: u8,
+    bounds: Option<[f64; 4]>,
+    #[serde(default)]
+    scheme: Scheme,
+}
+
+fn default_maxzoom() -> u8 {
+    // As defined by the specification.
+    30
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TileJsonError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    #[error("invalid TileJSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("TileJSON has no tile URLs")]
+    NoTiles,
+}
+
+impl TryFrom<Document> for TileJson {
+    type Error = TileJsonError;
+
+    fn try_from(document: Document) -> Result<Self, Self::Error> {
+        if document.tiles.is_empty() {
+            return Err(TileJsonError::NoTiles);
+        }
+        Ok(Self {
+            tiles: document.tiles,
+            attribution: document.attribution,
+            minzoom: document.minzoom,
+            maxzoom: document.maxzoom,
+            bounds: document.bounds,
+            scheme: document.scheme,
+        })
+    }
+}
+
+impl TileJson {
+    pub fn parse(json: &str) -> Result<Self, TileJsonError> {
+        let document: Document = serde_json::from_str(json)?;
+        document.try_into()
+    }
+
+    /// URL templates with `{z}`, `{x}` and `{y}` placeholders. If there is more than one, tiles
+    /// are spread between them.
+    pub fn tiles(&self) -> &[String] {
+        &self.tiles
+    }
+
+    /// URL of given tile. Rows are flipped for the [`Scheme::Tms`].
+    pub fn url(&self, tile_id: TileId) -> String {
+        let y = match self.scheme {
+            Scheme::Xyz => tile_id.y,
+            Scheme::Tms => (1 << tile_id.zoom) - 1 - tile_id.y,
+        };
+        let template = &self.tiles[(tile_id.x + tile_id.y) as usize % self.tiles.len()];
+        template
+            .replace("{z}", &tile_id.zoom.to_string())
+            .replace("{x}", &tile_id.x.to_string())
+            .replace("{y}", &y.to_string())
+    }
+
+    /// Zoom levels having tiles, limited to the ones supported by the map.
+    pub fn zoom_range(&self) -> RangeInclusive<u8> {
+        self.minzoom.min(MAX_ZOOM)..=self.maxzoom.min(MAX_ZOOM)
+    }
+
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        self.bounds.map(|[west, south, east, north]| BoundingBox {
+            west,
+            south,
+            east,
+            north,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_tilejson() {
+        let tilejson = TileJson::parse(
+            r#"{
+                "tilejson": "3.0.0",
+                "tiles": ["https://a.example.com/{z}/{x}/{y}.png", "https://b.example.com/{z}/{x}/{y}.png"],
+                "attribution": "© Example",
+                "minzoom": 2,
+                "maxzoom": 22,
+                "bounds": [14.1, 49.0, 24.2, 54.9]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(Some("© Example".to_owned()), tilejson.attribution);
+        assert_eq!(2..=MAX_ZOOM, tilejson.zoom_range());
+        assert_eq!(14.1, tilejson.bounds().unwrap().west);
+        assert_eq!(
+            "https://b.example.com/3/1/2.png",
+            tilejson.url(TileId {
+                x: 1,
+                y: 2,
+                zoom: 3
+            })
+        );
+
+        assert!(matches!(
+            TileJson::parse(r#"{"tiles": []}"#),
+            Err(TileJsonError::NoTiles)
+        ));
+        assert!(serde_json::from_str::<TileJson>(r#"{"tiles": []}"#).is_err());
+    }
+
+    #[test]
+    fn rows_are_flipped_for_tms() {
+        let tilejson =
+            TileJson::parse(r#"{"tiles": ["{z}/{x}/{y}.png"], "scheme": "tms"}"#).unwrap();
+
+        assert_eq!(Scheme::Tms, tilejson.scheme);
+        assert_eq!(
+            "3/1/5.png",
+            tilejson.url(TileId {
+                x: 1,
+                y: 2,
+                zoom: 3
+            })
+        );
+    }
+}
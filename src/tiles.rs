@@ -1,241 +1,2417 @@
 use std::collections::hash_map::Entry;
-use std::{collections::HashMap, sync::Arc};
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use egui::{pos2, Color32, Context, Mesh, Rect, Vec2};
+use bytes::Bytes;
+use egui::{pos2, vec2, Color32, ColorImage, Context, Mesh, Rect, Vec2};
 use egui_extras::RetainedImage;
-use reqwest::header::USER_AGENT;
+use reqwest::header::{HeaderValue, ETAG, IF_NONE_MATCH, USER_AGENT};
+use reqwest::{Request, RequestBuilder, StatusCode};
 use tokio::sync::mpsc::error::TryRecvError;
 
-use crate::mercator::TileId;
+use crate::mercator::{screen_to_position, BoundingBox, PositionExt, TileId, TILE_SIZE};
+use crate::providers::{Attribution, FILE_SCHEME};
+use crate::tilejson::{TileJson, TileJsonError};
 use crate::tokio::TokioRuntimeThread;
+use crate::zoom::MAX_ZOOM;
+use crate::Position;
 
 #[derive(Clone)]
 pub struct Tile {
-    image: Arc<RetainedImage>,
+    /// `None` if the tile is empty, see [`Tile::empty`].
+    image: Option<Arc<RetainedImage>>,
+
+    /// HTTP `ETag` the tile was served with, used to revalidate it later.
+    etag: Option<String>,
+
+    /// Size the tile is drawn with, which is the size of the original image, even if it was
+    /// downsampled.
+    size: Vec2,
+
+    /// Which of the providers given to [`Tiles::with_fallbacks`] served the tile.
+    provider: usize,
+}
+
+impl Tile {
+    pub fn from_image_bytes(image: &[u8]) -> Result<Self, String> {
+        RetainedImage::from_image_bytes("debug_name", image).map(Self::from_retained_image)
+    }
+
+    pub fn from_retained_image(image: RetainedImage) -> Self {
+        Self {
+            size: vec2(image.width() as f32, image.height() as f32),
+            image: Some(Arc::new(image)),
+            etag: None,
+            provider: 0,
+        }
+    }
+
+    /// Tile without any content, which is not drawn at all. That's what the providers serve with
+    /// `204 No Content` or an empty body, e.g. for the areas without any data of a sparse
+    /// overlay. It's cached like any other tile, so it's not requested again.
+    pub fn empty() -> Self {
+        Self {
+            image: None,
+            etag: None,
+            size: Vec2::splat(TILE_SIZE as f32),
+            provider: 0,
+        }
+    }
+
+    /// Whether it's the [`Tile::empty`].
+    pub fn is_empty(&self) -> bool {
+        self.image.is_none()
+    }
+
+    /// Decode the tile, downsampling it if any of its dimensions exceeds `max_size`. It's still
+    /// drawn with its original size.
+    fn from_image_bytes_downsampled(image: &[u8], max_size: u32) -> Result<Self, String> {
+        let image = image::load_from_memory(image).map_err(|e| e.to_string())?;
+        let size = vec2(image.width() as f32, image.height() as f32);
+
+        let image = if image.width() > max_size || image.height() > max_size {
+            image.thumbnail(max_size, max_size)
+        } else {
+            image
+        }
+        .to_rgba8();
+
+        let image = ColorImage::from_rgba_unmultiplied(
+            [image.width() as usize, image.height() as usize],
+            image.as_flat_samples().as_slice(),
+        );
+
+        Ok(Self {
+            size,
+            ..Self::from_retained_image(RetainedImage::from_color_image("debug_name", image))
+        })
+    }
+
+    /// Attach an HTTP `ETag` to the tile. Tiles coming from the hard cache which have one are
+    /// revalidated with the server using `If-None-Match`.
+    pub fn with_etag(self, etag: impl Into<String>) -> Self {
+        Self {
+            etag: Some(etag.into()),
+            ..self
+        }
+    }
+
+    /// HTTP `ETag` of this tile, if the server sent one. Store it along with the tile in the
+    /// hard cache, so it can be revalidated instead of downloaded again.
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    pub fn rect(&self, screen_position: Vec2) -> Rect {
+        Rect::from_two_pos(
+            screen_position.to_pos2(),
+            (screen_position + self.size).to_pos2(),
+        )
+    }
+
+    pub fn mesh(&self, screen_position: Vec2, ctx: &Context) -> Mesh {
+        self.mesh_with_uv(
+            self.rect(screen_position),
+            Rect::from_min_max(pos2(0., 0.0), pos2(1.0, 1.0)),
+            ctx,
+        )
+    }
+
+    /// Mesh drawing only the `uv` part of the tile, stretched over the `rect`.
+    pub(crate) fn mesh_with_uv(&self, rect: Rect, uv: Rect, ctx: &Context) -> Mesh {
+        let Some(image) = &self.image else {
+            return Mesh::default();
+        };
+        let mut mesh = Mesh::with_texture(image.texture_id(ctx));
+        mesh.add_rect_with_uv(rect, uv, Color32::WHITE);
+        mesh
+    }
+}
+
+/// Downloads and keeps cache of the tiles. It must persist between frames.
+pub struct Tiles {
+    cache: HashMap<TileId, Option<Tile>>,
+
+    /// Time of the tiles in the `cache`, see [`Tiles::set_time`].
+    time: Option<String>,
+
+    /// Caches of the other times, kept so that switching back to them is instant.
+    slices: HashMap<String, HashMap<TileId, Option<Tile>>>,
+
+    /// Tiles to be downloaded by the IO thread, along with the `ETag` of the version we already
+    /// have, if any.
+    request_tx: tokio::sync::mpsc::Sender<(TileKey, Option<String>)>,
+
+    /// Tiles to be downloaded by the IO thread when there is nothing in the `request_tx`, see
+    /// [`Tiles::warm`].
+    warm_tx: tokio::sync::mpsc::UnboundedSender<TileKey>,
+
+    /// Outcomes of the requests, including the tiles that got downloaded and should be put in
+    /// the cache. Each request gets exactly one.
+    tile_rx: tokio::sync::mpsc::Receiver<(TileKey, Downloaded)>,
+
+    /// Tiles requested from the IO thread, but without the outcome yet. These are not requested
+    /// again, even if they were dropped from the cache in the meantime.
+    in_flight: HashSet<TileKey>,
+
+    /// Tiles put in the cache by the most recent [`Tiles::warm`], which were not shown yet. These
+    /// are not dropped for being far away from the view, see [`crate::Map::with_tile_buffer`].
+    warmed: HashSet<TileKey>,
+
+    /// Tile provider, shared between the download tasks.
+    source: Arc<Source>,
+
+    /// Shared with the IO thread, so it requests repaints of the right context after
+    /// [`Tiles::rebind`].
+    egui_ctx: tokio::sync::watch::Sender<Context>,
+
+    #[allow(dead_code)] // Significant Drop
+    tokio_runtime_thread: TokioRuntimeThread,
+
+    /// Persistent cache (e.g. on the disk) consulted before downloading a tile.
+    hard_cache: Box<HardCache>,
+
+    /// Tiles outside of these are never requested.
+    zoom_range: RangeInclusive<u8>,
+    zoom_levels: Option<Vec<u8>>,
+    bounds: Option<BoundingBox>,
+
+    /// See [`TilesOptions::fetch_mode`] and [`TilesOptions::credentials`], for the
+    /// [`Tiles::download_region`].
+    fetch: (FetchMode, Credentials),
+
+    attribution: Option<Attribution>,
+
+    /// Attributions of each of the providers of [`Tiles::with_fallbacks`].
+    provider_attributions: Vec<Attribution>,
+
+    stats: TileStats,
+
+    /// See [`Tiles::with_events`]. `None` unless enabled, so that nothing piles up.
+    events: Option<Vec<TileEvent>>,
+
+    /// Counters updated by the IO thread, hence kept outside of `stats`, and settings it reads.
+    shared: Arc<Shared>,
+}
+
+/// Tile of a given time, if the tiles change over time, see [`Tiles::set_time`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TileKey {
+    tile_id: TileId,
+    time: Option<String>,
+}
+
+/// What the IO thread did with a requested tile.
+enum Downloaded {
+    Tile(Tile),
+
+    /// Download timed out, or was dropped, so the tile should be requested again.
+    Retry,
+
+    /// Nothing new for the cache, e.g. the tile was not modified, or could not be downloaded.
+    Nothing,
+}
+
+/// State shared between [`Tiles`] and the IO thread.
+struct Shared {
+    downloaded_bytes: AtomicU64,
+    invalid_responses: AtomicU64,
+
+    /// See [`Tiles::set_network_enabled`]. The IO thread drops the requests which are already
+    /// queued when it's disabled.
+    network_enabled: AtomicBool,
+}
+
+/// Counters of what [`Tiles`] did, see [`Tiles::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TileStats {
+    /// Tiles which were there when asked for, either in the memory or the hard cache.
+    pub hits: u64,
+
+    /// Tiles which were not there yet when asked for.
+    pub misses: u64,
+
+    /// Download requests sent, including revalidations of the hard cached tiles.
+    pub requested: u64,
+
+    /// Size of the downloaded tiles, before they were decoded.
+    pub downloaded_bytes: u64,
+
+    /// Responses which turned out not to be images at all, typically HTML error pages served
+    /// because of a wrong URL. Details are logged.
+    pub invalid_responses: u64,
+}
+
+/// Difference between two snapshots, the later one minus the earlier one. Counters which would
+/// go below zero, e.g. when the snapshots are swapped or come from different [`Tiles`], are zero.
+impl std::ops::Sub for TileStats {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            hits: self.hits.saturating_sub(rhs.hits),
+            misses: self.misses.saturating_sub(rhs.misses),
+            requested: self.requested.saturating_sub(rhs.requested),
+            downloaded_bytes: self.downloaded_bytes.saturating_sub(rhs.downloaded_bytes),
+            invalid_responses: self.invalid_responses.saturating_sub(rhs.invalid_responses),
+        }
+    }
+}
+
+/// Why [`Tiles::tile_ready`] gave up on a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TileError {
+    /// Provider does not have it, see [`TilesOptions::zoom_range`],
+    /// [`TilesOptions::zoom_levels`] and [`TilesOptions::bounds`].
+    #[error("provider does not have this tile")]
+    Unavailable,
+
+    /// Download or decoding failed. Details are logged.
+    #[error("tile could not be downloaded")]
+    Failed,
+
+    /// See [`Tiles::set_network_enabled`].
+    #[error("network is disabled")]
+    Offline,
+}
+
+/// Tile coming and going from the memory cache, along with the size of its texture, see
+/// [`Tiles::with_events`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileEvent {
+    /// Tile got into the memory cache. Its texture is not uploaded right away, but lazily, the
+    /// first time the tile is drawn.
+    Cached { tile_id: TileId, bytes: usize },
+
+    /// Tile was dropped from the memory cache, freeing its texture, unless the hard cache still
+    /// holds the same [`Tile`].
+    Evicted { tile_id: TileId, bytes: usize },
+}
+
+/// Size of the tile's texture, `None` for the [`Tile::empty`] ones.
+fn texture_bytes(tile: &Tile) -> Option<usize> {
+    tile.image
+        .as_ref()
+        .map(|image| image.width() * image.height() * 4)
+}
+
+/// Note an eviction of the `tile`, if it has a texture and the `events` are recorded.
+fn record_eviction(events: &mut Option<Vec<TileEvent>>, tile_id: TileId, tile: &Option<Tile>) {
+    if let (Some(events), Some(bytes)) = (events, tile.as_ref().and_then(texture_bytes)) {
+        events.push(TileEvent::Evicted { tile_id, bytes });
+    }
+}
+
+type HardCache = dyn Fn(&TileId) -> Result<Tile, String>;
+
+/// Where the tiles come from.
+enum Source {
+    /// URL of each tile, downloaded with a plain `GET`.
+    Url(Box<dyn Fn(TileId) -> String + Send + Sync>),
+
+    /// Complete request for each tile, see [`Tiles::with_requests`].
+    Request(Box<dyn Fn(TileId) -> Request + Send + Sync>),
+
+    /// URL of each tile at the given time, see [`Tiles::temporal`].
+    Temporal(Box<TemporalTileUrl>),
+
+    /// URLs of each tile at different providers, tried in order, see [`Tiles::with_fallbacks`].
+    Fallbacks(Vec<Box<TileUrl>>),
+
+    /// URLs of each tile in different formats, tried in order, see [`Tiles::with_formats`].
+    Formats {
+        urls: Vec<Box<TileUrl>>,
+
+        /// Formats before this one turned out not to be served at all (`406` or `415`), so they
+        /// are skipped.
+        first: AtomicUsize,
+    },
+
+    /// Tiles fetched by the application, see [`Tiles::external`].
+    External(std::sync::mpsc::Sender<TileRequest>),
+}
+
+/// Tile needed by the [`Tiles::external`], to be answered with [`TileRequest::respond`].
+/// Dropping it without a response means that the tile is not there, and it's not requested
+/// again.
+#[derive(Debug)]
+pub struct TileRequest {
+    pub tile_id: TileId,
+    response: tokio::sync::oneshot::Sender<Bytes>,
+}
+
+impl TileRequest {
+    /// Hand over the encoded image of the tile, e.g. a PNG. Empty `image` is the
+    /// [`Tile::empty`].
+    pub fn respond(self, image: impl Into<Bytes>) {
+        // Tiles might have been dropped in the meantime, nobody is waiting then.
+        let _ = self.response.send(image.into());
+    }
+}
+
+/// URL of a tile, e.g. one of the functions in [`crate::providers`].
+pub type TileUrl = dyn Fn(TileId) -> String + Send + Sync;
+
+/// URL of a tile at given time, see [`Tiles::temporal`].
+pub type TemporalTileUrl = dyn Fn(TileId, &str) -> String + Send + Sync;
+
+/// Transforms the downloaded bytes of a tile before they are decoded, see
+/// [`TilesOptions::preprocess`].
+pub type Preprocess = dyn Fn(TileId, Bytes) -> Result<Bytes, String> + Send + Sync;
+
+/// Tweaks of how [`Tiles`] are downloaded. Use [`Default`] for the fields you don't care about.
+#[derive(Clone)]
+pub struct TilesOptions {
+    /// How long a single tile request can take. Tiles which do not make it are requested again,
+    /// next time they are needed.
+    pub timeout: Duration,
+
+    /// Downloaded tiles larger than that (in pixels) are downsampled before they are uploaded
+    /// to the GPU, trading the sharpness for the memory. Tiles are still drawn with their
+    /// original size. Does not apply to the tiles coming from the hard cache. `None` (the
+    /// default) means no limit.
+    pub max_texture_size: Option<u32>,
+
+    /// Applied to the downloaded bytes of each tile, before they are decoded into an image. Use
+    /// it for tiles which need to be e.g. decrypted, or recolored, like the terrain-RGB ones.
+    /// Result has to be an image in one of the supported formats. Tiles for which it returns an
+    /// error are treated like the ones which could not be downloaded.
+    pub preprocess: Option<Arc<Preprocess>>,
+
+    /// Zoom levels the provider has tiles for. Tiles of other levels are not requested at all.
+    pub zoom_range: RangeInclusive<u8>,
+
+    /// Particular zoom levels the provider has tiles for, if it does not have all of the ones in
+    /// the `zoom_range`, e.g. `vec![5, 8, 12]`. Map snaps to them when zoomed, see
+    /// [`crate::Zoom::snap_to_levels`].
+    pub zoom_levels: Option<Vec<u8>>,
+
+    /// Area the provider has tiles for. Tiles which are entirely outside of it are not requested
+    /// at all.
+    pub bounds: Option<BoundingBox>,
+
+    /// Mode of the browser's `fetch` requesting the tiles, [`FetchMode::Cors`] by default. Only
+    /// the web downloads honour it, see [`FetchMode`].
+    pub fetch_mode: FetchMode,
+
+    /// Whether the browser's `fetch` sends the cookies along with the tile requests, e.g. for the
+    /// tile servers behind an authentication. [`Credentials::SameOrigin`] by default. Only the web
+    /// downloads honour it, see [`Credentials`].
+    pub credentials: Credentials,
+}
+
+/// Mode of the browser's `fetch` requesting the tiles, see [`TilesOptions::fetch_mode`]. Native
+/// downloads do not follow the CORS protocol, so it does not matter there. Neither does it for
+/// [`Tiles::with_requests`], which builds the requests itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FetchMode {
+    /// Tiles of other origins are fetched if their server allows it with the CORS headers.
+    #[default]
+    Cors,
+
+    /// Tiles of other origins are fetched without asking for the CORS headers. Browser does not
+    /// let anyone read such responses, though, so the tiles of other origins fail to load.
+    NoCors,
+}
+
+/// Whether the browser's `fetch` sends the cookies and HTTP authentication along with the tile
+/// requests, see [`TilesOptions::credentials`]. Native downloads do not keep the cookies, so it
+/// does not matter there. Neither does it for [`Tiles::with_requests`], which builds the requests
+/// itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Credentials {
+    /// Never sent.
+    Omit,
+
+    /// Sent only to the tile servers of the same origin as the application.
+    #[default]
+    SameOrigin,
+
+    /// Sent to the tile servers of other origins too. They need to allow it with the CORS headers.
+    Include,
+}
+
+impl TilesOptions {
+    /// Set the [`TilesOptions::preprocess`].
+    pub fn with_preprocess(
+        self,
+        preprocess: impl Fn(TileId, Bytes) -> Result<Bytes, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            preprocess: Some(Arc::new(preprocess)),
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Debug for TilesOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TilesOptions")
+            .field("timeout", &self.timeout)
+            .field("max_texture_size", &self.max_texture_size)
+            .field("preprocess", &self.preprocess.is_some())
+            .field("zoom_range", &self.zoom_range)
+            .field("zoom_levels", &self.zoom_levels)
+            .field("bounds", &self.bounds)
+            .field("fetch_mode", &self.fetch_mode)
+            .field("credentials", &self.credentials)
+            .finish()
+    }
+}
+
+impl Default for TilesOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_texture_size: None,
+            preprocess: None,
+            zoom_range: 0..=MAX_ZOOM,
+            zoom_levels: None,
+            bounds: None,
+            fetch_mode: FetchMode::default(),
+            credentials: Credentials::default(),
+        }
+    }
+}
+
+impl Tiles {
+    pub fn new<S, C>(source: S, egui_ctx: Context, cache: C) -> Self
+    where
+        S: Fn(TileId) -> String + Send + Sync + 'static,
+        C: Fn(&TileId) -> Result<Tile, String> + 'static,
+    {
+        Self::with_options(source, egui_ctx, cache, TilesOptions::default())
+    }
+
+    /// Tiles generated locally by `render`, instead of downloaded, e.g. for procedural or game
+    /// maps. Each is rendered once, when it's needed for the first time, and then cached. Images
+    /// can be larger than 256 pixels, e.g. to be sharp on a high DPI screen, they are still drawn
+    /// as 256 points wide tiles.
+    pub fn rendered<R>(render: R, egui_ctx: Context) -> Self
+    where
+        R: Fn(TileId) -> ColorImage + 'static,
+    {
+        Self::new(
+            // Never reached, as the hard cache always has the tile.
+            |_| String::new(),
+            egui_ctx,
+            move |tile_id: &TileId| {
+                let image = RetainedImage::from_color_image("rendered tile", render(*tile_id));
+                Ok(Tile {
+                    size: Vec2::splat(TILE_SIZE as f32),
+                    ..Tile::from_retained_image(image)
+                })
+            },
+        )
+    }
+
+    /// Like [`Tiles::new`], but lets you tweak how the tiles are downloaded.
+    pub fn with_options<S, C>(source: S, egui_ctx: Context, cache: C, options: TilesOptions) -> Self
+    where
+        S: Fn(TileId) -> String + Send + Sync + 'static,
+        C: Fn(&TileId) -> Result<Tile, String> + 'static,
+    {
+        Self::from_source(Source::Url(Box::new(source)), egui_ctx, cache, options)
+    }
+
+    /// Tiles from providers which need more than a URL, e.g. signed requests or a `POST`
+    /// body. `requests` builds a complete request (method, URL, headers and body) of each tile,
+    /// which is then executed as is.
+    pub fn with_requests<R, C>(
+        requests: R,
+        egui_ctx: Context,
+        cache: C,
+        options: TilesOptions,
+    ) -> Self
+    where
+        R: Fn(TileId) -> Request + Send + Sync + 'static,
+        C: Fn(&TileId) -> Result<Tile, String> + 'static,
+    {
+        Self::from_source(
+            Source::Request(Box::new(requests)),
+            egui_ctx,
+            cache,
+            options,
+        )
+    }
+
+    /// Like [`Tiles::new`], but for providers serving the tiles in more than one format, e.g. a
+    /// smaller WebP and a PNG for compatibility. `urls` are tried in order of preference, moving to
+    /// the next one when the server responds with `404 Not Found`, `406 Not Acceptable` or
+    /// `415 Unsupported Media Type`. The latter two mean that the format is not served at all, so
+    /// it's not tried anymore, while a `404` falls back just for that tile.
+    ///
+    /// # Panics
+    ///
+    /// If `urls` are empty.
+    pub fn with_formats<C>(urls: Vec<Box<TileUrl>>, egui_ctx: Context, cache: C) -> Self
+    where
+        C: Fn(&TileId) -> Result<Tile, String> + 'static,
+    {
+        assert!(!urls.is_empty(), "at least one URL is needed");
+        Self::from_source(
+            Source::Formats {
+                urls,
+                first: AtomicUsize::new(0),
+            },
+            egui_ctx,
+            cache,
+            TilesOptions::default(),
+        )
+    }
+
+    /// Tiles fetched by the application itself, e.g. in a sandbox which does not let the crate
+    /// make network calls. Each tile which is needed is sent to `requests` as a [`TileRequest`],
+    /// to be answered with the tile's bytes, from any thread and whenever they are there. These
+    /// are decoded and cached just like the downloaded ones. Up to 8 requests wait for the
+    /// response at once, so they can be fetched in parallel. Ones which are not answered within
+    /// the [`TilesOptions::timeout`] are requested again, next time the tile is needed.
+    pub fn external<C>(
+        requests: std::sync::mpsc::Sender<TileRequest>,
+        egui_ctx: Context,
+        cache: C,
+        options: TilesOptions,
+    ) -> Self
+    where
+        C: Fn(&TileId) -> Result<Tile, String> + 'static,
+    {
+        Self::from_source(Source::External(requests), egui_ctx, cache, options)
+    }
+
+    /// Tiles of more than one provider, tried in order for each of the tiles, e.g. a primary
+    /// server and the OpenStreetMap in case it's down. When a provider fails to serve a tile,
+    /// the next one is asked for it. Each provider comes with its attribution, see
+    /// [`Tiles::attributions`].
+    ///
+    /// # Panics
+    ///
+    /// If `providers` are empty.
+    pub fn with_fallbacks<C>(
+        providers: Vec<(Box<TileUrl>, Attribution)>,
+        egui_ctx: Context,
+        cache: C,
+    ) -> Self
+    where
+        C: Fn(&TileId) -> Result<Tile, String> + 'static,
+    {
+        assert!(!providers.is_empty(), "at least one provider is needed");
+        let (urls, attributions): (Vec<_>, Vec<_>) = providers.into_iter().unzip();
+        let mut tiles = Self::from_source(
+            Source::Fallbacks(urls),
+            egui_ctx,
+            cache,
+            TilesOptions::default(),
+        );
+        tiles.attribution = attributions.first().cloned();
+        tiles.provider_attributions = attributions;
+        tiles
+    }
+
+    /// Tiles which change over time, e.g. frames of a weather radar loop. `source` gets the time
+    /// along with the tile, typically put in place of a `{time}` placeholder of the URL. Tiles of
+    /// each time are cached separately, see [`Tiles::set_time`]. There is no hard cache, as it
+    /// knows nothing about time.
+    pub fn temporal<S>(source: S, time: impl Into<String>, egui_ctx: Context) -> Self
+    where
+        S: Fn(TileId, &str) -> String + Send + Sync + 'static,
+    {
+        let mut tiles = Self::from_source(
+            Source::Temporal(Box::new(source)),
+            egui_ctx,
+            |_| Err("tiles of time are not hard cached".to_owned()),
+            TilesOptions::default(),
+        );
+        tiles.time = Some(time.into());
+        tiles
+    }
+
+    /// Tiles of a provider described by a [TileJSON](https://github.com/mapbox/tilejson-spec)
+    /// document at `url`. The tile URL, attribution, zoom levels and bounds are all taken from it.
+    pub async fn from_tilejson<C>(
+        url: &str,
+        egui_ctx: Context,
+        cache: C,
+    ) -> Result<Self, TileJsonError>
+    where
+        C: Fn(&TileId) -> Result<Tile, String> + 'static,
+    {
+        let json = reqwest::Client::new()
+            .get(url)
+            .header(USER_AGENT, "Walkers")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let tilejson = TileJson::parse(&json)?;
+
+        let options = TilesOptions {
+            zoom_range: tilejson.zoom_range(),
+            bounds: tilejson.bounds(),
+            ..Default::default()
+        };
+        let attribution = tilejson.attribution.clone().map(Attribution::new);
+        let mut tiles = Self::with_options(
+            move |tile_id| tilejson.url(tile_id),
+            egui_ctx,
+            cache,
+            options,
+        );
+        tiles.attribution = attribution;
+        Ok(tiles)
+    }
+
+    fn from_source<C>(source: Source, egui_ctx: Context, cache: C, options: TilesOptions) -> Self
+    where
+        C: Fn(&TileId) -> Result<Tile, String> + 'static,
+    {
+        let tokio_runtime_thread = TokioRuntimeThread::new();
+        let source = Arc::new(source);
+
+        // Minimum value which didn't cause any stalls while testing.
+        let channel_size = 20;
+
+        let (request_tx, request_rx) = tokio::sync::mpsc::channel(channel_size);
+        let (warm_tx, warm_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (tile_tx, tile_rx) = tokio::sync::mpsc::channel(channel_size);
+        let zoom_range = options.zoom_range.clone();
+        let zoom_levels = options.zoom_levels.clone();
+        let bounds = options.bounds;
+        let fetch = (options.fetch_mode, options.credentials);
+        let shared = Arc::new(Shared {
+            downloaded_bytes: AtomicU64::new(0),
+            invalid_responses: AtomicU64::new(0),
+            network_enabled: AtomicBool::new(true),
+        });
+        let (egui_ctx, egui_ctx_rx) = tokio::sync::watch::channel(egui_ctx);
+        tokio_runtime_thread.runtime.spawn(download(
+            source.clone(),
+            request_rx,
+            warm_rx,
+            tile_tx,
+            egui_ctx_rx,
+            options,
+            shared.clone(),
+        ));
+        Self {
+            cache: Default::default(),
+            time: None,
+            slices: HashMap::new(),
+            request_tx,
+            warm_tx,
+            tile_rx,
+            in_flight: HashSet::new(),
+            warmed: HashSet::new(),
+            source,
+            egui_ctx,
+            tokio_runtime_thread,
+            hard_cache: Box::new(cache),
+            zoom_range,
+            zoom_levels,
+            bounds,
+            fetch,
+            attribution: None,
+            provider_attributions: Vec::new(),
+            stats: TileStats::default(),
+            events: None,
+            shared,
+        }
+    }
+
+    /// Return a tile if already in cache, schedule a download otherwise.
+    pub fn at(&mut self, tile_id: TileId) -> Option<Tile> {
+        if !self.is_available(tile_id) {
+            return None;
+        }
+
+        let tile = self.lookup(tile_id);
+        if tile.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+        tile
+    }
+
+    fn lookup(&mut self, tile_id: TileId) -> Option<Tile> {
+        // Just take one at the time.
+        match self.tile_rx.try_recv() {
+            Ok((key, downloaded)) => self.receive(key, downloaded),
+            Err(TryRecvError::Empty) => {
+                // Just ignore. It means that no new tile was downloaded.
+            }
+            Err(TryRecvError::Disconnected) => panic!("IO thread is dead"),
+        }
+
+        let network_enabled = self.network_enabled();
+        let key = TileKey {
+            tile_id,
+            time: self.time.clone(),
+        };
+        match self.cache.entry(tile_id) {
+            Entry::Occupied(entry) => {
+                let tile = entry.get().clone();
+                if tile.is_some() {
+                    self.warmed.remove(&key);
+                }
+                tile
+            }
+            Entry::Vacant(entry) => {
+                if let Ok(tile) = (self.hard_cache)(&tile_id) {
+                    // Ask the server whether the cached tile is still fresh. If the queue is
+                    // full, just stay with what we have.
+                    let etag = tile.etag().filter(|_| network_enabled);
+                    if let Some(etag) = etag.filter(|_| !self.in_flight.contains(&key)) {
+                        if let Ok(()) = self
+                            .request_tx
+                            .try_send((key.clone(), Some(etag.to_owned())))
+                        {
+                            log::debug!("Revalidating tile: {:?}", tile_id);
+                            self.stats.requested += 1;
+                            self.in_flight.insert(key);
+                        }
+                    }
+                    entry.insert(Some(tile.clone()));
+                    self.record_caching(tile_id, &tile);
+                    return Some(tile);
+                }
+                if !network_enabled {
+                    return None;
+                }
+                if self.in_flight.contains(&key) {
+                    // Still being downloaded, it ends up in the cache once it's there.
+                    entry.insert(None);
+                } else if let Ok(()) = self.request_tx.try_send((key.clone(), None)) {
+                    log::debug!("Requested tile: {:?}", tile_id);
+                    self.stats.requested += 1;
+                    self.in_flight.insert(key);
+                    entry.insert(None);
+                } else {
+                    log::debug!("Request queue is full.");
+                }
+                None
+            }
+        }
+    }
+
+    /// Put what the IO thread did with a tile into the cache.
+    fn receive(&mut self, key: TileKey, downloaded: Downloaded) {
+        self.in_flight.remove(&key);
+        let mut replaced = None;
+        // Time might have been forgotten in the meantime.
+        if let Some(cache) = self.cache_of(key.time.as_deref()) {
+            match downloaded {
+                Downloaded::Tile(tile) => {
+                    replaced = Some((cache.insert(key.tile_id, Some(tile.clone())), tile));
+                }
+                Downloaded::Retry => {
+                    // Forget about it, so it gets requested again.
+                    if let Some(None) = cache.get(&key.tile_id) {
+                        cache.remove(&key.tile_id);
+                    }
+                }
+                Downloaded::Nothing => {}
+            }
+        }
+
+        if let Some((old, new)) = replaced {
+            record_eviction(&mut self.events, key.tile_id, &old.flatten());
+            self.record_caching(key.tile_id, &new);
+        }
+    }
+
+    /// Note that the `tile` got into the memory cache, if the events are recorded.
+    fn record_caching(&mut self, tile_id: TileId, tile: &Tile) {
+        if let (Some(events), Some(bytes)) = (&mut self.events, texture_bytes(tile)) {
+            events.push(TileEvent::Cached { tile_id, bytes });
+        }
+    }
+
+    /// Wait until given tile is in the cache, downloading it if needed, e.g. to take a
+    /// screenshot of a map once it's complete. Unlike [`Tiles::at`], it does not need the map
+    /// to be shown in the meantime. Timed out downloads are retried.
+    pub async fn tile_ready(&mut self, tile_id: TileId) -> Result<(), TileError> {
+        if !self.is_available(tile_id) {
+            return Err(TileError::Unavailable);
+        }
+
+        loop {
+            if self.lookup(tile_id).is_some() {
+                return Ok(());
+            }
+            if !self.network_enabled() {
+                return Err(TileError::Offline);
+            }
+
+            let key = TileKey {
+                tile_id,
+                time: self.time.clone(),
+            };
+            if self.cache.contains_key(&tile_id) && !self.in_flight.contains(&key) {
+                // Requested before and it did not make it to the cache.
+                return Err(TileError::Failed);
+            }
+
+            // If the request queue was full, it's not in flight yet, but then something else
+            // is, so there is an outcome to wait for anyway.
+            let Some((received, downloaded)) = self.tile_rx.recv().await else {
+                panic!("IO thread is dead");
+            };
+            let failed = received == key && matches!(downloaded, Downloaded::Nothing);
+            self.receive(received, downloaded);
+            if failed && self.cache.get(&tile_id).is_none_or(Option::is_none) {
+                return Err(TileError::Failed);
+            }
+        }
+    }
+
+    /// Start loading the tiles of a map of given `size`, centered at `center` at given `zoom`
+    /// level, e.g. before flying there, so that they are ready once the map gets there. Tiles
+    /// needed by the map right away are downloaded first. Ones found in the hard cache are just
+    /// put in memory.
+    ///
+    /// Warmed tiles are kept in memory until they are shown, even if they are far away from the
+    /// map. Only the ones of the most recent call are, though, so warming another place lets the
+    /// tiles of the previous one go, like any other tiles outside the view.
+    pub fn warm(&mut self, center: Position, zoom: u8, size: Vec2) {
+        self.warmed.clear();
+
+        let center = center.project(zoom);
+        let bounds = BoundingBox::from_corners(
+            screen_to_position(&(center - size / 2.), zoom),
+            screen_to_position(&(center + size / 2.), zoom),
+        );
+
+        let network_enabled = self.network_enabled();
+        for tile_id in bounds.tile_ids(zoom) {
+            let key = TileKey {
+                tile_id,
+                time: self.time.clone(),
+            };
+            if !self.is_available(tile_id)
+                || self.cache.contains_key(&tile_id)
+                || self.in_flight.contains(&key)
+            {
+                continue;
+            }
+
+            if let Ok(tile) = (self.hard_cache)(&tile_id) {
+                self.record_caching(tile_id, &tile);
+                self.cache.insert(tile_id, Some(tile));
+                self.warmed.insert(key);
+            } else if network_enabled && self.warm_tx.send(key.clone()).is_ok() {
+                log::debug!("Warming tile: {:?}", tile_id);
+                self.stats.requested += 1;
+                self.in_flight.insert(key.clone());
+                self.cache.insert(tile_id, None);
+                self.warmed.insert(key);
+            }
+        }
+    }
+
+    /// See [`TilesOptions::zoom_levels`].
+    pub fn zoom_levels(&self) -> Option<&[u8]> {
+        self.zoom_levels.as_deref()
+    }
+
+    /// Whether the provider has given tile at all, see [`TilesOptions::zoom_range`],
+    /// [`TilesOptions::zoom_levels`] and [`TilesOptions::bounds`].
+    fn is_available(&self, tile_id: TileId) -> bool {
+        self.zoom_range.contains(&tile_id.zoom)
+            && self
+                .zoom_levels
+                .as_ref()
+                .is_none_or(|levels| levels.contains(&tile_id.zoom))
+            && self
+                .bounds
+                .is_none_or(|bounds| bounds.intersects(&tile_id.to_bounds()))
+    }
+
+    /// Totals since the tiles were created. Subtract two snapshots to get e.g. the numbers of a
+    /// single frame. Tiles downloaded by [`Tiles::download_region`] are not counted.
+    pub fn stats(&self) -> TileStats {
+        TileStats {
+            downloaded_bytes: self.shared.downloaded_bytes.load(Ordering::Relaxed),
+            invalid_responses: self.shared.invalid_responses.load(Ordering::Relaxed),
+            ..self.stats
+        }
+    }
+
+    /// Stop (or resume) downloading the tiles, e.g. to save data in a metered mode. While disabled,
+    /// only the tiles which are already in the memory or the hard cache are shown, and the queued
+    /// downloads are dropped. Once enabled again, missing tiles of the current view are requested
+    /// as soon as they are needed. [`Tiles::download_region`] is not affected.
+    pub fn set_network_enabled(&mut self, enabled: bool) {
+        self.shared
+            .network_enabled
+            .store(enabled, Ordering::Relaxed);
+        if !enabled {
+            // Forget the pending downloads, so they are requested again later.
+            for cache in std::iter::once(&mut self.cache).chain(self.slices.values_mut()) {
+                cache.retain(|_, tile| tile.is_some());
+            }
+        }
+    }
+
+    /// See [`Tiles::set_network_enabled`].
+    pub fn network_enabled(&self) -> bool {
+        self.shared.network_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Start using a new egui [`Context`], e.g. after the application recreated it or switched
+    /// renderers. Textures of the tiles in the memory cache belong to the old one, so they are
+    /// dropped, and tiles are uploaded again when they are needed. If your hard cache keeps
+    /// [`Tile`]s themselves, rather than their bytes, it needs to be cleared as well.
+    pub fn rebind(&mut self, egui_ctx: Context) {
+        self.egui_ctx.send_replace(egui_ctx);
+        // Pending downloads are kept, these do not have any textures yet.
+        for cache in std::iter::once(&mut self.cache).chain(self.slices.values_mut()) {
+            cache.retain(|tile_id, tile| {
+                record_eviction(&mut self.events, *tile_id, tile);
+                tile.is_none()
+            });
+        }
+    }
+
+    /// Switch to the tiles of another `time`, see [`Tiles::temporal`]. Tiles of the previous
+    /// time stay in memory, so that going back and forth, e.g. to animate a loop, does not
+    /// download them again. Use [`Tiles::forget_time`] to drop the ones which are not needed
+    /// anymore.
+    pub fn set_time(&mut self, time: impl Into<String>) {
+        let time = time.into();
+        if self.time.as_ref() == Some(&time) {
+            return;
+        }
+        let cache = self.slices.remove(&time).unwrap_or_default();
+        let previous = std::mem::replace(&mut self.cache, cache);
+        if let Some(previous_time) = self.time.replace(time) {
+            self.slices.insert(previous_time, previous);
+        }
+    }
+
+    /// Time of the tiles which are currently drawn, see [`Tiles::set_time`].
+    pub fn time(&self) -> Option<&str> {
+        self.time.as_deref()
+    }
+
+    /// Drop the cached tiles of a `time` other than the current one.
+    pub fn forget_time(&mut self, time: &str) {
+        for (tile_id, tile) in self.slices.remove(time).unwrap_or_default() {
+            record_eviction(&mut self.events, tile_id, &tile);
+        }
+    }
+
+    /// Cache of the tiles of given `time`, if it's still kept.
+    fn cache_of(&mut self, time: Option<&str>) -> Option<&mut HashMap<TileId, Option<Tile>>> {
+        if time == self.time.as_deref() {
+            Some(&mut self.cache)
+        } else {
+            self.slices.get_mut(time?)
+        }
+    }
+
+    /// Credits to the provider, if known, e.g. from [`Tiles::from_tilejson`].
+    pub fn attribution(&self) -> Option<&Attribution> {
+        self.attribution.as_ref()
+    }
+
+    /// Credits to the providers which served any of the `tile_ids`, e.g. the ones given by
+    /// [`crate::MapMemory::tiles_in_view`]. With [`Tiles::with_fallbacks`], these are the ones of
+    /// the providers which actually served the tiles, without repetitions. Otherwise, it's
+    /// just the [`Tiles::attribution`].
+    pub fn attributions(&self, tile_ids: &[TileId]) -> Vec<&Attribution> {
+        if self.provider_attributions.is_empty() {
+            return self.attribution.iter().collect();
+        }
+
+        let mut providers: Vec<usize> = tile_ids
+            .iter()
+            .filter_map(|tile_id| self.cached(*tile_id))
+            .map(|tile| tile.provider)
+            .collect();
+        providers.sort_unstable();
+        providers.dedup();
+        providers
+            .into_iter()
+            .filter_map(|provider| self.provider_attributions.get(provider))
+            .collect()
+    }
+
+    /// Set the [`Tiles::attribution`].
+    pub fn with_attribution(self, attribution: Attribution) -> Self {
+        Self {
+            attribution: Some(attribution),
+            ..self
+        }
+    }
+
+    /// Tile which is already in the cache. Unlike [`Tiles::at`], it does not request it.
+    pub(crate) fn cached(&self, tile_id: TileId) -> Option<&Tile> {
+        self.cache.get(&tile_id)?.as_ref()
+    }
+
+    pub fn insert(&mut self, tile_id: TileId, tile: Tile) {
+        let old = self.cache.insert(tile_id, Some(tile.clone()));
+        record_eviction(&mut self.events, tile_id, &old.flatten());
+        self.record_caching(tile_id, &tile);
+    }
+
+    pub fn cache(&self) -> &HashMap<TileId, Option<Tile>> {
+        &self.cache
+    }
+
+    /// Whether all tiles covering `bounds` at given `zoom` level are already there. Together with
+    /// [`crate::MapMemory::visible_bounds`], it tells whether the map is fully drawn, e.g. to
+    /// reveal it only then.
+    pub fn is_loaded(&self, bounds: BoundingBox, zoom: u8) -> bool {
+        bounds
+            .tile_ids(zoom)
+            .all(|tile_id| self.is_tile_loaded(tile_id))
+    }
+
+    /// Whether all of the `tile_ids` are already there, e.g. the ones given by
+    /// [`crate::MapMemory::tiles_in_view`]. Pump the frames until it's true to make sure that
+    /// the map is complete, before e.g. taking a screenshot.
+    pub fn all_visible_loaded(&self, tile_ids: &[TileId]) -> bool {
+        tile_ids.iter().all(|tile_id| self.is_tile_loaded(*tile_id))
+    }
+
+    fn is_tile_loaded(&self, tile_id: TileId) -> bool {
+        // Ones which are never going to be there do not count.
+        !self.is_available(tile_id) || matches!(self.cache.get(&tile_id), Some(Some(_)))
+    }
+
+    /// Drop the tiles of the current time for which `keep` returns `false`, except for the
+    /// warmed ones which were not shown yet, see [`Tiles::warm`].
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(TileId) -> bool) {
+        let warmed: HashSet<TileId> = self
+            .warmed
+            .iter()
+            .filter(|key| key.time == self.time)
+            .map(|key| key.tile_id)
+            .collect();
+        self.drop_tiles(|tile_id| warmed.contains(&tile_id) || keep(tile_id));
+    }
+
+    /// Drop the tiles of the current time for which `keep` returns `false`.
+    fn drop_tiles(&mut self, mut keep: impl FnMut(TileId) -> bool) {
+        self.cache.retain(|tile_id, tile| {
+            let kept = keep(*tile_id);
+            if !kept {
+                record_eviction(&mut self.events, *tile_id, tile);
+            }
+            kept
+        });
+    }
+
+    /// Drop the tiles of the current time which are not of one of the `zooms`, except for the
+    /// warmed ones which were not shown yet, like [`Tiles::retain`].
+    pub(crate) fn retain_zooms(&mut self, zooms: RangeInclusive<u8>) {
+        log::debug!("Keeping zooms: {zooms:?}");
+        self.retain(|tile_id| zooms.contains(&tile_id.zoom));
+    }
+
+    pub fn clean_up_zoom(&mut self, zoom: u8) {
+        log::debug!("Clean up zoom: {zoom}");
+        self.drop_tiles(|tile_id| tile_id.zoom != zoom);
+        self.warmed.retain(|key| key.tile_id.zoom != zoom);
+    }
+
+    /// Record the [`TileEvent`]s, so that the application can account for the memory taken by
+    /// the textures of the tiles, e.g. to keep within its own GPU budget. Collect them with
+    /// [`Tiles::take_events`], e.g. once per frame.
+    pub fn with_events(self) -> Self {
+        Self {
+            events: Some(Vec::new()),
+            ..self
+        }
+    }
+
+    /// Events recorded since the previous call, oldest first. Always empty, unless enabled with
+    /// [`Tiles::with_events`].
+    pub fn take_events(&mut self) -> Vec<TileEvent> {
+        self.events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Download all tiles covering `bounds` at each of the `zooms`, so that they can be used
+    /// while offline. Tiles are not kept in memory, they are handed to `store` along with their
+    /// `ETag` instead, which is expected to put them where the hard cache will find them.
+    /// Download happens in the background, one tile at the time.
+    pub fn download_region<F>(
+        &self,
+        bounds: BoundingBox,
+        zooms: RangeInclusive<u8>,
+        store: F,
+    ) -> RegionDownload
+    where
+        F: FnMut(TileId, &[u8], Option<&str>) + Send + 'static,
+    {
+        let tile_ids: Vec<_> = zooms.flat_map(|zoom| bounds.tile_ids(zoom)).collect();
+        let progress = RegionDownload {
+            total: tile_ids.len(),
+            downloaded: Default::default(),
+            failed: Default::default(),
+        };
+
+        log::debug!("Downloading {} tiles of {:?}.", tile_ids.len(), bounds);
+
+        self.tokio_runtime_thread.runtime.spawn(download_region(
+            self.source.clone(),
+            tile_ids,
+            self.time.clone(),
+            store,
+            progress.clone(),
+            self.egui_ctx.subscribe(),
+            Client {
+                http: reqwest::Client::new(),
+                fetch: self.fetch,
+            },
+        ));
+
+        progress
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error(transparent)]
+    Http(reqwest::Error),
+
+    #[error(transparent)]
+    Io(std::io::Error),
+
+    #[error("error while decoding the image: {0}")]
+    Image(String),
+
+    /// See [`TileRequest`].
+    #[error("tile request was dropped without a response")]
+    NoResponse,
+
+    /// See [`Tiles::external`].
+    #[error("no response in time")]
+    TimedOut,
+
+    /// Typically an HTML error page, served by a misconfigured provider.
+    #[error("response is not an image, it starts with: {0:?}")]
+    NotAnImage(String),
+}
+
+/// How much of a response which turned out not to be an image is shown in the logs.
+const NOT_AN_IMAGE_PREVIEW: usize = 64;
+
+/// HTTP client, along with how the requests it builds are to be fetched.
+#[derive(Clone)]
+struct Client {
+    http: reqwest::Client,
+
+    /// See [`TilesOptions::fetch_mode`] and [`TilesOptions::credentials`].
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    fetch: (FetchMode, Credentials),
+}
+
+impl Client {
+    /// Apply the [`Client::fetch`] settings to the `request`. Only the browser's `fetch` knows
+    /// about them.
+    #[cfg(target_arch = "wasm32")]
+    fn configure(&self, request: RequestBuilder) -> RequestBuilder {
+        let (mode, credentials) = self.fetch;
+        let request = match mode {
+            FetchMode::Cors => request,
+            FetchMode::NoCors => request.fetch_mode_no_cors(),
+        };
+        match credentials {
+            Credentials::Omit => request.fetch_credentials_omit(),
+            Credentials::SameOrigin => request.fetch_credentials_same_origin(),
+            Credentials::Include => request.fetch_credentials_include(),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn configure(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+    }
+}
+
+/// Raw bytes of a downloaded tile.
+struct Fetched {
+    image: Bytes,
+    etag: Option<String>,
+
+    /// Index of the provider which served the tile, see [`Source::Fallbacks`].
+    provider: usize,
+}
+
+impl Fetched {
+    fn new(fetched: Option<(Bytes, Option<String>)>, provider: usize) -> Option<Self> {
+        fetched.map(|(image, etag)| Self {
+            image,
+            etag,
+            provider,
+        })
+    }
+}
+
+/// Download raw bytes of a single tile, along with its `ETag`. If `etag` is given and the server
+/// confirms that the tile did not change, `None` is returned.
+async fn fetch(
+    client: &Client,
+    source: &Source,
+    tile_id: TileId,
+    time: Option<&str>,
+    etag: Option<&str>,
+) -> Result<Option<Fetched>, Error> {
+    let Source::Fallbacks(providers) = source else {
+        let fetched = fetch_one(client, source, tile_id, time, etag).await?;
+        return Ok(Fetched::new(fetched, 0));
+    };
+
+    let (last, fallbacks) = providers.split_last().expect("no providers");
+    for (provider, url) in fallbacks.iter().enumerate() {
+        match fetch_url(client, url(tile_id), tile_id, etag).await {
+            // Might be just the network, the next provider would not do better.
+            Err(Error::Http(e)) if e.is_timeout() => return Err(Error::Http(e)),
+            Err(e) => log::debug!("{:?} failed: {}, trying the next provider.", tile_id, e),
+            Ok(fetched) => return Ok(Fetched::new(fetched, provider)),
+        }
+    }
+    let fetched = fetch_url(client, last(tile_id), tile_id, etag).await?;
+    Ok(Fetched::new(fetched, fallbacks.len()))
+}
+
+/// Like [`fetch`], but for the sources which have a single provider.
+async fn fetch_one(
+    client: &Client,
+    source: &Source,
+    tile_id: TileId,
+    time: Option<&str>,
+    etag: Option<&str>,
+) -> Result<Option<(Bytes, Option<String>)>, Error> {
+    let url = match source {
+        Source::Url(source) => source(tile_id),
+        Source::Temporal(source) => source(tile_id, time.unwrap_or_default()),
+        // Going through the providers in turn is the `fetch`'s job, it never gets here.
+        Source::Fallbacks(_) => unreachable!("fallbacks are handled by the fetch"),
+        Source::Request(source) => {
+            return read(send(client, tile_id, source(tile_id), etag).await?).await;
+        }
+        Source::External(requests) => {
+            let (response, image) = tokio::sync::oneshot::channel();
+            requests
+                .send(TileRequest { tile_id, response })
+                .map_err(|_| Error::NoResponse)?;
+            let image = image.await.map_err(|_| Error::NoResponse)?;
+            return Ok(Some((image, None)));
+        }
+        Source::Formats { urls, first } => {
+            let preferred = first.load(Ordering::Relaxed);
+            let (last, fallbacks) = urls[preferred..].split_last().expect("no tile URLs");
+            for (n, url) in fallbacks.iter().enumerate() {
+                let response = send(client, tile_id, get(client, url(tile_id))?, etag).await?;
+                match response.status() {
+                    // Just this tile is missing, others might still be there.
+                    StatusCode::NOT_FOUND => {}
+                    // Whole format is not served, so it's not tried anymore.
+                    StatusCode::NOT_ACCEPTABLE | StatusCode::UNSUPPORTED_MEDIA_TYPE => {
+                        first.fetch_max(preferred + n + 1, Ordering::Relaxed);
+                    }
+                    _ => return read(response).await,
+                }
+                log::debug!(
+                    "{:?} is not served in this format, trying the next.",
+                    tile_id
+                );
+            }
+
+            return read(send(client, tile_id, get(client, last(tile_id))?, etag).await?).await;
+        }
+    };
+
+    fetch_url(client, url, tile_id, etag).await
+}
+
+/// Download the tile from the `url`. `file://` URLs are read from the disk, see
+/// [`crate::providers::filesystem`].
+async fn fetch_url(
+    client: &Client,
+    url: String,
+    tile_id: TileId,
+    etag: Option<&str>,
+) -> Result<Option<(Bytes, Option<String>)>, Error> {
+    if let Some(path) = url.strip_prefix(FILE_SCHEME) {
+        let image = tokio::fs::read(path).await.map_err(Error::Io)?;
+        return Ok(Some((image.into(), None)));
+    }
+    read(send(client, tile_id, get(client, url)?, etag).await?).await
+}
+
+fn get(client: &Client, url: String) -> Result<Request, Error> {
+    client
+        .configure(client.http.get(url).header(USER_AGENT, "Walkers"))
+        .build()
+        .map_err(Error::Http)
+}
+
+/// Send the `request`, asking only for a newer version than `etag`, if given.
+async fn send(
+    client: &Client,
+    tile_id: TileId,
+    mut request: Request,
+    etag: Option<&str>,
+) -> Result<reqwest::Response, Error> {
+    log::debug!("Getting {:?} from {}.", tile_id, request.url());
+
+    if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(etag).ok()) {
+        request.headers_mut().insert(IF_NONE_MATCH, etag);
+    }
+
+    let response = client.http.execute(request).await.map_err(Error::Http)?;
+    log::debug!("Downloaded {:?}.", response.status());
+    Ok(response)
+}
+
+/// Tile's bytes and its `ETag`, or `None` if it was not modified.
+async fn read(image: reqwest::Response) -> Result<Option<(Bytes, Option<String>)>, Error> {
+    if image.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+
+    let image = image.error_for_status().map_err(Error::Http)?;
+
+    let etag = image
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(ToOwned::to_owned);
+
+    let image = image.bytes().await.map_err(Error::Http)?;
+
+    Ok(Some((image, etag)))
+}
+
+/// Download and decode a single tile. If `etag` is given and the server confirms that the tile
+/// did not change, `None` is returned.
+async fn download_single(
+    client: &Client,
+    source: &Source,
+    key: &TileKey,
+    etag: Option<&str>,
+    options: &TilesOptions,
+    downloaded_bytes: &AtomicU64,
+) -> Result<Option<Tile>, Error> {
+    let tile_id = key.tile_id;
+    let Some(Fetched {
+        image,
+        etag,
+        provider,
+    }) = fetch(client, source, tile_id, key.time.as_deref(), etag).await?
+    else {
+        return Ok(None);
+    };
+    downloaded_bytes.fetch_add(image.len() as u64, Ordering::Relaxed);
+
+    if image.is_empty() {
+        log::debug!("{:?} is empty.", tile_id);
+        let tile = Tile {
+            provider,
+            ..Tile::empty()
+        };
+        return Ok(Some(match etag {
+            Some(etag) => tile.with_etag(etag),
+            None => tile,
+        }));
+    }
+
+    let image = match &options.preprocess {
+        Some(preprocess) => preprocess(tile_id, image).map_err(Error::Image)?,
+        None => image,
+    };
+
+    if image::guess_format(&image).is_err() {
+        let preview = &image[..image.len().min(NOT_AN_IMAGE_PREVIEW)];
+        return Err(Error::NotAnImage(
+            String::from_utf8_lossy(preview).into_owned(),
+        ));
+    }
+
+    let tile = match options.max_texture_size {
+        Some(max_size) => Tile::from_image_bytes_downsampled(&image, max_size),
+        None => Tile::from_image_bytes(&image),
+    }
+    .map_err(Error::Image)?;
+    let tile = Tile { provider, ..tile };
+
+    Ok(Some(match etag {
+        Some(etag) => tile.with_etag(etag),
+        None => tile,
+    }))
+}
+
+/// How many [`TileRequest`]s of the [`Tiles::external`] can wait for the response at once.
+const EXTERNAL_REQUESTS: usize = 8;
+
+async fn download(
+    source: Arc<Source>,
+    mut request_rx: tokio::sync::mpsc::Receiver<(TileKey, Option<String>)>,
+    mut warm_rx: tokio::sync::mpsc::UnboundedReceiver<TileKey>,
+    tile_tx: tokio::sync::mpsc::Sender<(TileKey, Downloaded)>,
+    egui_ctx: tokio::sync::watch::Receiver<Context>,
+    options: TilesOptions,
+    shared: Arc<Shared>,
+) -> Result<(), ()> {
+    // Keep outside the loop to reuse it as much as possible.
+    let client = Client {
+        http: reqwest::Client::builder()
+            .timeout(options.timeout)
+            .build()
+            .map_err(|e| log::error!("Could not create HTTP client: {}", e))?,
+        fetch: (options.fetch_mode, options.credentials),
+    };
+
+    let downloader = Downloader {
+        client,
+        source,
+        tile_tx,
+        egui_ctx,
+        options,
+        shared,
+    };
+    let external_slots = Arc::new(tokio::sync::Semaphore::new(EXTERNAL_REQUESTS));
+
+    loop {
+        let (request, etag) = tokio::select! {
+            // Tiles needed by the map right now go first.
+            biased;
+            request = request_rx.recv() => request,
+            request = warm_rx.recv() => request.map(|request| (request, None)),
+        }
+        .ok_or(())?;
+
+        if matches!(*downloader.source, Source::External(_)) {
+            // Application might be fetching these in parallel, so do not wait for each of them.
+            let slot = external_slots
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|_| ())?;
+            let downloader = downloader.clone();
+            tokio::spawn(async move {
+                let _ = downloader.download(request, etag).await;
+                drop(slot);
+            });
+        } else {
+            downloader.download(request, etag).await?;
+        }
+    }
+}
+
+/// Everything the IO thread needs to download a tile and hand it over to the [`Tiles`].
+#[derive(Clone)]
+struct Downloader {
+    client: Client,
+    source: Arc<Source>,
+    tile_tx: tokio::sync::mpsc::Sender<(TileKey, Downloaded)>,
+    egui_ctx: tokio::sync::watch::Receiver<Context>,
+    options: TilesOptions,
+    shared: Arc<Shared>,
+}
+
+impl Downloader {
+    async fn download(&self, request: TileKey, etag: Option<String>) -> Result<(), ()> {
+        if !self.shared.network_enabled.load(Ordering::Relaxed) {
+            log::debug!("Network is disabled, dropping request of {:?}.", request);
+            return self
+                .tile_tx
+                .send((request, Downloaded::Retry))
+                .await
+                .map_err(|_| ());
+        }
+
+        let download = download_single(
+            &self.client,
+            &self.source,
+            &request,
+            etag.as_deref(),
+            &self.options,
+            &self.shared.downloaded_bytes,
+        );
+        let result = match *self.source {
+            // HTTP client takes care of the timeout of the others.
+            Source::External(_) => tokio::time::timeout(self.options.timeout, download)
+                .await
+                .unwrap_or(Err(Error::TimedOut)),
+            _ => download.await,
+        };
+
+        let downloaded = match result {
+            Ok(Some(tile)) => Downloaded::Tile(tile),
+            Ok(None) => {
+                log::debug!("{:?} was not modified.", request);
+                Downloaded::Nothing
+            }
+            Err(Error::Http(e)) if e.is_timeout() => {
+                log::warn!("Timed out while downloading {:?}.", request);
+                Downloaded::Retry
+            }
+            Err(Error::TimedOut) => {
+                log::warn!("No response for {:?} in time.", request);
+                Downloaded::Retry
+            }
+            Err(e @ Error::NotAnImage(_)) => {
+                log::warn!("Invalid response for {:?}: {}", request, e);
+                self.shared
+                    .invalid_responses
+                    .fetch_add(1, Ordering::Relaxed);
+                Downloaded::Nothing
+            }
+            Err(e) => {
+                log::warn!("Could not download {:?}: {}", request, e);
+                Downloaded::Nothing
+            }
+        };
+
+        let repaint = !matches!(downloaded, Downloaded::Nothing);
+        self.tile_tx
+            .send((request, downloaded))
+            .await
+            .map_err(|_| ())?;
+        if repaint {
+            self.egui_ctx.borrow().request_repaint();
+        }
+        Ok(())
+    }
+}
+
+/// Progress of the [`Tiles::download_region`]. Clones share it.
+#[derive(Clone)]
+pub struct RegionDownload {
+    total: usize,
+    downloaded: Arc<AtomicUsize>,
+    failed: Arc<AtomicUsize>,
+}
+
+impl RegionDownload {
+    /// Number of tiles covering the region.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Number of tiles which got downloaded and stored.
+    pub fn downloaded(&self) -> usize {
+        self.downloaded.load(Ordering::Relaxed)
+    }
+
+    /// Number of tiles which could not be downloaded.
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.downloaded() + self.failed() >= self.total
+    }
+}
+
+async fn download_region<F>(
+    source: Arc<Source>,
+    tile_ids: Vec<TileId>,
+    time: Option<String>,
+    mut store: F,
+    progress: RegionDownload,
+    egui_ctx: tokio::sync::watch::Receiver<Context>,
+    client: Client,
+) where
+    F: FnMut(TileId, &[u8], Option<&str>) + Send + 'static,
+{
+    // One tile at the time, so that it does not compete with what is currently being shown.
+    for tile_id in tile_ids {
+        match fetch(&client, &source, tile_id, time.as_deref(), None).await {
+            Ok(Some(Fetched { image, etag, .. })) if image::guess_format(&image).is_ok() => {
+                store(tile_id, &image, etag.as_deref());
+                progress.downloaded.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(_) => {
+                log::warn!("{:?} is not an image.", tile_id);
+                progress.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                log::warn!("Could not download {:?}: {}", tile_id, e);
+                progress.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        egui_ctx.borrow().request_repaint();
+    }
 }
 
-impl Tile {
-    pub fn from_image_bytes(image: &[u8]) -> Result<Self, String> {
-        RetainedImage::from_image_bytes("debug_name", image).map(|image| Self {
-            image: Arc::new(image),
-        })
-    }
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    static TILE_ID: TileId = TileId {
+        x: 1,
+        y: 2,
+        zoom: 3,
+    };
+
+    type Source = Box<dyn Fn(TileId) -> String + Send + Sync>;
+
+    /// Creates `mockito::Server` and function mapping `TileId` to this
+    /// server's URL.
+    fn mockito_server() -> (mockito::ServerGuard, Source) {
+        let server = mockito::Server::new();
+        let url = server.url();
+
+        let source = move |tile_id: TileId| {
+            format!("{}/{}/{}/{}.png", url, tile_id.zoom, tile_id.x, tile_id.y)
+        };
+
+        (server, Box::new(source))
+    }
+
+    #[test]
+    fn fetch_settings_do_not_matter_for_native_downloads() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let options = TilesOptions {
+            fetch_mode: FetchMode::NoCors,
+            credentials: Credentials::Include,
+            ..Default::default()
+        };
+        let mut tiles =
+            Tiles::with_options(source, Context::default(), |_| Err(String::new()), options);
+
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn download_single_tile() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+
+        // First query start the download, but it will always return None.
+        assert!(tiles.at(TILE_ID).is_none());
+
+        // Eventually it gets downloaded and become available in cache.
+        while tiles.at(TILE_ID).is_none() {}
+
+        tile_mock.assert();
+
+        let stats = tiles.stats();
+        assert_eq!(1, stats.requested);
+        assert_eq!(1, stats.hits);
+        assert!(stats.misses >= 1);
+        assert_eq!(
+            include_bytes!("valid.png").len() as u64,
+            stats.downloaded_bytes
+        );
+    }
+
+    #[test]
+    fn subtracting_stats_does_not_go_below_zero() {
+        let earlier = TileStats {
+            hits: 1,
+            requested: 2,
+            ..Default::default()
+        };
+        let later = TileStats {
+            hits: 3,
+            misses: 1,
+            requested: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            later - earlier,
+            TileStats {
+                hits: 2,
+                misses: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(earlier - later, TileStats::default());
+    }
+
+    #[test]
+    fn cached_and_evicted_tiles_are_recorded() {
+        let mut tiles = Tiles::rendered(
+            |_| ColorImage::new([256, 256], Color32::GRAY),
+            Context::default(),
+        )
+        .with_events();
+        let other = TileId { x: 2, ..TILE_ID };
+        let bytes = 256 * 256 * 4;
+
+        assert!(tiles.at(TILE_ID).is_some());
+        assert!(tiles.at(TILE_ID).is_some());
+        assert!(tiles.at(other).is_some());
+        assert_eq!(
+            tiles.take_events(),
+            [
+                TileEvent::Cached {
+                    tile_id: TILE_ID,
+                    bytes
+                },
+                TileEvent::Cached {
+                    tile_id: other,
+                    bytes
+                }
+            ]
+        );
+        assert!(tiles.take_events().is_empty());
+
+        tiles.clean_up_zoom(TILE_ID.zoom);
+        let mut events = tiles.take_events();
+        events.sort_by_key(|event| match event {
+            TileEvent::Evicted { tile_id, .. } => tile_id.x,
+            TileEvent::Cached { .. } => u32::MAX,
+        });
+        assert_eq!(
+            events,
+            [
+                TileEvent::Evicted {
+                    tile_id: TILE_ID,
+                    bytes
+                },
+                TileEvent::Evicted {
+                    tile_id: other,
+                    bytes
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn inserted_tiles_are_recorded() {
+        let mut tiles = Tiles::rendered(
+            |_| ColorImage::new([256, 256], Color32::GRAY),
+            Context::default(),
+        )
+        .with_events();
+        let tile = || {
+            Tile::from_retained_image(RetainedImage::from_color_image(
+                "tile",
+                ColorImage::new([512, 512], Color32::RED),
+            ))
+        };
+        let bytes = 512 * 512 * 4;
+
+        tiles.insert(TILE_ID, tile());
+        tiles.insert(TILE_ID, tile());
+        assert_eq!(
+            tiles.take_events(),
+            [
+                TileEvent::Cached {
+                    tile_id: TILE_ID,
+                    bytes
+                },
+                TileEvent::Evicted {
+                    tile_id: TILE_ID,
+                    bytes
+                },
+                TileEvent::Cached {
+                    tile_id: TILE_ID,
+                    bytes
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn rendered_tiles_are_available_immediately_and_cached() {
+        let renders = Arc::new(AtomicUsize::new(0));
+        let mut tiles = Tiles::rendered(
+            {
+                let renders = renders.clone();
+                move |tile_id| {
+                    renders.fetch_add(1, Ordering::SeqCst);
+                    ColorImage::new([256, 256], Color32::from_gray(tile_id.zoom))
+                }
+            },
+            Context::default(),
+        );
+
+        assert!(tiles.at(TILE_ID).is_some());
+        assert!(tiles.at(TILE_ID).is_some());
+        assert_eq!(1, renders.load(Ordering::SeqCst));
+
+        // Textures need to be uploaded to the new context, so the tile is rendered again.
+        tiles.rebind(Context::default());
+        assert!(tiles.at(TILE_ID).is_some());
+        assert!(tiles.at(TILE_ID).is_some());
+        assert_eq!(2, renders.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn tiles_configured_by_tilejson() {
+        let _ = env_logger::try_init();
+
+        let mut server = mockito::Server::new();
+        let url = server.url();
+        let tilejson_mock = server
+            .mock("GET", "/tiles.json")
+            .with_body(format!(
+                r#"{{"tiles": ["{url}/{{z}}/{{x}}/{{y}}.png"], "attribution": "Test", "minzoom": 3, "maxzoom": 3}}"#
+            ))
+            .create();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut tiles = runtime
+            .block_on(Tiles::from_tilejson(
+                &format!("{url}/tiles.json"),
+                Context::default(),
+                |_| Err(String::new()),
+            ))
+            .unwrap();
+
+        tilejson_mock.assert();
+        assert_eq!("Test", tiles.attribution().unwrap().text);
+
+        while tiles.at(TILE_ID).is_none() {}
+        tile_mock.assert();
+
+        // Provider does not have this zoom level, so it's not even requested.
+        let other_zoom = TileId { zoom: 4, ..TILE_ID };
+        assert!(tiles.at(other_zoom).is_none());
+        assert!(tiles.cache().get(&other_zoom).is_none());
+    }
+
+    #[test]
+    fn downloaded_tile_is_downsampled() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let options = TilesOptions {
+            max_texture_size: Some(64),
+            ..Default::default()
+        };
+        let mut tiles =
+            Tiles::with_options(source, Context::default(), |_| Err(String::new()), options);
+
+        let tile = loop {
+            if let Some(tile) = tiles.at(TILE_ID) {
+                break tile;
+            }
+        };
+
+        // Texture is smaller, but it takes the same space on the screen.
+        assert_eq!([64, 64], tile.image.as_ref().unwrap().size());
+        assert_eq!(Vec2::new(256., 256.), tile.rect(Vec2::ZERO).size());
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn downloaded_tile_is_preprocessed() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body("encrypted")
+            .create();
+
+        let options = TilesOptions::default().with_preprocess(|tile_id, bytes| {
+            assert_eq!(TILE_ID, tile_id);
+            assert_eq!(&b"encrypted"[..], &bytes[..]);
+            Ok(Bytes::from_static(include_bytes!("valid.png")))
+        });
+        let mut tiles =
+            Tiles::with_options(source, Context::default(), |_| Err(String::new()), options);
+
+        while tiles.at(TILE_ID).is_none() {}
+
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn downloaded_tile_keeps_etag() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_header("ETag", "\"some-etag\"")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+
+        let tile = loop {
+            if let Some(tile) = tiles.at(TILE_ID) {
+                break tile;
+            }
+        };
+
+        assert_eq!(Some("\"some-etag\""), tile.etag());
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn hard_cached_tile_is_revalidated_using_etag() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .match_header("If-None-Match", "\"some-etag\"")
+            .with_status(304)
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default(), |_| {
+            Ok(Tile::from_image_bytes(include_bytes!("valid.png"))?.with_etag("\"some-etag\""))
+        });
+
+        // Cached tile is available right away, regardless of the revalidation.
+        assert!(tiles.at(TILE_ID).is_some());
+
+        while !tile_mock.matched() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // Server said it's not modified, so we still have the same one.
+        let tile = tiles.at(TILE_ID).expect("tile should still be cached");
+        assert_eq!(Some("\"some-etag\""), tile.etag());
+    }
+
+    #[test]
+    fn downloading_region() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex(r"^/[01]/\d/\d.png$".to_owned()),
+            )
+            .with_body(include_bytes!("valid.png"))
+            .expect(5)
+            .create();
+
+        let tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+        let stored = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let world = BoundingBox {
+            west: -180.,
+            south: -90.,
+            east: 180.,
+            north: 90.,
+        };
+        let region = tiles.download_region(world, 0..=1, {
+            let stored = stored.clone();
+            move |tile_id, _, _| stored.lock().unwrap().push(tile_id)
+        });
+
+        assert_eq!(5, region.total());
+
+        while !region.is_finished() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(5, region.downloaded());
+        assert_eq!(0, region.failed());
+        assert_eq!(5, stored.lock().unwrap().len());
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn reading_tiles_from_filesystem() {
+        let _ = env_logger::try_init();
+
+        let root = std::env::temp_dir().join(format!("walkers-tiles-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("3/1")).unwrap();
+        std::fs::write(root.join("3/1/2.png"), include_bytes!("valid.png")).unwrap();
+
+        let source =
+            crate::providers::filesystem(format!("{}/{{z}}/{{x}}/{{y}}.png", root.display()));
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+
+        while tiles.at(TILE_ID).is_none() {}
+
+        // Other tiles are not there.
+        let missing = TileId { x: 0, ..TILE_ID };
+        assert!(tiles.at(missing).is_none());
+        std::thread::sleep(Duration::from_secs(1));
+        assert!(tiles.at(missing).is_none());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn tile_is_requested_again_after_timeout() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_chunked_body(|_| {
+                std::thread::sleep(Duration::from_millis(500));
+                Ok(())
+            })
+            .expect_at_least(2)
+            .create();
+
+        let options = TilesOptions {
+            timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut tiles =
+            Tiles::with_options(source, Context::default(), |_| Err(String::new()), options);
+
+        while !tile_mock.matched() {
+            assert!(tiles.at(TILE_ID).is_none());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn tiles_are_not_downloaded_while_network_is_disabled() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+        tiles.set_network_enabled(false);
+        assert_tile_is_empty_forever(&mut tiles);
+        assert!(!tile_mock.matched());
+        assert_eq!(0, tiles.stats().requested);
 
-    pub fn from_retained_image(image: RetainedImage)->Self{
-        Self { image: Arc::new(image) }
+        tiles.set_network_enabled(true);
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        tile_mock.assert();
     }
 
-    pub fn rect(&self, screen_position: Vec2) -> Rect {
-        let tile_size = pos2(self.image.width() as f32, self.image.height() as f32);
-        Rect::from_two_pos(
-            screen_position.to_pos2(),
-            (screen_position + tile_size.to_vec2()).to_pos2(),
-        )
-    }
+    #[test]
+    fn tile_requested_again_while_being_downloaded_is_downloaded_once() {
+        let _ = env_logger::try_init();
 
-    pub fn mesh(&self, screen_position: Vec2, ctx: &Context) -> Mesh {
-        let mut mesh = Mesh::with_texture(self.image.texture_id(ctx));
-        mesh.add_rect_with_uv(
-            self.rect(screen_position),
-            Rect::from_min_max(pos2(0., 0.0), pos2(1.0, 1.0)),
-            Color32::WHITE,
-        );
-        mesh
-    }
-}
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_millis(500));
+                w.write_all(include_bytes!("valid.png"))
+            })
+            .create();
 
-/// Downloads and keeps cache of the tiles. It must persist between frames.
-pub struct Tiles {
-    cache: HashMap<TileId, Option<Tile>>,
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+        assert!(tiles.at(TILE_ID).is_none());
 
-    /// Tiles to be downloaded by the IO thread.
-    request_tx: tokio::sync::mpsc::Sender<TileId>,
+        // E.g. zooming out and back in quickly.
+        tiles.clean_up_zoom(TILE_ID.zoom);
+        assert!(tiles.at(TILE_ID).is_none());
 
-    /// Tiles that got downloaded and should be put in the cache.
-    tile_rx: tokio::sync::mpsc::Receiver<(TileId, Tile)>,
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        tile_mock.assert();
+        assert_eq!(1, tiles.stats().requested);
+    }
 
-    #[allow(dead_code)] // Significant Drop
-    tokio_runtime_thread: TokioRuntimeThread,
+    #[test]
+    fn switching_between_times() {
+        let _ = env_logger::try_init();
 
-    hard_cache: Box<dyn Fn(&TileId)->Result<Tile, String>>
-}
+        let mut server = mockito::Server::new();
+        let url = server.url();
+        let first_mock = server
+            .mock("GET", "/first/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+        let second_mock = server
+            .mock("GET", "/second/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
 
-impl Tiles {
-    pub fn new<S, C>(source: S, egui_ctx: Context, cache: C) -> Self
-    where
-        S: Fn(TileId) -> String + Send + 'static,
-        C: Fn(&TileId) -> Result<Tile, String> + 'static
-    {
-        let tokio_runtime_thread = TokioRuntimeThread::new();
+        let mut tiles = Tiles::temporal(
+            move |tile_id, time| {
+                format!(
+                    "{url}/{time}/{}/{}/{}.png",
+                    tile_id.zoom, tile_id.x, tile_id.y
+                )
+            },
+            "first",
+            Context::default(),
+        );
+        assert_eq!(Some("first"), tiles.time());
 
-        // Minimum value which didn't cause any stalls while testing.
-        let channel_size = 20;
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
 
-        let (request_tx, request_rx) = tokio::sync::mpsc::channel(channel_size);
-        let (tile_tx, tile_rx) = tokio::sync::mpsc::channel(channel_size);
-        tokio_runtime_thread
-            .runtime
-            .spawn(download(source, request_rx, tile_tx, egui_ctx));
-        Self {
-            cache: Default::default(),
-            request_tx,
-            tile_rx,
-            tokio_runtime_thread,
-            hard_cache: Box::new(cache)
+        tiles.set_time("second");
+        assert_eq!(Some("second"), tiles.time());
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
         }
+
+        // Both are cached by now.
+        tiles.set_time("first");
+        assert!(tiles.at(TILE_ID).is_some());
+        tiles.set_time("second");
+        assert!(tiles.at(TILE_ID).is_some());
+
+        first_mock.assert();
+        second_mock.assert();
+        assert_eq!(2, tiles.stats().requested);
+
+        tiles.forget_time("first");
+        tiles.set_time("first");
+        assert!(tiles.at(TILE_ID).is_none());
     }
 
-    /// Return a tile if already in cache, schedule a download otherwise.
-    pub fn at(&mut self, tile_id: TileId) -> Option<Tile> {
-        // Just take one at the time.
-        match self.tile_rx.try_recv() {
-            Ok((tile_id, tile)) => {
-                self.cache.insert(tile_id, Some(tile));
-            }
-            Err(TryRecvError::Empty) => {
-                // Just ignore. It means that no new tile was downloaded.
-            }
-            Err(TryRecvError::Disconnected) => panic!("IO thread is dead"),
-        }
+    #[test]
+    fn warming_tiles_before_they_are_shown() {
+        let _ = env_logger::try_init();
 
-        match self.cache.entry(tile_id) {
-            Entry::Occupied(entry) => entry.get().clone(),
-            Entry::Vacant(entry) => {
-                if let Ok(tile) = (self.hard_cache)(&tile_id){
-                    entry.insert(Some(tile.clone()));
-                    return Some(tile);
-                }
-                if let Ok(()) = self.request_tx.try_send(tile_id) {
-                    log::debug!("Requested tile: {:?}", tile_id);
-                    entry.insert(None);
-                } else {
-                    log::debug!("Request queue is full.");
-                }
-                None
-            }
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+        let center = screen_to_position(&pos2(1.5 * 256., 2.5 * 256.), 3);
+        tiles.warm(center, 3, Vec2::splat(10.));
+        assert_eq!(1, tiles.stats().requested);
+
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
         }
+        tile_mock.assert();
+        assert_eq!(1, tiles.stats().requested);
     }
 
-    pub fn insert(&mut self, tile_id: TileId, tile: Tile){
-        self.cache.insert(tile_id, Some(tile));
-    }
+    #[test]
+    fn no_content_is_an_empty_tile() {
+        let _ = env_logger::try_init();
 
-    pub fn cache(&self)->&HashMap<TileId, Option<Tile>>{
-        &self.cache
-    }
+        let (mut server, source) = mockito_server();
+        let tile_mock = server.mock("GET", "/3/1/2.png").with_status(204).create();
 
-    pub fn clean_up_zoom(&mut self, zoom: u8){
-        log::debug!("Clean up zoom: {zoom}");
-        self.cache.retain(|k, v|{
-            k.zoom != zoom
-        });
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+        let tile = loop {
+            if let Some(tile) = tiles.at(TILE_ID) {
+                break tile;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        assert!(tile.is_empty());
+        assert!(tile.mesh(Vec2::ZERO, &Context::default()).is_empty());
+        assert!(tiles.at(TILE_ID).is_some());
+        tile_mock.assert();
+        assert_eq!(1, tiles.stats().requested);
     }
-}
 
-#[derive(Debug, thiserror::Error)]
-enum Error {
-    #[error(transparent)]
-    Http(reqwest::Error),
+    #[test]
+    fn falling_back_to_another_provider() {
+        let _ = env_logger::try_init();
 
-    #[error("error while decoding the image: {0}")]
-    Image(String),
-}
+        let mut server = mockito::Server::new();
+        let url = server.url();
+        let primary_mock = server
+            .mock("GET", "/primary/3/1/2.png")
+            .with_status(503)
+            .create();
+        let backup_mock = server
+            .mock("GET", "/backup/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
 
-async fn download_single(client: &reqwest::Client, url: &str) -> Result<Tile, Error> {
-    let image = client
-        .get(url)
-        .header(USER_AGENT, "Walkers")
-        .send()
-        .await
-        .map_err(Error::Http)?;
+        let provider = |name: &'static str| -> Box<TileUrl> {
+            let url = url.clone();
+            Box::new(move |tile_id: TileId| {
+                format!(
+                    "{url}/{name}/{}/{}/{}.png",
+                    tile_id.zoom, tile_id.x, tile_id.y
+                )
+            })
+        };
 
-    log::debug!("Downloaded {:?}.", image.status());
+        let mut tiles = Tiles::with_fallbacks(
+            vec![
+                (provider("primary"), Attribution::new("Primary")),
+                (provider("backup"), Attribution::new("Backup")),
+            ],
+            Context::default(),
+            |_| Err(String::new()),
+        );
+        assert_eq!("Primary", tiles.attribution().unwrap().text);
 
-    let image = image
-        .error_for_status()
-        .map_err(Error::Http)?
-        .bytes()
-        .await
-        .map_err(Error::Http)?;
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        primary_mock.assert();
+        backup_mock.assert();
 
-    Tile::from_image_bytes(&image).map_err(Error::Image)
-}
+        let attributions = tiles.attributions(&[TILE_ID]);
+        assert_eq!(1, attributions.len());
+        assert_eq!("Backup", attributions[0].text);
+    }
 
-async fn download<S>(
-    source: S,
-    mut request_rx: tokio::sync::mpsc::Receiver<TileId>,
-    tile_tx: tokio::sync::mpsc::Sender<(TileId, Tile)>,
-    egui_ctx: Context,
-) -> Result<(), ()>
-where
-    S: Fn(TileId) -> String + Send + 'static,
-{
-    // Keep outside the loop to reuse it as much as possible.
-    let client = reqwest::Client::new();
+    #[test]
+    fn falling_back_to_another_format() {
+        let _ = env_logger::try_init();
 
-    loop {
-        let request = request_rx.recv().await.ok_or(())?;
-        let url = source(request);
+        let mut server = mockito::Server::new();
+        let url = server.url();
+        let webp_mock = server
+            .mock("GET", mockito::Matcher::Regex(r"\.webp$".into()))
+            .with_status(406)
+            .create();
+        let png_mock = server
+            .mock("GET", mockito::Matcher::Regex(r"\.png$".into()))
+            .with_body(include_bytes!("valid.png"))
+            .expect(2)
+            .create();
 
-        log::debug!("Getting {:?} from {}.", request, url);
+        let format = |extension: &'static str| -> Box<TileUrl> {
+            let url = url.clone();
+            Box::new(move |tile_id: TileId| {
+                format!(
+                    "{url}/{}/{}/{}.{extension}",
+                    tile_id.zoom, tile_id.x, tile_id.y
+                )
+            })
+        };
+        let mut tiles = Tiles::with_formats(
+            vec![format("webp"), format("png")],
+            Context::default(),
+            |_| Err(String::new()),
+        );
 
-        match download_single(&client, &url).await {
-            Ok(tile) => {
-                tile_tx.send((request, tile)).await.map_err(|_| ())?;
-                egui_ctx.request_repaint();
-            }
-            Err(e) => {
-                log::warn!("Could not download '{}': {}", &url, e);
-            }
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
-
-    use super::*;
+        // WebP is not tried anymore.
+        let other = TileId { x: 2, ..TILE_ID };
+        while tiles.at(other).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
 
-    static TILE_ID: TileId = TileId {
-        x: 1,
-        y: 2,
-        zoom: 3,
-    };
+        webp_mock.assert();
+        png_mock.assert();
+    }
 
-    type Source = Box<dyn Fn(TileId) -> String + Send>;
+    #[test]
+    fn missing_tile_falls_back_to_another_format_just_for_itself() {
+        let _ = env_logger::try_init();
 
-    /// Creates `mockito::Server` and function mapping `TileId` to this
-    /// server's URL.
-    fn mockito_server() -> (mockito::ServerGuard, Source) {
-        let server = mockito::Server::new();
+        let mut server = mockito::Server::new();
         let url = server.url();
+        let missing_webp_mock = server.mock("GET", "/3/1/2.webp").with_status(404).create();
+        let webp_mock = server
+            .mock("GET", "/3/2/2.webp")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+        let png_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
 
-        let source = move |tile_id: TileId| {
-            format!("{}/{}/{}/{}.png", url, tile_id.zoom, tile_id.x, tile_id.y)
+        let format = |extension: &'static str| -> Box<TileUrl> {
+            let url = url.clone();
+            Box::new(move |tile_id: TileId| {
+                format!(
+                    "{url}/{}/{}/{}.{extension}",
+                    tile_id.zoom, tile_id.x, tile_id.y
+                )
+            })
         };
+        let mut tiles = Tiles::with_formats(
+            vec![format("webp"), format("png")],
+            Context::default(),
+            |_| Err(String::new()),
+        );
 
-        (server, Box::new(source))
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // WebP is still preferred for the other tiles.
+        let other = TileId { x: 2, ..TILE_ID };
+        while tiles.at(other).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        missing_webp_mock.assert();
+        webp_mock.assert();
+        png_mock.assert();
     }
 
     #[test]
-    fn download_single_tile() {
+    fn download_tile_using_custom_request() {
         let _ = env_logger::try_init();
 
-        let (mut server, source) = mockito_server();
+        let mut server = mockito::Server::new();
+        let url = server.url();
         let tile_mock = server
-            .mock("GET", "/3/1/2.png")
+            .mock("POST", "/tiles")
+            .match_header("Authorization", "secret")
+            .match_body("3/1/2")
             .with_body(include_bytes!("valid.png"))
             .create();
 
-        let mut tiles = Tiles::new(source, Context::default(), |_|{
-            Err(String::new())
-        });
+        let requests = move |tile_id: TileId| {
+            let mut request = Request::new(
+                reqwest::Method::POST,
+                format!("{url}/tiles").parse().unwrap(),
+            );
+            request
+                .headers_mut()
+                .insert("Authorization", HeaderValue::from_static("secret"));
+            *request.body_mut() =
+                Some(format!("{}/{}/{}", tile_id.zoom, tile_id.x, tile_id.y).into());
+            request
+        };
 
-        // First query start the download, but it will always return None.
-        assert!(tiles.at(TILE_ID).is_none());
+        let mut tiles = Tiles::with_requests(
+            requests,
+            Context::default(),
+            |_| Err(String::new()),
+            TilesOptions::default(),
+        );
 
-        // Eventually it gets downloaded and become available in cache.
         while tiles.at(TILE_ID).is_none() {}
 
         tile_mock.assert();
@@ -253,9 +2429,7 @@ mod tests {
         let _ = env_logger::try_init();
 
         let (mut server, source) = mockito_server();
-        let mut tiles = Tiles::new(source, Context::default(),  |_|{
-            Err(String::new())
-        });
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
         let tile_mock = server.mock("GET", "/3/1/2.png").with_status(404).create();
 
         assert_tile_is_empty_forever(&mut tiles);
@@ -263,16 +2437,145 @@ mod tests {
     }
 
     #[test]
-    fn tile_is_empty_forever_if_http_returns_no_body() {
+    fn tiles_fetched_by_the_application() {
         let _ = env_logger::try_init();
 
-        let (mut server, source) = mockito_server();
-        let mut tiles = Tiles::new(source, Context::default(),  |_|{
-            Err(String::new())
+        let (requests_tx, requests_rx) = std::sync::mpsc::channel();
+        let mut tiles = Tiles::external(
+            requests_tx,
+            Context::default(),
+            |_| Err(String::new()),
+            TilesOptions::default(),
+        );
+        let missing = TileId { x: 2, ..TILE_ID };
+
+        let application = std::thread::spawn(move || {
+            for request in requests_rx.iter().take(2) {
+                if request.tile_id == TILE_ID {
+                    request.respond(&include_bytes!("valid.png")[..]);
+                }
+            }
         });
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        assert_eq!(Ok(()), runtime.block_on(tiles.tile_ready(TILE_ID)));
+        assert_eq!(
+            Err(TileError::Failed),
+            runtime.block_on(tiles.tile_ready(missing))
+        );
+        application.join().unwrap();
+    }
+
+    #[test]
+    fn external_tiles_are_fetched_in_parallel() {
+        let _ = env_logger::try_init();
+
+        let (requests_tx, requests_rx) = std::sync::mpsc::channel();
+        let mut tiles = Tiles::external(
+            requests_tx,
+            Context::default(),
+            |_| Err(String::new()),
+            TilesOptions::default(),
+        );
+        let other = TileId { x: 2, ..TILE_ID };
+
+        assert!(tiles.at(TILE_ID).is_none());
+        assert!(tiles.at(other).is_none());
+
+        // Both are waiting for the response at the same time.
+        let first = requests_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        let second = requests_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        second.respond(&include_bytes!("valid.png")[..]);
+        first.respond(&include_bytes!("valid.png")[..]);
+
+        while tiles.at(TILE_ID).is_none() || tiles.at(other).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn external_tiles_are_requested_again_if_not_answered_in_time() {
+        let _ = env_logger::try_init();
+
+        let (requests_tx, requests_rx) = std::sync::mpsc::channel();
+        let mut tiles = Tiles::external(
+            requests_tx,
+            Context::default(),
+            |_| Err(String::new()),
+            TilesOptions {
+                timeout: Duration::from_millis(50),
+                ..Default::default()
+            },
+        );
+
+        assert!(tiles.at(TILE_ID).is_none());
+        let _unanswered = requests_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        let again = loop {
+            assert!(tiles.at(TILE_ID).is_none());
+            if let Ok(request) = requests_rx.recv_timeout(Duration::from_millis(10)) {
+                break request;
+            }
+        };
+        assert_eq!(TILE_ID, again.tile_id);
+    }
+
+    #[test]
+    fn waiting_for_tiles() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+        let missing_mock = server.mock("GET", "/3/2/2.png").with_status(404).create();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        assert_eq!(Ok(()), runtime.block_on(tiles.tile_ready(TILE_ID)));
+        assert!(tiles.at(TILE_ID).is_some());
+        tile_mock.assert();
+
+        let missing = TileId { x: 2, ..TILE_ID };
+        assert_eq!(
+            Err(TileError::Failed),
+            runtime.block_on(tiles.tile_ready(missing))
+        );
+        // Not requested again.
+        assert_eq!(
+            Err(TileError::Failed),
+            runtime.block_on(tiles.tile_ready(missing))
+        );
+        missing_mock.expect(1).assert();
+
+        let too_deep = TileId {
+            zoom: 30,
+            ..TILE_ID
+        };
+        assert_eq!(
+            Err(TileError::Unavailable),
+            runtime.block_on(tiles.tile_ready(too_deep))
+        );
+    }
+
+    #[test]
+    fn tile_is_transparent_if_http_returns_no_body() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
         let tile_mock = server.mock("GET", "/3/1/2.png").create();
 
-        assert_tile_is_empty_forever(&mut tiles);
+        while tiles.at(TILE_ID).is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(tiles.at(TILE_ID).unwrap().is_empty());
         tile_mock.assert();
     }
 
@@ -281,9 +2584,7 @@ mod tests {
         let _ = env_logger::try_init();
 
         let (mut server, source) = mockito_server();
-        let mut tiles = Tiles::new(source, Context::default(),  |_|{
-            Err(String::new())
-        });
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
         let tile_mock = server
             .mock("GET", "/3/1/2.png")
             .with_body("definitely not an image")
@@ -293,14 +2594,29 @@ mod tests {
         tile_mock.assert();
     }
 
+    #[test]
+    fn html_error_page_is_reported_as_invalid_response() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_header("Content-Type", "text/html")
+            .with_body("<html><body>Invalid API key</body></html>")
+            .create();
+
+        assert_tile_is_empty_forever(&mut tiles);
+        tile_mock.assert();
+        assert_eq!(tiles.stats().invalid_responses, 1);
+    }
+
     #[test]
     fn tile_is_empty_forever_if_http_can_not_even_connect() {
         let _ = env_logger::try_init();
 
         let source = |_| "totally invalid url".to_string();
-        let mut tiles = Tiles::new(source, Context::default(),  |_|{
-            Err(String::new())
-        });
+        let mut tiles = Tiles::new(source, Context::default(), |_| Err(String::new()));
 
         assert_tile_is_empty_forever(&mut tiles);
     }
@@ -10,7 +10,8 @@ pub struct TokioRuntimeThread {
 impl TokioRuntimeThread {
     pub fn new() -> Self {
         let (quit_tx, mut quit_rx) = tokio::sync::mpsc::unbounded_channel();
-        let (rt_tx, mut rt_rx) = tokio::sync::mpsc::unbounded_channel();
+        // Not a Tokio channel, so that it can be also created from within an async context.
+        let (rt_tx, rt_rx) = std::sync::mpsc::channel();
 
         let join_handle = std::thread::spawn(move || {
             let runtime = tokio::runtime::Builder::new_current_thread()
@@ -29,7 +30,7 @@ impl TokioRuntimeThread {
             join_handle: Some(join_handle),
             quit_tx,
             runtime: rt_rx
-                .blocking_recv()
+                .recv()
                 .expect("Tokio thread died before returning the Tokio runtime"),
         }
     }
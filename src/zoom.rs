@@ -1,9 +1,24 @@
+use std::ops::RangeInclusive;
+
+/// Highest supported zoom level.
+pub(crate) const MAX_ZOOM: u8 = 19;
+
 #[derive(thiserror::Error, Debug, PartialEq, Eq)]
 #[error("invalid zoom level")]
 pub struct InvalidZoom;
 
+/// How far past the middle between two integer zoom levels [`Zoom::value`] needs to go before
+/// [`Zoom::round`] switches to the other one. Keeps the tiles from flipping back and forth when
+/// zoom jitters right at the boundary.
+const HYSTERESIS: f32 = 0.1;
+
 #[derive(Debug, Clone, Copy)]
-pub struct Zoom(f32);
+pub struct Zoom {
+    value: f32,
+
+    /// Integer level the tiles are drawn at, see [`Zoom::round`].
+    level: u8,
+}
 
 impl TryFrom<f32> for Zoom {
     type Error = InvalidZoom;
@@ -11,39 +26,133 @@ impl TryFrom<f32> for Zoom {
     fn try_from(value: f32) -> Result<Self, Self::Error> {
         // Mapnik supports zooms up to 19.
         // https://wiki.openstreetmap.org/wiki/Slippy_map_tilenames#Zoom_levels
-        if !(0. ..=17.).contains(&value) {
+        if !Self::range().contains(&value) {
             Err(InvalidZoom)
         } else {
-            Ok(Self(value))
+            Ok(Self {
+                value,
+                level: value.round() as u8,
+            })
         }
     }
 }
 
 impl Default for Zoom {
     fn default() -> Self {
-        Self(16.)
+        Self {
+            value: 16.,
+            level: 16,
+        }
     }
 }
 
 impl Zoom {
+    /// How much [`Zoom::zoom_in`] and [`Zoom::zoom_out`] change the zoom, that is, one level.
+    pub const STEP: f32 = 1.;
+
+    /// All supported zoom levels. Integer ones are where the tiles are sharpest, so a slider can
+    /// snap to them.
+    ///
+    /// ```
+    /// # use walkers::Zoom;
+    /// # #[cfg(feature = "gui")]
+    /// fn zoom_slider(ui: &mut egui::Ui, zoom: &mut Zoom) {
+    ///     let mut level = zoom.value();
+    ///     if ui.add(egui::Slider::new(&mut level, Zoom::range()).step_by(1.)).changed() {
+    ///         let _ = zoom.set(level);
+    ///     }
+    /// }
+    /// ```
+    pub fn range() -> RangeInclusive<f32> {
+        0. ..=MAX_ZOOM as f32
+    }
+
+    /// Set the exact zoom level.
+    pub fn set(&mut self, value: f32) -> Result<(), InvalidZoom> {
+        *self = self.moved_to(value)?;
+        Ok(())
+    }
+
+    /// Zoom changed to `value`, but still at the same [`Zoom::round`] level, unless it went
+    /// clearly into another one.
+    fn moved_to(&self, value: f32) -> Result<Self, InvalidZoom> {
+        let new_self = Self::try_from(value)?;
+        if (value - self.level as f32).abs() < 0.5 + HYSTERESIS {
+            Ok(Self {
+                level: self.level,
+                ..new_self
+            })
+        } else {
+            Ok(new_self)
+        }
+    }
+
+    /// Zoom level scaled to be between `0.` (the lowest) and `1.` (the highest).
+    pub fn normalized(&self) -> f32 {
+        self.value / MAX_ZOOM as f32
+    }
+
+    /// Integer level the tiles are drawn at. It is usually the nearest one, but it sticks for a
+    /// bit after the [`Zoom::value`] crosses the middle between two levels.
     pub fn round(&self) -> u8 {
-        self.0.round() as u8
+        self.level
     }
 
+    /// Exact zoom level, which may be between the integer ones.
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Zoom in by [`Zoom::STEP`].
     pub fn zoom_in(&mut self) -> Result<(), InvalidZoom> {
-        *self = Self::try_from(self.0 + 1.)?;
+        *self = self.moved_to(self.value + Self::STEP)?;
         Ok(())
     }
 
+    /// Zoom out by [`Zoom::STEP`].
     pub fn zoom_out(&mut self) -> Result<(), InvalidZoom> {
-        *self = Self::try_from(self.0 - 1.)?;
+        *self = self.moved_to(self.value - Self::STEP)?;
         Ok(())
     }
 
-    /// Zoom using a relative value.
-    pub fn zoom_by(&mut self, value: f32)->bool {
-        if let Ok(new_self) = Self::try_from(self.0 + value) {
-            let re =  self.round() != new_self.round();
+    /// Move to the nearest of the `levels`, unless the zoom is already at one of them, e.g. when
+    /// the provider has tiles only for some levels, see [`crate::TilesOptions::zoom_levels`].
+    /// Zoom moves on in the direction it went since `previous`, so that zooming in by a bit
+    /// reaches the next level, instead of going back. Levels which are not supported are ignored.
+    pub fn snap_to_levels(&mut self, levels: &[u8], previous: Zoom) {
+        let level = self.level;
+        let levels = || levels.iter().copied().filter(|level| *level <= MAX_ZOOM);
+        if levels().any(|allowed| allowed == level) {
+            return;
+        }
+
+        let above = levels().filter(|allowed| *allowed > level).min();
+        let below = levels().filter(|allowed| *allowed < level).max();
+        let snapped = if self.value > previous.value {
+            above.or(below)
+        } else if self.value < previous.value {
+            below.or(above)
+        } else {
+            match (below, above) {
+                (Some(below), Some(above)) if level - below <= above - level => Some(below),
+                (below, above) => above.or(below),
+            }
+        };
+
+        if let Some(snapped) = snapped {
+            *self = Self {
+                value: snapped as f32,
+                level: snapped,
+            };
+        }
+    }
+
+    /// Zoom using a relative value, which can be a fraction of a level, e.g. when building a
+    /// slider. Positive values zoom in. Zoom stays the same if it would go out of the supported
+    /// range. Returns whether [`Zoom::round`] changed.
+    pub fn zoom_by(&mut self, value: f32) -> bool {
+        if let Ok(new_self) = self.moved_to(self.value + value) {
+            let re = self.round() != new_self.round();
             *self = new_self;
             re
         } else {
@@ -63,6 +172,31 @@ mod tests {
         assert_eq!(InvalidZoom, Zoom::try_from(20.).unwrap_err());
     }
 
+    #[test]
+    fn test_snapping_to_levels() {
+        let levels = [5, 8, 12];
+        let snapped = |from: f32, to: f32| {
+            let previous = Zoom::try_from(from).unwrap();
+            let mut zoom = Zoom::try_from(to).unwrap();
+            zoom.snap_to_levels(&levels, previous);
+            zoom.round()
+        };
+
+        // Zooming in or out by one level goes all the way to the next one.
+        assert_eq!(12, snapped(8., 9.));
+        assert_eq!(5, snapped(8., 7.));
+
+        // Nowhere else to go.
+        assert_eq!(12, snapped(12., 13.));
+
+        // Without the movement, it's the nearest one.
+        assert_eq!(8, snapped(10., 10.));
+        assert_eq!(12, snapped(11., 11.));
+
+        // Already there.
+        assert_eq!(8, snapped(7., 8.));
+    }
+
     #[test]
     fn test_zooming_in() {
         let mut zoom = Zoom::try_from(18.).unwrap();
@@ -71,6 +205,43 @@ mod tests {
         assert_eq!(Err(InvalidZoom), zoom.zoom_in());
     }
 
+    #[test]
+    fn test_zooming_by_fraction() {
+        let mut zoom = Zoom::try_from(10.).unwrap();
+        assert!(!zoom.zoom_by(0.25));
+        assert!(!zoom.zoom_by(0.25));
+        assert!(zoom.zoom_by(0.25));
+        assert_eq!(11, zoom.round());
+        assert!(!zoom.zoom_by(20.));
+        assert_eq!(11, zoom.round());
+    }
+
+    #[test]
+    fn test_level_does_not_flip_when_zoom_jitters_at_boundary() {
+        let mut zoom = Zoom::try_from(10.45).unwrap();
+        for delta in [0.1, -0.1, 0.1, -0.1, 0.1] {
+            assert!(!zoom.zoom_by(delta));
+            assert_eq!(10, zoom.round());
+        }
+
+        // Clearly in the next level.
+        assert!(zoom.zoom_by(0.1));
+        assert_eq!(11, zoom.round());
+        assert!(!zoom.zoom_by(-0.1));
+        assert_eq!(11, zoom.round());
+    }
+
+    #[test]
+    fn test_zooming_within_range() {
+        let mut zoom = Zoom::default();
+        assert!(zoom.set(*Zoom::range().end()).is_ok());
+        assert_eq!(1., zoom.normalized());
+        assert!(zoom.set(9.5).is_ok());
+        assert_eq!(0.5, zoom.normalized());
+        assert_eq!(Err(InvalidZoom), zoom.set(*Zoom::range().end() + 0.1));
+        assert_eq!(9.5, zoom.value());
+    }
+
     #[test]
     fn test_zooming_out() {
         let mut zoom = Zoom::try_from(1.).unwrap();